@@ -1,17 +1,282 @@
-use std::env;
+use std::num::NonZeroUsize;
 
-use color_eyre::eyre::{OptionExt, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-pub struct CliArgs {
-    pub input_file_path: String,
+use crate::domain::{
+    account::{
+        aggregate::DisputePolicy,
+        precision::{
+            CurrencyPrecision, DEFAULT_MINOR_UNITS, PrecisionPolicy, PrecisionRule, RoundingMode,
+        },
+    },
+    props::AuthorizationTtl,
+};
+
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // `payments-toy-engine <input>` is kept as a shorthand for `payments-toy-engine process <input>`
+    #[command(flatten)]
+    process: ProcessArgs,
+}
+
+impl Cli {
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Process(self.process))
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Process input transactions and print resulting accounts
+    Process(ProcessArgs),
+    /// Print accounts of a persistent store
+    Accounts(AccountsArgs),
+    /// Print event history of a client account in a persistent store
+    History(HistoryArgs),
+    /// Rebuild account projections of a persistent store from its events
+    Replay(ReplayArgs),
+    /// Print debit and credit totals per ledger account of a persistent store, failing when they do not sum to zero
+    TrialBalance(TrialBalanceArgs),
+    /// Lock, unlock or freeze a client account in a persistent store
+    Admin(AdminArgs),
+    /// Check input transactions without processing them
+    Validate(ValidateArgs),
+    /// Generate sample input transactions
+    Generate(GenerateArgs),
 }
 
-impl CliArgs {
-    pub fn load() -> Result<Self> {
-        let args: Vec<String> = env::args().collect();
+#[derive(Args)]
+pub struct ProcessArgs {
+    /// Input csv files, processed in order as one stream, `-` for stdin, may be gzip or zstd compressed
+    pub input_file_paths: Vec<String>,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+
+    /// Stop at the first malformed row, also checking header columns and field counts
+    #[arg(long)]
+    pub strict: bool,
+
+    /// What happens when a disputed deposit is larger than the available funds
+    #[arg(long, value_enum, default_value_t = DisputePolicy::RequireFunds)]
+    pub dispute_policy: DisputePolicy,
+
+    /// Decimal places of amounts in currencies without a `--precision` rule
+    #[arg(long, default_value_t = DEFAULT_MINOR_UNITS)]
+    pub minor_units: u32,
+
+    /// What happens to amounts with more decimal places than their currency has
+    #[arg(long, value_enum, default_value_t = RoundingMode::Reject)]
+    pub rounding: RoundingMode,
+
+    /// Decimal places of a currency and optionally its own rounding, e.g. `JPY=0` or `BTC=8:truncate`, can be repeated
+    #[arg(long, value_name = "CODE=UNITS[:ROUNDING]")]
+    pub precision: Vec<CurrencyPrecision>,
+
+    /// JSON fee schedule of deposits, withdrawals and chargebacks, no fees are charged when not passed
+    #[arg(long, value_name = "FILE")]
+    pub fees: Option<String>,
+
+    /// Input rows after which an authorization never captured expires, rows skipped as already processed are not counted
+    #[arg(long, value_name = "ROWS")]
+    pub authorization_expiry_rows: Option<u64>,
+
+    /// Seconds after the `timestamp` of its row an authorization never captured expires, by the `timestamp` of later rows
+    #[arg(long, value_name = "SECONDS")]
+    pub authorization_expiry_secs: Option<u32>,
 
-        let input_file_path = args.get(1).ok_or_eyre("Input file not passed")?.to_owned();
+    /// JSON withdrawal limits, by default and per client, withdrawals are not limited when not passed
+    #[arg(long, value_name = "FILE")]
+    pub limits: Option<String>,
 
-        Ok(CliArgs { input_file_path })
+    /// JSON risk rules screening deposits, withdrawals and disputes, all are approved when not passed
+    #[arg(long, value_name = "FILE")]
+    pub risk_rules: Option<String>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Directory of a persistent event store, temp dbs are used when not passed
+    ///
+    /// Rows already processed against the store are skipped. A row is identified by its `key` column,
+    /// or otherwise by the path of its input file, its line number and a hash of its content.
+    #[arg(long, value_name = "DIR")]
+    pub store: Option<String>,
+
+    /// File to report ignored input rows to, with their line numbers and reasons
+    #[arg(long, value_name = "FILE")]
+    pub rejects: Option<String>,
+
+    /// Format of the rejected rows report
+    #[arg(long, value_enum, default_value_t = RejectsFormat::Csv)]
+    pub rejects_format: RejectsFormat,
+}
+
+impl ProcessArgs {
+    pub fn precision_policy(&self) -> PrecisionPolicy {
+        let default = PrecisionRule {
+            minor_units: self.minor_units,
+            rounding: self.rounding,
+        };
+        self.precision
+            .iter()
+            .fold(PrecisionPolicy::new(default), |policy, p| {
+                policy.with_currency(
+                    p.currency.clone(),
+                    PrecisionRule {
+                        minor_units: p.minor_units,
+                        rounding: p.rounding.unwrap_or(self.rounding),
+                    },
+                )
+            })
+    }
+
+    pub fn authorization_ttl(&self) -> AuthorizationTtl {
+        AuthorizationTtl {
+            rows: self.authorization_expiry_rows,
+            seconds: self.authorization_expiry_secs,
+        }
     }
 }
+
+#[derive(Args)]
+pub struct AccountsArgs {
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// Client id to print the history for
+    #[arg(long)]
+    pub client: String,
+
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+#[derive(Args)]
+pub struct TrialBalanceArgs {
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+#[derive(Args)]
+pub struct AdminArgs {
+    #[command(subcommand)]
+    pub action: AdminAction,
+}
+
+#[derive(Subcommand)]
+pub enum AdminAction {
+    /// Block all transactions of the account
+    Lock(AdminActionArgs),
+    /// Lift a lock (also one set by a chargeback) or a freeze
+    Unlock(AdminActionArgs),
+    /// Block withdrawals, while deposits are still accepted
+    Freeze(AdminActionArgs),
+}
+
+#[derive(Args)]
+pub struct AdminActionArgs {
+    /// Client id of the account
+    #[arg(long)]
+    pub client: String,
+
+    /// Why the action is taken, recorded in the event
+    #[arg(long)]
+    pub reason: String,
+
+    /// Id of the operator taking the action, recorded in the event
+    #[arg(long)]
+    pub operator: String,
+
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Input csv files, `-` for stdin, may be gzip or zstd compressed
+    pub input_file_paths: Vec<String>,
+
+    /// Also check header columns and field counts
+    #[arg(long)]
+    pub strict: bool,
+}
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// Number of distinct clients
+    #[arg(long, default_value = "1000")]
+    pub clients: NonZeroUsize,
+
+    /// Number of deposit & withdrawal pairs
+    #[arg(long, default_value = "200000")]
+    pub rows: usize,
+
+    /// Output csv file, stdout when not passed
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<String>,
+}
+
+#[derive(Args)]
+pub struct EngineArgs {
+    /// Number of worker partitions, cpu core count when not passed
+    #[arg(long)]
+    pub workers: Option<NonZeroUsize>,
+
+    /// Capacity of each worker channel
+    #[arg(long, default_value = "100")]
+    pub channel_capacity: NonZeroUsize,
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Output format of the accounts
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    /// Merge accounts of all partitions and sort them by client id, so the output is deterministic
+    #[arg(long)]
+    pub sorted: bool,
+
+    /// Also write the debt, frozen, currency and authorized columns in csv, json always has them
+    #[arg(long)]
+    pub all_columns: bool,
+}
+
+#[derive(Args)]
+pub struct StoreArgs {
+    /// Directory of a persistent event store
+    #[arg(long, value_name = "DIR")]
+    pub store: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Csv,
+    /// A single JSON array of accounts
+    Json,
+    /// A JSON account object per line
+    Ndjson,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RejectsFormat {
+    Csv,
+    Ndjson,
+}
@@ -0,0 +1,171 @@
+use std::{fs::File, io};
+
+use color_eyre::eyre::{Result, eyre};
+use sqlx::SqlitePool;
+
+use crate::{
+    cli::{
+        AccountsArgs, AdminAction, AdminArgs, GenerateArgs, HistoryArgs, ReplayArgs,
+        TrialBalanceArgs, ValidateArgs,
+    },
+    csv::{self, CsvPaymentRecord},
+    domain::{
+        account::{
+            aggregate::AccountServices,
+            command::{
+                AccountCommand, FreezeAccountPayload, LockAccountPayload, UnlockAccountPayload,
+            },
+        },
+        fee::FeeSchedule,
+        props::{AuthorizationTtl, ClientId},
+    },
+    get_channel_by_client_id,
+    payments::PaymentsService,
+    query::{
+        account::{print_accounts, replay_accounts},
+        authorization::replay_authorizations,
+        history::print_account_history,
+        journal::{print_trial_balance, replay_journal},
+    },
+    store::Store,
+};
+
+pub async fn accounts(args: AccountsArgs) -> Result<()> {
+    let store = Store::open_existing(&args.store.store)?;
+    let pools = store.connect(store.partition_count(None, 0)?).await?;
+
+    let printed = print_accounts(&pools, &args.output).await;
+    store.close(&pools).await?;
+
+    printed
+}
+
+pub async fn history(args: HistoryArgs) -> Result<()> {
+    let store = Store::open_existing(&args.store.store)?;
+    let partitions = store.partition_count(None, 0)?;
+    let pools = store.connect(partitions).await?;
+
+    let pool = &pools[get_channel_by_client_id(partitions as u32, &args.client)];
+    let printed = print_account_history(pool, &args.client).await;
+    store.close(&pools).await?;
+
+    printed
+}
+
+pub async fn trial_balance(args: TrialBalanceArgs) -> Result<()> {
+    let store = Store::open_existing(&args.store.store)?;
+    let pools = store.connect(store.partition_count(None, 0)?).await?;
+
+    let printed = print_trial_balance(&pools).await;
+    store.close(&pools).await?;
+
+    printed
+}
+
+/// Projections are rebuilt from scratch, e.g. after a projection logic change.
+pub async fn replay(args: ReplayArgs) -> Result<()> {
+    let store = Store::open_existing(&args.store.store)?;
+    let pools = store.connect(store.partition_count(None, 0)?).await?;
+
+    let replayed = replay_partitions(&pools).await;
+    store.close(&pools).await?;
+
+    replayed
+}
+
+async fn replay_partitions(pools: &[SqlitePool]) -> Result<()> {
+    for pool in pools {
+        replay_accounts(pool).await?;
+        replay_journal(pool).await?;
+        replay_authorizations(pool).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn admin(args: AdminArgs) -> Result<()> {
+    let (args, command) = match args.action {
+        AdminAction::Lock(args) => {
+            let command = AccountCommand::LockAccount(LockAccountPayload {
+                client_id: ClientId(args.client.to_owned()),
+                reason: args.reason.to_owned(),
+                operator_id: args.operator.to_owned(),
+            });
+            (args, command)
+        }
+        AdminAction::Unlock(args) => {
+            let command = AccountCommand::UnlockAccount(UnlockAccountPayload {
+                client_id: ClientId(args.client.to_owned()),
+                reason: args.reason.to_owned(),
+                operator_id: args.operator.to_owned(),
+            });
+            (args, command)
+        }
+        AdminAction::Freeze(args) => {
+            let command = AccountCommand::FreezeAccount(FreezeAccountPayload {
+                client_id: ClientId(args.client.to_owned()),
+                reason: args.reason.to_owned(),
+                operator_id: args.operator.to_owned(),
+            });
+            (args, command)
+        }
+    };
+
+    let store = Store::open_existing(&args.store.store)?;
+    let partitions = store.partition_count(None, 0)?;
+    let pools = store.connect(partitions).await?;
+
+    let pool = &pools[get_channel_by_client_id(partitions as u32, &args.client)];
+    let payments_service = PaymentsService::new(
+        pool.clone(),
+        AccountServices::default(),
+        FeeSchedule::default(),
+        AuthorizationTtl::default(),
+    )
+    .await;
+    let handled = payments_service
+        .handle_account_admin(&args.client, command)
+        .await;
+    store.close(&pools).await?;
+
+    handled
+}
+
+/// Invalid rows are reported to stderr, the summary to stdout.
+pub fn validate(args: ValidateArgs) -> Result<()> {
+    if args.input_file_paths.is_empty() {
+        return Err(eyre!("Input file not passed"));
+    }
+
+    let mut rows = 0;
+    let mut invalid = 0;
+    for row in csv::read_input::<CsvPaymentRecord>(&args.input_file_paths, args.strict)? {
+        rows += 1;
+        let validated = row.record.and_then(|record| match args.strict {
+            true => record.validate_strict(),
+            false => record.validate(),
+        });
+        if let Err(e) = validated {
+            invalid += 1;
+            eprintln!("{} line {}: {}", row.source.path, row.line, e);
+        }
+    }
+
+    println!("rows: {}, invalid: {}", rows, invalid);
+    if invalid > 0 {
+        return Err(eyre!("{} invalid rows found", invalid));
+    }
+
+    Ok(())
+}
+
+pub fn generate(args: GenerateArgs) -> Result<()> {
+    match args.output {
+        Some(output_file_path) => {
+            let file = File::create(output_file_path)
+                .map_err(|e| eyre!("Could not create output file: {}", e))?;
+            csv::generate(file, args.clients.get(), args.rows)
+        }
+        None => csv::generate(io::stdout(), args.clients.get(), args.rows),
+    }
+}
@@ -1,11 +1,23 @@
 use core::str;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    iter,
+    sync::Arc,
+};
 
-use color_eyre::eyre::{Result, eyre};
-use csv::{ReaderBuilder, Trim};
-use rust_decimal::Decimal;
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use csv::{Position, ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use derive_more::Display;
+use flate2::read::MultiGzDecoder;
+use murmur2::{KAFKA_SEED, murmur2};
+use rust_decimal::{Decimal, dec};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::domain::fee::HOUSE_CLIENT_ID;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CsvPaymentRecord {
     #[serde(rename = "type")]
     pub tx_type: TxType,
@@ -14,39 +26,392 @@ pub struct CsvPaymentRecord {
     #[serde(rename = "tx")]
     pub tx_id: String,
     pub amount: Option<Decimal>,
+    /// Credited client of a transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Identifies the row when the input is processed again, instead of its position in the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Currency of the amount, the default one when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Unix time of the row in seconds, authorizations expire by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+impl CsvPaymentRecord {
+    /// Checks the row has everything needed for its type, without touching any state.
+    pub fn validate(&self) -> Result<()> {
+        if self.client_id.is_empty() {
+            return Err(RowError::MissingClientId)
+                .wrap_err(format!("No client_id in row for tx {}", self.tx_id));
+        }
+        if self.client_id == HOUSE_CLIENT_ID || self.to.as_deref() == Some(HOUSE_CLIENT_ID) {
+            return Err(RowError::ReservedClientId).wrap_err(format!(
+                "Client {} in row for tx {} is the house account",
+                HOUSE_CLIENT_ID, self.tx_id
+            ));
+        }
+
+        if matches!(
+            self.tx_type,
+            TxType::Deposit | TxType::Withdrawal | TxType::Transfer | TxType::Authorize
+        ) {
+            require_amount(self.amount, &self.tx_id)?;
+        }
+
+        if self.tx_type == TxType::Transfer {
+            let to = require_recipient(self.to.as_deref(), &self.tx_id)?;
+            if to == self.client_id {
+                return Err(RowError::SelfTransfer)
+                    .wrap_err(format!("Transfer {} to the same client", self.tx_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like `validate`, but a partial amount of a dispute, resolve or chargeback also has to be positive.
+    pub fn validate_strict(&self) -> Result<()> {
+        self.validate()?;
+
+        if matches!(
+            self.tx_type,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback
+        ) && self.amount.is_some_and(|amount| amount <= Decimal::ZERO)
+        {
+            return Err(RowError::IllegalAmount)
+                .wrap_err(format!("Amount not positive in row for tx {}", self.tx_id));
+        }
+
+        Ok(())
+    }
+}
+
+pub fn require_amount(amount_opt: Option<Decimal>, tx_id: &str) -> Result<Decimal> {
+    amount_opt
+        .ok_or(RowError::MissingAmount)
+        .wrap_err(format!("No amount found in row for tx {}", tx_id))
+}
+
+pub fn require_recipient<'a>(to_opt: Option<&'a str>, tx_id: &str) -> Result<&'a str> {
+    to_opt
+        .filter(|to| !to.is_empty())
+        .ok_or(RowError::MissingRecipient)
+        .wrap_err(format!("No recipient client in row for transfer {}", tx_id))
+}
+
+/// Row level problems, found before the row reaches any aggregate.
+#[derive(Debug, PartialEq, Display)]
+pub enum RowError {
+    UnparsableRow,
+    MissingClientId,
+    ReservedClientId,
+    MissingAmount,
+    IllegalAmount,
+    UnexpectedFieldCount,
+    MissingRecipient,
+    SelfTransfer,
+}
+
+impl RowError {
+    /// Machine readable reason, used in rejection reports.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RowError::UnparsableRow => "unparsable_row",
+            RowError::MissingClientId => "missing_client_id",
+            RowError::ReservedClientId => "reserved_client_id",
+            RowError::MissingAmount => "missing_amount",
+            RowError::IllegalAmount => "illegal_amount",
+            RowError::UnexpectedFieldCount => "unexpected_field_count",
+            RowError::MissingRecipient => "missing_recipient",
+            RowError::SelfTransfer => "self_transfer",
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+pub type PaymentRow = InputRow<CsvPaymentRecord>;
+
+impl PaymentRow {
+    /// Identifies the row across runs: its idempotency key if it has one, otherwise its position in the input
+    /// along with a hash of its content, so a file rewritten in place does not skip its changed rows.
+    /// Rows read from stdin without a key are not identified.
+    pub fn idempotency_key(&self) -> Option<String> {
+        match (&self.record.key, &self.source.id) {
+            (Some(key), _) => Some(format!("key:{}", key)),
+            (None, Some(id)) => Some(format!(
+                "{}:{}:{:08x}",
+                id,
+                self.line,
+                murmur2(self.raw.as_bytes(), KAFKA_SEED)
+            )),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Input file path, or `-` for stdin.
+pub const STDIN_PATH: &str = "-";
+
+/// Header columns required in strict mode.
+pub const CSV_HEADER: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Optional header columns, any of which may follow the required ones, in any order.
+pub const CSV_OPTIONAL_HEADER: [&str; 4] = ["to", "key", "currency", "timestamp"];
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// One of the inputs, in the order they were passed.
+#[derive(Debug, PartialEq)]
+pub struct InputSource {
+    pub index: usize,
+    pub path: String,
+    /// Canonical path identifying the input across runs, stdin has none
+    pub id: Option<String>,
+}
+
+/// Input row together with its position in the input, so it can be reported back.
+#[derive(Debug)]
+pub struct InputRow<T> {
+    pub source: Arc<InputSource>,
+    /// Line within the source
+    pub line: u64,
+    /// Original row as read, without its line terminator
+    pub raw: String,
+    pub record: T,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum TxType {
+    #[default]
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    Transfer,
+    /// Reserves funds of the client, to be captured or voided later by the same tx id
+    Authorize,
+    /// Withdraws all of the authorized funds or the given part of them, releasing the rest
+    Capture,
+    /// Releases the authorized funds
+    Void,
 }
 
+/// Reads all the inputs one after another as a single stream of rows.
+/// Each input has its own header row and can be gzip or zstd compressed.
+/// All inputs are opened upfront, so a missing file fails before anything is processed.
+/// In strict mode the header columns are checked and rows must have exactly as many fields as the header.
 pub fn read_input<D: serde::de::DeserializeOwned>(
-    file_path: &str,
-) -> Result<impl Iterator<Item = Result<D>>> {
-    let reader = ReaderBuilder::new()
+    file_paths: &[String],
+    strict: bool,
+) -> Result<impl Iterator<Item = InputRow<Result<D>>> + use<D>> {
+    let mut sources = Vec::with_capacity(file_paths.len());
+    for (index, file_path) in file_paths.iter().enumerate() {
+        let source = InputSource {
+            index,
+            path: file_path.clone(),
+            id: match file_path.as_str() {
+                STDIN_PATH => None,
+                path => fs::canonicalize(path)
+                    .ok()
+                    .map(|path| path.to_string_lossy().into_owned()),
+            },
+        };
+        sources.push(read_source::<D>(source, strict)?);
+    }
+
+    Ok(sources.into_iter().flatten())
+}
+
+fn read_source<D: serde::de::DeserializeOwned>(
+    source: InputSource,
+    strict: bool,
+) -> Result<impl Iterator<Item = InputRow<Result<D>>> + use<D>> {
+    let input = open_input(&source.path)
+        .map_err(|e| eyre!("Could not read input file {}: {}", source.path, e))?;
+    let mut reader = ReaderBuilder::new()
         .trim(Trim::All)
         .flexible(true)
-        .from_path(file_path)
-        .map_err(|e| eyre!("Could not read input file: {}", e))?;
+        .from_reader(RecordingReader::new(input));
+    let headers = reader
+        .headers()
+        .map_err(|e| eyre!("Could not read input file {}: {}", source.path, e))?
+        .clone();
+    let header_end = reader.position().byte();
+    let raw_header = reader.get_mut().take_row(0, header_end);
+    if strict && !is_expected_header(&headers) {
+        return Err(eyre!(
+            "Unexpected header in {}: expected '{}' and any of '{}', found '{}'",
+            source.path,
+            CSV_HEADER.join(","),
+            CSV_OPTIONAL_HEADER.join(","),
+            raw_header
+        ));
+    }
+    let source = Arc::new(source);
+    let mut row_start = header_end;
 
-    Ok(reader
-        .into_deserialize()
-        .map(|r| r.map_err(|ee| eyre!("Error parsing row: {}", ee))))
+    Ok(iter::from_fn(move || {
+        let mut record = StringRecord::new();
+        let read = reader.read_record(&mut record);
+        // Rows start where the previous one ended, even when they could not be read
+        let row_end = reader.position().byte();
+        let raw = reader.get_mut().take_row(row_start, row_end);
+        row_start = row_end;
+
+        let row = |line: Option<&Position>, record: Result<D>| InputRow {
+            source: source.clone(),
+            line: line.map(|p| p.line()).unwrap_or_default(),
+            raw,
+            record,
+        };
+        match read {
+            Ok(false) => None,
+            Ok(true) if strict && record.len() != headers.len() => Some(row(
+                record.position(),
+                Err(eyre!(RowError::UnexpectedFieldCount).wrap_err(format!(
+                    "Expected {} fields, found {}",
+                    headers.len(),
+                    record.len()
+                ))),
+            )),
+            Ok(true) => Some(row(
+                record.position(),
+                record
+                    .deserialize(Some(&headers))
+                    .map_err(|e| unparsable_row(&e)),
+            )),
+            Err(e) => Some(row(e.position(), Err(unparsable_row(&e)))),
+        }
+    }))
+}
+
+/// Keeps what the csv reader read from the input, so rows can be reported back exactly as they were.
+struct RecordingReader<R> {
+    input: R,
+    read: Vec<u8>,
+    /// Input offset of the first byte kept
+    offset: u64,
+}
+
+impl<R: io::Read> RecordingReader<R> {
+    fn new(input: R) -> Self {
+        RecordingReader {
+            input,
+            read: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Row between the given input offsets, without line terminators.
+    /// Nothing before the end of the row is kept anymore.
+    fn take_row(&mut self, start: u64, end: u64) -> String {
+        let from = (start.saturating_sub(self.offset) as usize).min(self.read.len());
+        let to = (end.saturating_sub(self.offset) as usize).clamp(from, self.read.len());
+        let row = String::from_utf8_lossy(&self.read[from..to])
+            .trim_matches(['\r', '\n'])
+            .to_owned();
+        self.read.drain(..to);
+        self.offset += to as u64;
+        row
+    }
+}
+
+impl<R: io::Read> io::Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.input.read(buf)?;
+        self.read.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Required columns in their order, then optional ones, neither unknown nor repeated.
+fn is_expected_header(headers: &StringRecord) -> bool {
+    let mut optional = HashSet::new();
+    headers.iter().take(CSV_HEADER.len()).eq(CSV_HEADER)
+        && headers
+            .iter()
+            .skip(CSV_HEADER.len())
+            .all(|column| CSV_OPTIONAL_HEADER.contains(&column) && optional.insert(column))
+}
+
+/// Opens a file or stdin, decompressing it when it starts with gzip or zstd magic bytes.
+fn open_input(file_path: &str) -> io::Result<Box<dyn io::Read + Send>> {
+    let input: Box<dyn io::Read + Send> = if file_path == STDIN_PATH {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(file_path)?)
+    };
+    let mut input = BufReader::new(input);
+
+    let magic = input.fill_buf()?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(input)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(input)?))
+    } else {
+        Ok(Box::new(input))
+    }
+}
+
+fn unparsable_row(e: &csv::Error) -> color_eyre::eyre::Report {
+    eyre!(RowError::UnparsableRow).wrap_err(format!("Error parsing row: {}", e))
+}
+
+/// Writes a deposit & withdrawal pair per row, spread across the given number of clients.
+pub fn generate<W: io::Write>(writer: W, n_clients: usize, rows: usize) -> Result<()> {
+    let mut csv_writer = WriterBuilder::new().from_writer(writer);
+
+    for i in 1..=rows {
+        let client_id = (i % n_clients + 1).to_string();
+
+        let deposit = CsvPaymentRecord {
+            tx_type: TxType::Deposit,
+            client_id: client_id.clone(),
+            tx_id: format!("c{}-{}-dps", client_id, i),
+            amount: dec!(1.2345).into(),
+            to: None,
+            key: None,
+            currency: None,
+            timestamp: None,
+        };
+        csv_writer.serialize(deposit)?;
+
+        let withdrawal = CsvPaymentRecord {
+            tx_type: TxType::Withdrawal,
+            client_id: client_id.clone(),
+            tx_id: format!("c{}-{}-wthr", client_id, i),
+            amount: dec!(0.2345).into(),
+            to: None,
+            key: None,
+            currency: None,
+            timestamp: None,
+        };
+        csv_writer.serialize(withdrawal)?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{fs::File, sync::Arc};
+
     use color_eyre::eyre::{Result, eyre};
-    use csv::{ReaderBuilder, Trim, WriterBuilder};
+    use csv::{ReaderBuilder, StringRecord, Trim};
     use rust_decimal::{Decimal, dec};
 
-    use crate::csv::{CsvPaymentRecord, TxType};
+    use crate::csv::{
+        CsvPaymentRecord, InputSource, PaymentRow, RowError, TxType, generate, is_expected_header,
+        read_input,
+    };
 
     #[test]
     fn parses_data() {
@@ -116,31 +481,228 @@ mod tests {
             .expect_err("Not parsable entry not found");
     }
 
+    #[test]
+    fn reads_input_positions() {
+        let rows: Vec<_> =
+            read_input::<CsvPaymentRecord>(&["sample/transactions_invalid.csv".to_owned()], false)
+                .unwrap()
+                .collect();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].raw, "deposit, 1, 1, 1.0");
+        assert!(rows[0].record.is_ok());
+        assert_eq!(rows[2].line, 4);
+        assert_eq!(rows[2].raw, "refund, 1, 3, 1.0");
+        assert!(rows[2].record.is_err());
+    }
+
+    #[test]
+    fn reads_multiple_compressed_inputs() {
+        let rows: Vec<_> = read_input::<CsvPaymentRecord>(
+            &[
+                "sample/shard_1.csv.gz".to_owned(),
+                "sample/shard_2.csv.zst".to_owned(),
+                "sample/transactions.csv".to_owned(),
+            ],
+            false,
+        )
+        .unwrap()
+        .collect();
+
+        let sources: Vec<_> = rows.iter().map(|r| r.source.index).collect();
+        let mut sorted_sources = sources.clone();
+        sorted_sources.sort();
+        assert_eq!(sources, sorted_sources);
+        assert_eq!(rows[0].source.path, "sample/shard_1.csv.gz");
+        assert_eq!(rows[0].line, 2);
+        assert!(rows.iter().all(|r| r.record.is_ok()));
+        assert_eq!(rows.last().unwrap().source.path, "sample/transactions.csv");
+    }
+
+    fn record(
+        tx_type: TxType,
+        client_id: &str,
+        tx_id: &str,
+        amount: Option<Decimal>,
+    ) -> CsvPaymentRecord {
+        CsvPaymentRecord {
+            tx_type,
+            client_id: client_id.to_owned(),
+            tx_id: tx_id.to_owned(),
+            amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validates_data() {
+        let valid = record(TxType::Dispute, "1", "1", None);
+        let no_amount = record(TxType::Deposit, "1", "2", None);
+        let no_client = record(TxType::Withdrawal, "", "3", Some(dec!(1.0)));
+        let no_recipient = record(TxType::Transfer, "1", "4", Some(dec!(1.0)));
+        let to_self = CsvPaymentRecord {
+            to: Some("1".to_owned()),
+            ..record(TxType::Transfer, "1", "5", Some(dec!(1.0)))
+        };
+        let transfer = CsvPaymentRecord {
+            to: Some("2".to_owned()),
+            ..record(TxType::Transfer, "1", "6", Some(dec!(1.0)))
+        };
+        let to_house = CsvPaymentRecord {
+            to: Some("house".to_owned()),
+            ..record(TxType::Transfer, "1", "6", Some(dec!(1.0)))
+        };
+        let authorize = CsvPaymentRecord {
+            timestamp: Some(1_700_000_000),
+            ..record(TxType::Authorize, "1", "7", None)
+        };
+
+        assert!(valid.validate().is_ok());
+        assert!(no_amount.validate().is_err());
+        assert!(no_client.validate().is_err());
+        assert_eq!(
+            no_recipient.validate().unwrap_err().downcast_ref(),
+            Some(&RowError::MissingRecipient)
+        );
+        assert_eq!(
+            to_self.validate().unwrap_err().downcast_ref(),
+            Some(&RowError::SelfTransfer)
+        );
+        assert!(transfer.validate().is_ok());
+        assert_eq!(
+            authorize.validate().unwrap_err().downcast_ref(),
+            Some(&RowError::MissingAmount)
+        );
+        assert_eq!(
+            to_house.validate().unwrap_err().downcast_ref(),
+            Some(&RowError::ReservedClientId)
+        );
+    }
+
+    #[test]
+    fn validates_data_strictly() {
+        let partial_dispute = record(TxType::Dispute, "1", "1", Some(dec!(1.0)));
+        let zero_resolve = record(TxType::Resolve, "1", "1", Some(dec!(0)));
+        let negative_chargeback = record(TxType::Chargeback, "1", "1", Some(dec!(-1.0)));
+
+        assert!(partial_dispute.validate_strict().is_ok());
+        assert!(zero_resolve.validate().is_ok());
+        assert_eq!(
+            zero_resolve.validate_strict().unwrap_err().downcast_ref(),
+            Some(&RowError::IllegalAmount)
+        );
+        assert_eq!(
+            negative_chargeback
+                .validate_strict()
+                .unwrap_err()
+                .downcast_ref(),
+            Some(&RowError::IllegalAmount)
+        );
+    }
+
+    #[test]
+    fn identifies_rows_by_position_and_content() {
+        let source = Arc::new(InputSource {
+            index: 0,
+            path: "batch.csv".to_owned(),
+            id: Some("/data/batch.csv".to_owned()),
+        });
+        let row = |raw: &str, key: Option<&str>| PaymentRow {
+            source: source.clone(),
+            line: 2,
+            raw: raw.to_owned(),
+            record: CsvPaymentRecord {
+                key: key.map(str::to_owned),
+                ..record(TxType::Deposit, "1", "1", Some(dec!(1.0)))
+            },
+        };
+
+        let key = row("deposit, 1, 1, 1.0", None).idempotency_key().unwrap();
+        assert!(key.starts_with("/data/batch.csv:2:"));
+        assert_eq!(
+            row("deposit, 1, 1, 1.0", None).idempotency_key(),
+            Some(key.clone())
+        );
+        assert_ne!(row("deposit, 1, 1, 2.0", None).idempotency_key(), Some(key));
+        assert_eq!(
+            row("deposit, 1, 1, 2.0", Some("req-1")).idempotency_key(),
+            Some("key:req-1".to_owned())
+        );
+    }
+
+    #[test]
+    fn reads_input_strictly() {
+        let rows: Vec<_> =
+            read_input::<CsvPaymentRecord>(&["sample/transaction_chargeback.csv".to_owned()], true)
+                .unwrap()
+                .collect();
+
+        assert!(rows[2].record.is_ok());
+        let e = rows[3].record.as_ref().unwrap_err();
+        assert_eq!(rows[3].line, 5);
+        assert_eq!(e.downcast_ref(), Some(&RowError::UnexpectedFieldCount));
+
+        assert!(
+            read_input::<CsvPaymentRecord>(
+                &["sample/transactions_bad_header.csv".to_owned()],
+                true
+            )
+            .is_err()
+        );
+        assert!(
+            read_input::<CsvPaymentRecord>(
+                &["sample/transactions_bad_header.csv".to_owned()],
+                false
+            )
+            .is_ok()
+        );
+
+        let transfers: Vec<_> =
+            read_input::<CsvPaymentRecord>(&["sample/transaction_transfer.csv".to_owned()], true)
+                .unwrap()
+                .collect();
+        let transfer = transfers[2].record.as_ref().unwrap();
+        assert_eq!(transfer.tx_type, TxType::Transfer);
+        assert_eq!(transfer.to.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn accepts_optional_columns_in_any_order() {
+        let header = |columns: &[&str]| StringRecord::from(columns.to_vec());
+
+        assert!(is_expected_header(&header(&[
+            "type", "client", "tx", "amount"
+        ])));
+        assert!(is_expected_header(&header(&[
+            "type", "client", "tx", "amount", "currency"
+        ])));
+        assert!(is_expected_header(&header(&[
+            "type",
+            "client",
+            "tx",
+            "amount",
+            "timestamp",
+            "to",
+            "key"
+        ])));
+        assert!(!is_expected_header(&header(&["type", "client", "tx"])));
+        assert!(!is_expected_header(&header(&[
+            "client", "type", "tx", "amount"
+        ])));
+        assert!(!is_expected_header(&header(&[
+            "type", "client", "tx", "amount", "fee"
+        ])));
+        assert!(!is_expected_header(&header(&[
+            "type", "client", "tx", "amount", "key", "key"
+        ])));
+    }
+
     #[test]
     #[ignore]
     fn generate_csv() {
-        let mut csv_writer = WriterBuilder::new().from_path("generated.csv").unwrap();
-
-        let n_clients = 1000;
-
-        for i in 1..200000 {
-            let client_id = (i % n_clients + 1).to_string();
-
-            let deposit = CsvPaymentRecord {
-                tx_type: TxType::Deposit,
-                client_id: client_id.clone(),
-                tx_id: format!("c{}-{}-dps", client_id, i),
-                amount: dec!(1.2345).into(),
-            };
-            csv_writer.serialize(deposit).unwrap();
-
-            let withdrawal = CsvPaymentRecord {
-                tx_type: TxType::Withdrawal,
-                client_id: client_id.clone(),
-                tx_id: format!("c{}-{}-wthr", client_id, i),
-                amount: dec!(0.2345).into(),
-            };
-            csv_writer.serialize(withdrawal).unwrap();
-        }
+        let file = File::create("generated.csv").unwrap();
+
+        generate(file, 1000, 200000).unwrap();
     }
 }
@@ -1,6 +1,10 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
+use clap::ValueEnum;
 use cqrs_es::Aggregate;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
@@ -9,29 +13,148 @@ use tracing::debug;
 use crate::domain::{
     account::{
         command::{
-            AccountCommand, ChargebackDisputePayload, DepositAccountPayload, DisputeFundsPayload,
-            ResolveDisputePayload, WithdrawAccountPayload,
+            AccountCommand, AuthorizeFundsPayload, CaptureAuthorizationPayload,
+            ChargebackDisputePayload, CollectFeePayload, DepositAccountPayload,
+            DisputeFundsPayload, DisputeWithdrawalPayload, ExpireAuthorizationPayload,
+            FreezeAccountPayload, LockAccountPayload, ReceiveTransferPayload,
+            ResolveDisputePayload, RevertTransferPayload, SendTransferPayload,
+            UnlockAccountPayload, VoidAuthorizationPayload, WithdrawAccountPayload,
         },
         error::AccountError,
         event::{
-            AccountDepositedPayload, AccountEvent, AccountWithdrawnPayload,
-            DisputeChargedbackPayload, DisputeResolvedPayload, FundsDisputedPayload,
+            AccountDepositedPayload, AccountEvent, AccountFrozenPayload, AccountLockedPayload,
+            AccountUnlockedPayload, AccountWithdrawnPayload, AuthorizationCapturedPayload,
+            AuthorizationExpiredPayload, AuthorizationVoidedPayload, DebtRepaidPayload,
+            DisputeChargedbackPayload, DisputeResolvedPayload, FeeChargedPayload,
+            FeeCollectedPayload, FundsAuthorizedPayload, FundsDisputedPayload,
+            TransferReceivedPayload, TransferRevertedPayload, TransferSentPayload,
+            WithdrawalDisputeChargedbackPayload, WithdrawalDisputeResolvedPayload,
+            WithdrawalDisputedPayload,
+        },
+        limits::{LimitPolicy, WithdrawalLimits},
+        precision::{PrecisionPolicy, Rounding},
+        risk::{
+            HOLD_REASON, RiskDecision, RiskRules, RiskScreen, SCREENING_OPERATOR,
+            ScreenedOperation, Screening,
         },
     },
-    props::{Amount, TransactionId},
+    fee::FeeKind,
+    props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId, TxType},
 };
 
 // Aggregate
 #[derive(Serialize, Default, Deserialize)]
 pub struct Account {
     locked: bool,
-    funds_available: Decimal,
-    funds_held: Decimal,
-    disputes: HashMap<TransactionId, Decimal>,
+    /// Funds can come in, but not leave
+    frozen: bool,
+    balances: HashMap<Currency, Balance>,
+    disputes: HashMap<TransactionId, Dispute>,
+    /// Deposits, withdrawals and transfers applied, so retried commands are not applied twice
+    #[serde(default)]
+    transactions: HashSet<TransactionId>,
+    /// Sent transfers given back to the sender, they stay applied so a retried transfer is not sent again
+    #[serde(default)]
+    reverted_transfers: HashSet<TransactionId>,
+    /// Disputes, resolves, chargebacks, captures and voids applied by payments, so retried steps are not applied twice
+    #[serde(default, alias = "dispute_payments")]
+    action_payments: HashSet<String>,
+    /// Open authorizations, their funds are authorized until captured, voided or expired
+    #[serde(default)]
+    authorizations: HashMap<TransactionId, Authorization>,
+    /// Kept, so capturing or voiding an expired authorization tells why it failed
+    #[serde(default)]
+    expired_authorizations: HashSet<TransactionId>,
+    /// Withdrawals made at a known clock, window limits look back on them
+    #[serde(default)]
+    withdrawals: Vec<Movement>,
+    /// Deposits made at a known clock, risk screening looks back on them
+    #[serde(default)]
+    deposits: Vec<Movement>,
+    /// Payments whose fees the house account collected, so retried steps are not applied twice
+    #[serde(default)]
+    collected_fees: HashSet<String>,
+}
+
+/// Funds of the account in one currency.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Balance {
+    available: Decimal,
+    held: Decimal,
+    /// Part of negative available funds, owed by the client after a dispute
+    debt: Decimal,
+    /// Reserved by open authorizations, apart from the funds held by disputes
+    #[serde(default)]
+    authorized: Decimal,
+}
+
+/// Funds moved in or out of the account at a known clock.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Movement {
+    pub currency: Currency,
+    pub amount: Decimal,
+    pub clock: InputClock,
+    /// Transaction that moved the funds, a reverted transfer no longer counts
+    #[serde(default)]
+    pub transaction_id: Option<TransactionId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Authorization {
+    currency: Currency,
+    amount: Decimal,
+    expiry: Expiry,
+}
+
+/// Disputes of a transaction, with its type deciding how funds move when they end.
+/// A transaction can be disputed in parts, as long as the parts do not exceed its amount.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Dispute {
+    tx_type: TxType,
+    currency: Currency,
+    transaction_amount: Decimal,
+    /// Disputed and neither resolved nor charged back yet
+    held: Decimal,
+    charged_back: Decimal,
+}
+
+impl Dispute {
+    /// What can still be disputed, resolved parts can be disputed again.
+    fn undisputed(&self) -> Decimal {
+        self.transaction_amount - self.held - self.charged_back
+    }
+}
+
+// Interface to the outside world, carrying the configured policies and the risk screening.
+#[derive(Clone)]
+pub struct AccountServices {
+    pub dispute_policy: DisputePolicy,
+    pub precision: PrecisionPolicy,
+    pub limits: LimitPolicy,
+    /// Screens deposits, withdrawals and disputes before they are carried out
+    pub risk: Arc<dyn RiskScreen>,
+}
+
+impl Default for AccountServices {
+    fn default() -> Self {
+        AccountServices {
+            dispute_policy: DisputePolicy::default(),
+            precision: PrecisionPolicy::default(),
+            limits: LimitPolicy::default(),
+            risk: Arc::new(RiskRules::default()),
+        }
+    }
 }
 
-// Interface to the outside world, not used in this case.
-pub struct AccountServices {}
+/// What happens when a disputed deposit is larger than the available funds.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DisputePolicy {
+    /// Reject the dispute
+    #[default]
+    RequireFunds,
+    /// Hold the funds anyway, the shortfall becomes debt paid down by later deposits
+    AllowNegative,
+}
 
 #[async_trait]
 impl Aggregate for Account {
@@ -47,154 +170,1023 @@ impl Aggregate for Account {
     async fn handle(
         &self,
         command: Self::Command,
-        _services: &Self::Services,
+        services: &Self::Services,
     ) -> Result<Vec<Self::Event>, Self::Error> {
         match command {
-            AccountCommand::DepositAccount(p) => self.deposit(p).await,
-            AccountCommand::WithdrawAccount(p) => self.withdraw(p).await,
-            AccountCommand::DisputeFunds(p) => self.dispute(p).await,
-            AccountCommand::ResolveDispute(p) => self.resolve_dispute(p).await,
-            AccountCommand::ChargebackDispute(p) => self.chargeback_dispute(p).await,
+            AccountCommand::DepositAccount(p) => self.deposit(p, services).await,
+            AccountCommand::WithdrawAccount(p) => self.withdraw(p, services).await,
+            AccountCommand::DisputeFunds(p) => self.dispute(p, services).await,
+            AccountCommand::DisputeWithdrawal(p) => {
+                self.dispute_withdrawal(p, &services.precision).await
+            }
+            AccountCommand::ResolveDispute(p) => self.resolve_dispute(p, &services.precision).await,
+            AccountCommand::ChargebackDispute(p) => {
+                self.chargeback_dispute(p, &services.precision).await
+            }
+            AccountCommand::LockAccount(p) => self.lock(p).await,
+            AccountCommand::UnlockAccount(p) => self.unlock(p).await,
+            AccountCommand::FreezeAccount(p) => self.freeze(p).await,
+            AccountCommand::SendTransfer(p) => self.send_transfer(p, services).await,
+            AccountCommand::ReceiveTransfer(p) => {
+                self.receive_transfer(p, &services.precision).await
+            }
+            AccountCommand::RevertTransfer(p) => self.revert_transfer(p, &services.precision).await,
+            AccountCommand::AuthorizeFunds(p) => self.authorize(p, &services.precision).await,
+            AccountCommand::CaptureAuthorization(p) => {
+                self.capture_authorization(p, services).await
+            }
+            AccountCommand::VoidAuthorization(p) => self.void_authorization(p).await,
+            AccountCommand::ExpireAuthorization(p) => self.expire_authorization(p).await,
+            AccountCommand::CollectFee(p) => self.collect_fee(p).await,
         }
     }
 
     fn apply(&mut self, event: Self::Event) {
         match event {
             AccountEvent::AccountDeposited(p) => {
-                self.funds_available += *p.amount;
+                self.transactions.insert(p.transaction_id.clone());
+                self.balance_mut(&p.currency).available += *p.amount;
+                if let Some(clock) = p.clock {
+                    self.deposits.push(Movement {
+                        currency: p.currency,
+                        amount: *p.amount,
+                        clock,
+                        transaction_id: Some(p.transaction_id),
+                    });
+                }
             }
             AccountEvent::AccountWithdrawn(p) => {
-                self.funds_available -= *p.amount;
+                self.transactions.insert(p.transaction_id.clone());
+                self.balance_mut(&p.currency).available -= *p.amount;
+                if let Some(clock) = p.clock {
+                    self.withdrawals.push(Movement {
+                        currency: p.currency,
+                        amount: *p.amount,
+                        clock,
+                        transaction_id: Some(p.transaction_id),
+                    });
+                }
             }
             AccountEvent::FundsDisputed(p) => {
-                self.disputes.insert(p.transaction_id, *p.amount);
-                self.funds_available -= *p.amount;
-                self.funds_held += *p.amount;
+                self.action_payments.extend(p.payment_id);
+                self.open_dispute(
+                    TxType::Deposit,
+                    p.transaction_id,
+                    p.currency.clone(),
+                    p.transaction_amount,
+                    *p.amount,
+                );
+                let balance = self.balance_mut(&p.currency);
+                balance.debt += shortfall(balance.available, *p.amount);
+                balance.available -= *p.amount;
+                balance.held += *p.amount;
             }
             AccountEvent::DisputeResolved(p) => {
-                self.disputes.remove(&p.transaction_id);
-                self.funds_available += *p.amount;
-                self.funds_held -= *p.amount;
+                self.action_payments.extend(p.payment_id);
+                self.close_dispute(&p.transaction_id, *p.amount, false);
+                let balance = self.balance_mut(&p.currency);
+                balance.available += *p.amount;
+                balance.held -= *p.amount;
             }
             AccountEvent::DisputeChargedback(p) => {
+                self.action_payments.extend(p.payment_id);
+                self.close_dispute(&p.transaction_id, *p.amount, true);
                 self.locked = true;
-                self.funds_held -= *p.amount;
+                self.balance_mut(&p.currency).held -= *p.amount;
+            }
+            AccountEvent::WithdrawalDisputed(p) => {
+                self.action_payments.extend(p.payment_id);
+                self.open_dispute(
+                    TxType::Withdrawal,
+                    p.transaction_id,
+                    p.currency.clone(),
+                    p.transaction_amount,
+                    *p.amount,
+                );
+                self.balance_mut(&p.currency).held += *p.amount;
+            }
+            AccountEvent::WithdrawalDisputeResolved(p) => {
+                self.action_payments.extend(p.payment_id);
+                self.close_dispute(&p.transaction_id, *p.amount, false);
+                self.balance_mut(&p.currency).held -= *p.amount;
+            }
+            AccountEvent::WithdrawalDisputeChargedback(p) => {
+                self.action_payments.extend(p.payment_id);
+                self.close_dispute(&p.transaction_id, *p.amount, true);
+                let balance = self.balance_mut(&p.currency);
+                balance.held -= *p.amount;
+                balance.available += *p.amount;
+            }
+            AccountEvent::DebtRepaid(p) => {
+                self.balance_mut(&p.currency).debt -= *p.amount;
+            }
+            AccountEvent::AccountLocked(_) => {
+                self.locked = true;
+            }
+            AccountEvent::AccountUnlocked(_) => {
+                self.locked = false;
+                self.frozen = false;
+            }
+            AccountEvent::AccountFrozen(_) => {
+                self.frozen = true;
+            }
+            AccountEvent::TransferSent(p) => {
+                self.transactions.insert(p.transaction_id.clone());
+                self.balance_mut(&p.currency).available -= *p.amount;
+                if let Some(clock) = p.clock {
+                    self.withdrawals.push(Movement {
+                        currency: p.currency,
+                        amount: *p.amount,
+                        clock,
+                        transaction_id: Some(p.transaction_id),
+                    });
+                }
+            }
+            AccountEvent::TransferReceived(p) => {
+                self.transactions.insert(p.transaction_id);
+                self.balance_mut(&p.currency).available += *p.amount;
+            }
+            AccountEvent::TransferReverted(p) => {
+                self.withdrawals
+                    .retain(|w| w.transaction_id.as_ref() != Some(&p.transaction_id));
+                self.reverted_transfers.insert(p.transaction_id);
+                self.balance_mut(&p.currency).available += *p.amount;
+            }
+            AccountEvent::FeeCharged(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.debt += shortfall(balance.available, *p.amount);
+                balance.available -= *p.amount;
+            }
+            AccountEvent::FundsAuthorized(p) => {
+                self.transactions.insert(p.transaction_id.clone());
+                self.authorizations.insert(
+                    p.transaction_id,
+                    Authorization {
+                        currency: p.currency.clone(),
+                        amount: *p.amount,
+                        expiry: p.expiry,
+                    },
+                );
+                let balance = self.balance_mut(&p.currency);
+                balance.available -= *p.amount;
+                balance.authorized += *p.amount;
+            }
+            AccountEvent::AuthorizationCaptured(p) => {
+                self.action_payments.extend(p.payment_id);
+                self.authorizations.remove(&p.transaction_id);
+                let balance = self.balance_mut(&p.currency);
+                balance.authorized -= *p.amount + *p.released;
+                balance.available += *p.released;
+                if let Some(clock) = p.clock {
+                    self.withdrawals.push(Movement {
+                        currency: p.currency,
+                        amount: *p.amount,
+                        clock,
+                        transaction_id: Some(p.transaction_id),
+                    });
+                }
+            }
+            AccountEvent::AuthorizationVoided(p) => {
+                self.action_payments.extend(p.payment_id);
+                self.authorizations.remove(&p.transaction_id);
+                let balance = self.balance_mut(&p.currency);
+                balance.authorized -= *p.amount;
+                balance.available += *p.amount;
+            }
+            AccountEvent::AuthorizationExpired(p) => {
+                self.authorizations.remove(&p.transaction_id);
+                self.expired_authorizations.insert(p.transaction_id);
+                let balance = self.balance_mut(&p.currency);
+                balance.authorized -= *p.amount;
+                balance.available += *p.amount;
+            }
+            AccountEvent::FeeCollected(p) => {
+                self.collected_fees.insert(p.payment_id);
+                self.balance_mut(&p.currency).available += *p.amount;
             }
         }
     }
 }
 
 impl Account {
+    /// A deposit flagged by the risk screening is still credited, but the account is frozen for review.
     async fn deposit(
         &self,
         p: DepositAccountPayload,
+        services: &AccountServices,
     ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
         debug!("Depositing {} with {}", p.client_id, p.amount);
 
-        require_legal_amount(&p.amount)?;
+        if self.is_applied(&p.transaction_id) {
+            return Ok(vec![]);
+        }
+        let precision = &services.precision;
+        let (amount, rounding) = require_legal_amount(precision, &p.currency, p.amount)?;
+        let (fee, fee_rounding) = require_legal_part(precision, &p.currency, p.fee)?;
         require_active_account(self)?;
+        // The fee is taken from the funds with the deposit in them
+        let funds = self.balance(&p.currency).available + *amount;
+        if fee.as_ref().is_some_and(|fee| funds < **fee) {
+            return Err(AccountError::InsufficientFunds);
+        }
+        let decision = require_not_rejected(
+            self,
+            services,
+            ScreenedOperation::Deposit,
+            &p.client_id,
+            &p.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?;
+        let hold = (decision == RiskDecision::FlagAndHold && !self.frozen).then(|| {
+            AccountEvent::AccountFrozen(AccountFrozenPayload {
+                client_id: p.client_id.clone(),
+                reason: HOLD_REASON.to_owned(),
+                operator_id: SCREENING_OPERATOR.to_owned(),
+            })
+        });
 
-        Ok(vec![AccountEvent::AccountDeposited(
-            AccountDepositedPayload {
-                client_id: p.client_id,
-                transaction_id: p.transaction_id,
-                amount: p.amount,
-            },
-        )])
+        let mut events = vec![AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: p.client_id.clone(),
+            transaction_id: p.transaction_id.clone(),
+            amount: amount.clone(),
+            currency: p.currency.clone(),
+            rounding,
+            clock: p.clock,
+        })];
+        events.extend(self.repay_debt(
+            p.client_id.clone(),
+            p.transaction_id.clone(),
+            p.currency.clone(),
+            *amount,
+        ));
+        events.extend(fee_charged(
+            p.client_id,
+            p.transaction_id,
+            FeeKind::Deposit,
+            p.currency,
+            fee,
+            fee_rounding,
+        ));
+        events.extend(hold);
+
+        Ok(events)
     }
 
     async fn withdraw(
         &self,
         p: WithdrawAccountPayload,
+        services: &AccountServices,
     ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
         debug!("Withdrawing {} from {}", p.amount, p.client_id);
 
-        require_legal_amount(&p.amount)?;
+        if self.is_applied(&p.transaction_id) {
+            return Ok(vec![]);
+        }
+        let precision = &services.precision;
+        let (amount, rounding) = require_legal_amount(precision, &p.currency, p.amount)?;
+        let (fee, fee_rounding) = require_legal_part(precision, &p.currency, p.fee)?;
         require_active_account(self)?;
-        require_sufficient_funds(self, &p.amount)?;
+        require_not_frozen(self)?;
+        let fee_amount = fee.as_ref().map_or(Decimal::ZERO, |fee| **fee);
+        require_sufficient_funds(self, &p.currency, &Amount(*amount + fee_amount))?;
+        require_within_limits(
+            self,
+            services.limits.limits(&p.client_id),
+            &p.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?;
+        require_not_held(require_not_rejected(
+            self,
+            services,
+            ScreenedOperation::Withdrawal,
+            &p.client_id,
+            &p.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?)?;
 
-        Ok(vec![AccountEvent::AccountWithdrawn(
-            AccountWithdrawnPayload {
-                client_id: p.client_id,
-                transaction_id: p.transaction_id,
-                amount: p.amount,
-            },
-        )])
+        let mut events = vec![AccountEvent::AccountWithdrawn(AccountWithdrawnPayload {
+            client_id: p.client_id.clone(),
+            transaction_id: p.transaction_id.clone(),
+            amount,
+            currency: p.currency.clone(),
+            rounding,
+            clock: p.clock,
+        })];
+        events.extend(fee_charged(
+            p.client_id,
+            p.transaction_id,
+            FeeKind::Withdrawal,
+            p.currency,
+            fee,
+            fee_rounding,
+        ));
+
+        Ok(events)
     }
 
     async fn dispute(
         &self,
         p: DisputeFundsPayload,
+        services: &AccountServices,
     ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
-        debug!("Disputing {} from {}", p.amount, p.client_id);
+        debug!("Disputing {} from {}", p.transaction_id, p.client_id);
 
-        require_legal_amount(&p.amount)?;
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
         require_active_account(self)?;
-        require_no_active_dispute(self, &p.transaction_id)?;
-        require_sufficient_funds(self, &p.amount)?;
+        require_same_currency(&p.transaction_currency, p.currency.as_ref())?;
+        let (transaction_amount, _) = require_legal_amount(
+            &services.precision,
+            &p.transaction_currency,
+            p.transaction_amount,
+        )?;
+        let (amount, rounding) =
+            require_legal_part(&services.precision, &p.transaction_currency, p.amount)?;
+        let amount =
+            require_disputable_amount(self, &p.transaction_id, &transaction_amount, amount)?;
+        if services.dispute_policy == DisputePolicy::RequireFunds {
+            require_sufficient_funds(self, &p.transaction_currency, &amount)?;
+        }
+        require_not_held(require_not_rejected(
+            self,
+            services,
+            ScreenedOperation::Dispute,
+            &p.client_id,
+            &p.transaction_currency,
+            &amount,
+            None,
+        )?)?;
 
         Ok(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
             client_id: p.client_id,
             transaction_id: p.transaction_id,
-            amount: p.amount,
+            transaction_amount: Some(transaction_amount),
+            amount,
+            currency: p.transaction_currency,
+            rounding,
+            payment_id: p.payment_id,
         })])
     }
 
+    /// A disputed withdrawal is provisionally credited back into held funds, so no available funds are needed.
+    async fn dispute_withdrawal(
+        &self,
+        p: DisputeWithdrawalPayload,
+        precision: &PrecisionPolicy,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Disputing withdrawal {} from {}",
+            p.transaction_id, p.client_id
+        );
+
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
+        require_active_account(self)?;
+        require_same_currency(&p.transaction_currency, p.currency.as_ref())?;
+        let (transaction_amount, _) =
+            require_legal_amount(precision, &p.transaction_currency, p.transaction_amount)?;
+        let (amount, rounding) = require_legal_part(precision, &p.transaction_currency, p.amount)?;
+        let amount =
+            require_disputable_amount(self, &p.transaction_id, &transaction_amount, amount)?;
+
+        Ok(vec![AccountEvent::WithdrawalDisputed(
+            WithdrawalDisputedPayload {
+                client_id: p.client_id,
+                transaction_id: p.transaction_id,
+                transaction_amount: Some(transaction_amount),
+                amount,
+                currency: p.transaction_currency,
+                rounding,
+                payment_id: p.payment_id,
+            },
+        )])
+    }
+
     async fn resolve_dispute(
         &self,
         p: ResolveDisputePayload,
+        precision: &PrecisionPolicy,
     ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
         debug!(
             "Resolving dispute for {} from {}",
             p.transaction_id, p.client_id
         );
 
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
         require_active_account(self)?;
 
         let dispute = require_dispute(self, &p.transaction_id)?;
+        require_same_currency(&dispute.currency, p.currency.as_ref())?;
+        let (amount, rounding) = require_legal_part(precision, &dispute.currency, p.amount)?;
+        let amount = require_held_amount(&dispute, amount)?;
 
-        Ok(vec![AccountEvent::DisputeResolved(
-            DisputeResolvedPayload {
-                client_id: p.client_id,
-                transaction_id: p.transaction_id,
-                amount: Amount(dispute),
-            },
-        )])
+        match dispute.tx_type {
+            TxType::Deposit => {
+                // Funds released back to available pay the debt down as well
+                let mut events = vec![AccountEvent::DisputeResolved(DisputeResolvedPayload {
+                    client_id: p.client_id.clone(),
+                    transaction_id: p.transaction_id.clone(),
+                    amount: amount.clone(),
+                    currency: dispute.currency.clone(),
+                    rounding,
+                    payment_id: p.payment_id,
+                })];
+                events.extend(self.repay_debt(
+                    p.client_id,
+                    p.transaction_id,
+                    dispute.currency,
+                    *amount,
+                ));
+                Ok(events)
+            }
+            TxType::Withdrawal => Ok(vec![AccountEvent::WithdrawalDisputeResolved(
+                WithdrawalDisputeResolvedPayload {
+                    client_id: p.client_id,
+                    transaction_id: p.transaction_id,
+                    amount,
+                    currency: dispute.currency,
+                    rounding,
+                    payment_id: p.payment_id,
+                },
+            )]),
+            // Transfers and authorizations are never disputed, see `Transaction::require_disputable`
+            TxType::Transfer | TxType::Authorization => Err(AccountError::DisputeNotFound),
+        }
     }
 
     async fn chargeback_dispute(
         &self,
         p: ChargebackDisputePayload,
+        precision: &PrecisionPolicy,
     ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
         debug!(
             "Carging back dispute for {} from {}",
             p.transaction_id, p.client_id
         );
 
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
         require_active_account(self)?;
 
         let dispute = require_dispute(self, &p.transaction_id)?;
+        require_same_currency(&dispute.currency, p.currency.as_ref())?;
+        let (amount, rounding) = require_legal_part(precision, &dispute.currency, p.amount)?;
+        let amount = require_held_amount(&dispute, amount)?;
+        let (fee, fee_rounding) = require_legal_part(precision, &dispute.currency, p.fee)?;
+
+        match dispute.tx_type {
+            TxType::Deposit => {
+                let mut events = vec![AccountEvent::DisputeChargedback(
+                    DisputeChargedbackPayload {
+                        client_id: p.client_id.clone(),
+                        transaction_id: p.transaction_id.clone(),
+                        amount,
+                        currency: dispute.currency.clone(),
+                        rounding,
+                        payment_id: p.payment_id,
+                    },
+                )];
+                events.extend(fee_charged(
+                    p.client_id,
+                    p.transaction_id,
+                    FeeKind::Chargeback,
+                    dispute.currency,
+                    fee,
+                    fee_rounding,
+                ));
+                Ok(events)
+            }
+            TxType::Withdrawal => {
+                let mut events = vec![AccountEvent::WithdrawalDisputeChargedback(
+                    WithdrawalDisputeChargedbackPayload {
+                        client_id: p.client_id.clone(),
+                        transaction_id: p.transaction_id.clone(),
+                        amount: amount.clone(),
+                        currency: dispute.currency.clone(),
+                        rounding,
+                        payment_id: p.payment_id,
+                    },
+                )];
+                events.extend(self.repay_debt(
+                    p.client_id,
+                    p.transaction_id,
+                    dispute.currency,
+                    *amount,
+                ));
+                Ok(events)
+            }
+            TxType::Transfer | TxType::Authorization => Err(AccountError::DisputeNotFound),
+        }
+    }
+
+    async fn lock(
+        &self,
+        p: LockAccountPayload,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!("Locking {} by {}: {}", p.client_id, p.operator_id, p.reason);
+
+        require_active_account(self)?;
+
+        Ok(vec![AccountEvent::AccountLocked(AccountLockedPayload {
+            client_id: p.client_id,
+            reason: p.reason,
+            operator_id: p.operator_id,
+        })])
+    }
+
+    async fn unlock(
+        &self,
+        p: UnlockAccountPayload,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Unlocking {} by {}: {}",
+            p.client_id, p.operator_id, p.reason
+        );
+
+        if !self.locked && !self.frozen {
+            return Err(AccountError::AccountNotLocked);
+        }
+
+        Ok(vec![AccountEvent::AccountUnlocked(
+            AccountUnlockedPayload {
+                client_id: p.client_id,
+                reason: p.reason,
+                operator_id: p.operator_id,
+            },
+        )])
+    }
+
+    async fn freeze(
+        &self,
+        p: FreezeAccountPayload,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Freezing {} by {}: {}",
+            p.client_id, p.operator_id, p.reason
+        );
+
+        require_active_account(self)?;
+        require_not_frozen(self)?;
+
+        Ok(vec![AccountEvent::AccountFrozen(AccountFrozenPayload {
+            client_id: p.client_id,
+            reason: p.reason,
+            operator_id: p.operator_id,
+        })])
+    }
+
+    /// The sent side is limited and screened like a withdrawal.
+    async fn send_transfer(
+        &self,
+        p: SendTransferPayload,
+        services: &AccountServices,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Transferring {} from {} to {}",
+            p.amount, p.client_id, p.to_client_id
+        );
+
+        if self.is_applied(&p.transaction_id) {
+            return Ok(vec![]);
+        }
+        let (amount, rounding) = require_legal_amount(&services.precision, &p.currency, p.amount)?;
+        require_active_account(self)?;
+        require_not_frozen(self)?;
+        require_sufficient_funds(self, &p.currency, &amount)?;
+        require_within_limits(
+            self,
+            services.limits.limits(&p.client_id),
+            &p.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?;
+        require_not_held(require_not_rejected(
+            self,
+            services,
+            ScreenedOperation::Withdrawal,
+            &p.client_id,
+            &p.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?)?;
+
+        Ok(vec![AccountEvent::TransferSent(TransferSentPayload {
+            client_id: p.client_id,
+            transaction_id: p.transaction_id,
+            to_client_id: p.to_client_id,
+            amount,
+            currency: p.currency,
+            rounding,
+            clock: p.clock,
+        })])
+    }
+
+    async fn receive_transfer(
+        &self,
+        p: ReceiveTransferPayload,
+        precision: &PrecisionPolicy,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Receiving {} from {} to {}",
+            p.amount, p.from_client_id, p.client_id
+        );
+
+        if self.is_applied(&p.transaction_id) {
+            return Ok(vec![]);
+        }
+        let (amount, rounding) = require_legal_amount(precision, &p.currency, p.amount)?;
+        require_active_account(self)?;
+
+        let mut events = vec![AccountEvent::TransferReceived(TransferReceivedPayload {
+            client_id: p.client_id.clone(),
+            transaction_id: p.transaction_id.clone(),
+            from_client_id: p.from_client_id,
+            amount: amount.clone(),
+            currency: p.currency.clone(),
+            rounding,
+        })];
+        events.extend(self.repay_debt(p.client_id, p.transaction_id, p.currency, *amount));
+
+        Ok(events)
+    }
+
+    /// Compensation of a sent transfer, so it is allowed even for a locked account.
+    /// Like other commands moving funds, it can be retried without being applied twice.
+    /// The amount is rounded like it was when sent.
+    async fn revert_transfer(
+        &self,
+        p: RevertTransferPayload,
+        precision: &PrecisionPolicy,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Reverting transfer {} of {} from {}",
+            p.transaction_id, p.amount, p.client_id
+        );
+
+        // Nothing to revert, when the transfer was never sent or is reverted already
+        if !self.is_applied(&p.transaction_id)
+            || self.reverted_transfers.contains(&p.transaction_id)
+        {
+            return Ok(vec![]);
+        }
+        let (amount, rounding) = require_legal_amount(precision, &p.currency, p.amount)?;
 
-        Ok(vec![AccountEvent::DisputeChargedback(
-            DisputeChargedbackPayload {
+        Ok(vec![AccountEvent::TransferReverted(
+            TransferRevertedPayload {
                 client_id: p.client_id,
                 transaction_id: p.transaction_id,
-                amount: Amount(dispute),
+                to_client_id: p.to_client_id,
+                amount,
+                currency: p.currency,
+                rounding,
             },
         )])
     }
-}
 
-fn require_legal_amount(amount: &Amount) -> Result<(), <Account as Aggregate>::Error> {
-    if amount.0 <= Decimal::ZERO {
-        return Err(AccountError::IllegalAmount);
+    /// Reserves the funds like a withdrawal would take them, without them leaving the account yet.
+    async fn authorize(
+        &self,
+        p: AuthorizeFundsPayload,
+        precision: &PrecisionPolicy,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!("Authorizing {} from {}", p.amount, p.client_id);
+
+        if self.is_applied(&p.transaction_id) {
+            return Ok(vec![]);
+        }
+        let (amount, rounding) = require_legal_amount(precision, &p.currency, p.amount)?;
+        require_active_account(self)?;
+        require_not_frozen(self)?;
+        require_sufficient_funds(self, &p.currency, &amount)?;
+
+        Ok(vec![AccountEvent::FundsAuthorized(
+            FundsAuthorizedPayload {
+                client_id: p.client_id,
+                transaction_id: p.transaction_id,
+                amount,
+                currency: p.currency,
+                expiry: p.expiry,
+                rounding,
+            },
+        )])
+    }
+
+    /// Without an amount, all of the authorized funds are captured.
+    /// An authorization is captured once, what is not captured is released.
+    /// The captured part leaves the account like a withdrawal, so it is limited and screened as one.
+    async fn capture_authorization(
+        &self,
+        p: CaptureAuthorizationPayload,
+        services: &AccountServices,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Capturing authorization {} from {}",
+            p.transaction_id, p.client_id
+        );
+
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
+        let precision = &services.precision;
+        let authorization = require_authorization(self, &p.transaction_id)?;
+        require_active_account(self)?;
+        require_not_frozen(self)?;
+        require_same_currency(&authorization.currency, p.currency.as_ref())?;
+        let (amount, rounding) = require_legal_part(precision, &authorization.currency, p.amount)?;
+        let amount = match amount {
+            None => Amount(authorization.amount),
+            Some(amount) if *amount > authorization.amount => {
+                return Err(AccountError::CaptureAmountExceeded);
+            }
+            Some(amount) => amount,
+        };
+        let released = authorization.amount - *amount;
+        let (fee, fee_rounding) = require_legal_part(precision, &authorization.currency, p.fee)?;
+        // The fee is taken from the funds with the released part back in them
+        let funds = self.balance(&authorization.currency).available + released;
+        if fee.as_ref().is_some_and(|fee| funds < **fee) {
+            return Err(AccountError::InsufficientFunds);
+        }
+        require_within_limits(
+            self,
+            services.limits.limits(&p.client_id),
+            &authorization.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?;
+        require_not_held(require_not_rejected(
+            self,
+            services,
+            ScreenedOperation::Withdrawal,
+            &p.client_id,
+            &authorization.currency,
+            &amount,
+            p.clock.as_ref(),
+        )?)?;
+
+        let mut events = vec![AccountEvent::AuthorizationCaptured(
+            AuthorizationCapturedPayload {
+                client_id: p.client_id.clone(),
+                transaction_id: p.transaction_id.clone(),
+                amount,
+                released: Amount(released),
+                currency: authorization.currency.clone(),
+                rounding,
+                clock: p.clock,
+                payment_id: p.payment_id,
+            },
+        )];
+        // Released part of the authorization pays the debt down like a deposit
+        if !released.is_zero() {
+            events.extend(self.repay_debt(
+                p.client_id.clone(),
+                p.transaction_id.clone(),
+                authorization.currency.clone(),
+                released,
+            ));
+        }
+        events.extend(fee_charged(
+            p.client_id,
+            p.transaction_id,
+            FeeKind::Withdrawal,
+            authorization.currency,
+            fee,
+            fee_rounding,
+        ));
+
+        Ok(events)
     }
 
-    if amount.scale() > 4 {
-        return Err(AccountError::IllegalAmount);
+    /// Releasing funds back is allowed even for a locked or frozen account, they pay the debt down first.
+    async fn void_authorization(
+        &self,
+        p: VoidAuthorizationPayload,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Voiding authorization {} from {}",
+            p.transaction_id, p.client_id
+        );
+
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
+        let authorization = require_authorization(self, &p.transaction_id)?;
+
+        let mut events = vec![AccountEvent::AuthorizationVoided(
+            AuthorizationVoidedPayload {
+                client_id: p.client_id.clone(),
+                transaction_id: p.transaction_id.clone(),
+                amount: Amount(authorization.amount),
+                currency: authorization.currency.clone(),
+                payment_id: p.payment_id,
+            },
+        )];
+        events.extend(self.repay_debt(
+            p.client_id,
+            p.transaction_id,
+            authorization.currency,
+            authorization.amount,
+        ));
+
+        Ok(events)
     }
 
-    Ok(())
+    /// Nothing happens when the authorization is closed already or not due yet, so expiring can be retried.
+    async fn expire_authorization(
+        &self,
+        p: ExpireAuthorizationPayload,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        let Some(authorization) = self.authorizations.get(&p.transaction_id) else {
+            return Ok(vec![]);
+        };
+        if !authorization.expiry.is_due(&p.clock) {
+            return Ok(vec![]);
+        }
+        debug!(
+            "Expiring authorization {} from {}",
+            p.transaction_id, p.client_id
+        );
+
+        let mut events = vec![AccountEvent::AuthorizationExpired(
+            AuthorizationExpiredPayload {
+                client_id: p.client_id.clone(),
+                transaction_id: p.transaction_id.clone(),
+                amount: Amount(authorization.amount),
+                currency: authorization.currency.clone(),
+            },
+        )];
+        events.extend(self.repay_debt(
+            p.client_id,
+            p.transaction_id,
+            authorization.currency.clone(),
+            authorization.amount,
+        ));
+
+        Ok(events)
+    }
+
+    /// Fees are collected even by a locked or frozen house account, they were charged already.
+    async fn collect_fee(
+        &self,
+        p: CollectFeePayload,
+    ) -> Result<Vec<<Account as Aggregate>::Event>, <Account as Aggregate>::Error> {
+        debug!(
+            "Collecting {} fee of {} from {}",
+            p.kind, p.amount, p.from_client_id
+        );
+
+        if self.collected_fees.contains(&p.payment_id) {
+            return Ok(vec![]);
+        }
+
+        Ok(vec![AccountEvent::FeeCollected(FeeCollectedPayload {
+            client_id: p.client_id,
+            from_client_id: p.from_client_id,
+            transaction_id: p.transaction_id,
+            kind: p.kind,
+            amount: p.amount,
+            currency: p.currency,
+            payment_id: p.payment_id,
+        })])
+    }
+
+    fn is_applied(&self, transaction_id: &TransactionId) -> bool {
+        self.transactions.contains(transaction_id)
+    }
+
+    fn is_payment_applied(&self, payment_id: &Option<String>) -> bool {
+        payment_id
+            .as_ref()
+            .is_some_and(|id| self.action_payments.contains(id))
+    }
+
+    fn balance(&self, currency: &Currency) -> Balance {
+        self.balances.get(currency).cloned().unwrap_or_default()
+    }
+
+    fn balance_mut(&mut self, currency: &Currency) -> &mut Balance {
+        self.balances.entry(currency.clone()).or_default()
+    }
+
+    /// Events recorded before partial disputes have no transaction amount, those always disputed all of it.
+    fn open_dispute(
+        &mut self,
+        tx_type: TxType,
+        transaction_id: TransactionId,
+        currency: Currency,
+        transaction_amount: Option<Amount>,
+        amount: Decimal,
+    ) {
+        let transaction_amount = transaction_amount.map_or(amount, |a| *a);
+        self.disputes
+            .entry(transaction_id)
+            .or_insert(Dispute {
+                tx_type,
+                currency,
+                transaction_amount,
+                held: Decimal::ZERO,
+                charged_back: Decimal::ZERO,
+            })
+            .held += amount;
+    }
+
+    /// Charged back parts are kept, so they can not be disputed again.
+    fn close_dispute(
+        &mut self,
+        transaction_id: &TransactionId,
+        amount: Decimal,
+        charged_back: bool,
+    ) {
+        let Some(dispute) = self.disputes.get_mut(transaction_id) else {
+            return;
+        };
+
+        dispute.held -= amount;
+        if charged_back {
+            dispute.charged_back += amount;
+        }
+        if dispute.held.is_zero() && dispute.charged_back.is_zero() {
+            self.disputes.remove(transaction_id);
+        }
+    }
+
+    /// Funds credited to available go to the outstanding debt in the same currency first.
+    fn repay_debt(
+        &self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+        currency: Currency,
+        credited: Decimal,
+    ) -> Option<AccountEvent> {
+        let debt = self.balance(&currency).debt;
+        if debt <= Decimal::ZERO {
+            return None;
+        }
+
+        Some(AccountEvent::DebtRepaid(DebtRepaidPayload {
+            client_id,
+            transaction_id,
+            amount: Amount(credited.min(debt)),
+            currency,
+        }))
+    }
+}
+
+fn fee_charged(
+    client_id: ClientId,
+    transaction_id: TransactionId,
+    kind: FeeKind,
+    currency: Currency,
+    fee: Option<Amount>,
+    rounding: Option<Rounding>,
+) -> Option<AccountEvent> {
+    fee.map(|amount| {
+        AccountEvent::FeeCharged(FeeChargedPayload {
+            client_id,
+            transaction_id,
+            kind,
+            amount,
+            currency,
+            rounding,
+        })
+    })
+}
+
+/// Part of the amount not covered by the available funds.
+pub fn shortfall(funds_available: Decimal, amount: Decimal) -> Decimal {
+    (amount - funds_available.max(Decimal::ZERO)).max(Decimal::ZERO)
+}
+
+/// Amount moved by the command, following the precision rule of its currency.
+fn require_legal_amount(
+    precision: &PrecisionPolicy,
+    currency: &Currency,
+    amount: Amount,
+) -> Result<(Amount, Option<Rounding>), <Account as Aggregate>::Error> {
+    precision.rule(currency).apply(amount)
+}
+
+/// Like `require_legal_amount`, for an amount which may not be given, like a part of a dispute or a fee.
+fn require_legal_part(
+    precision: &PrecisionPolicy,
+    currency: &Currency,
+    amount: Option<Amount>,
+) -> Result<(Option<Amount>, Option<Rounding>), <Account as Aggregate>::Error> {
+    match amount {
+        Some(amount) => {
+            let (amount, rounding) = require_legal_amount(precision, currency, amount)?;
+            Ok((Some(amount), rounding))
+        }
+        None => Ok((None, None)),
+    }
 }
 
 fn require_active_account(account: &Account) -> Result<(), <Account as Aggregate>::Error> {
@@ -205,402 +1197,683 @@ fn require_active_account(account: &Account) -> Result<(), <Account as Aggregate
     Ok(())
 }
 
+fn require_not_frozen(account: &Account) -> Result<(), <Account as Aggregate>::Error> {
+    if account.frozen {
+        return Err(AccountError::AccountFrozen);
+    }
+
+    Ok(())
+}
+
 fn require_sufficient_funds(
     account: &Account,
+    currency: &Currency,
     amount: &Amount,
 ) -> Result<(), <Account as Aggregate>::Error> {
-    if account.funds_available < amount.0 {
+    if account.balance(currency).available < amount.0 {
         return Err(AccountError::InsufficientFunds);
     }
 
     Ok(())
 }
 
-fn require_dispute(
-    account: &Account,
-    transaction_id: &TransactionId,
-) -> Result<Decimal, <Account as Aggregate>::Error> {
-    account
-        .disputes
-        .get(transaction_id)
-        .map(|x| x.to_owned())
-        .ok_or(AccountError::DisputeNotFound)
-}
-
-fn require_no_active_dispute(
+/// The withdrawal together with the earlier ones in its window, in the same currency, stays within the limits.
+/// Without a clock of its own (a payment started before limits existed), only the single withdrawal limit applies.
+fn require_within_limits(
     account: &Account,
-    transaction_id: &TransactionId,
+    limits: &WithdrawalLimits,
+    currency: &Currency,
+    amount: &Amount,
+    clock: Option<&InputClock>,
 ) -> Result<(), <Account as Aggregate>::Error> {
-    if account.disputes.contains_key(transaction_id) {
-        return Err(AccountError::DuplicateDispute);
+    if limits.max_amount.is_some_and(|max| **amount > max) {
+        return Err(AccountError::LimitExceeded);
+    }
+    let Some(now) = clock else {
+        return Ok(());
+    };
+
+    let (count, total) = account
+        .withdrawals
+        .iter()
+        .filter(|w| &w.currency == currency && limits.in_window(&w.clock, now))
+        .fold((1, **amount), |(count, total), w| {
+            (count + 1, total + w.amount)
+        });
+    if limits.max_window_count.is_some_and(|max| count > max)
+        || limits.max_window_amount.is_some_and(|max| total > max)
+    {
+        return Err(AccountError::LimitExceeded);
+    }
+
+    Ok(())
+}
+
+/// Screens the operation, a rejected one fails. Others are left to the caller, as holding differs per operation.
+fn require_not_rejected(
+    account: &Account,
+    services: &AccountServices,
+    operation: ScreenedOperation,
+    client_id: &ClientId,
+    currency: &Currency,
+    amount: &Amount,
+    clock: Option<&InputClock>,
+) -> Result<RiskDecision, <Account as Aggregate>::Error> {
+    match services.risk.screen(&Screening {
+        operation,
+        client_id,
+        currency,
+        amount: **amount,
+        clock,
+        deposits: &account.deposits,
+    }) {
+        RiskDecision::Reject => Err(AccountError::RiskRejected),
+        decision => Ok(decision),
+    }
+}
+
+/// A flagged operation taking funds is not carried out, the caller freezes the account for review.
+fn require_not_held(decision: RiskDecision) -> Result<(), <Account as Aggregate>::Error> {
+    if decision == RiskDecision::FlagAndHold {
+        return Err(AccountError::RiskHeld);
+    }
+
+    Ok(())
+}
+
+/// A dispute stays in the currency of its transaction, rows not naming one are in it as well.
+fn require_same_currency(
+    currency: &Currency,
+    requested: Option<&Currency>,
+) -> Result<(), <Account as Aggregate>::Error> {
+    if requested.is_some_and(|requested| requested != currency) {
+        return Err(AccountError::CurrencyMismatch);
     }
 
     Ok(())
 }
 
+/// Authorization still open, an expired one can not be captured or voided anymore.
+fn require_authorization(
+    account: &Account,
+    transaction_id: &TransactionId,
+) -> Result<Authorization, <Account as Aggregate>::Error> {
+    if account.expired_authorizations.contains(transaction_id) {
+        return Err(AccountError::AuthorizationExpired);
+    }
+
+    account
+        .authorizations
+        .get(transaction_id)
+        .cloned()
+        .ok_or(AccountError::AuthorizationNotFound)
+}
+
+/// Dispute with some amount still held.
+fn require_dispute(
+    account: &Account,
+    transaction_id: &TransactionId,
+) -> Result<Dispute, <Account as Aggregate>::Error> {
+    account
+        .disputes
+        .get(transaction_id)
+        .filter(|d| d.held > Decimal::ZERO)
+        .cloned()
+        .ok_or(AccountError::DisputeNotFound)
+}
+
+/// The requested part of the transaction, or all of it which is not disputed yet.
+fn require_disputable_amount(
+    account: &Account,
+    transaction_id: &TransactionId,
+    transaction_amount: &Amount,
+    amount: Option<Amount>,
+) -> Result<Amount, <Account as Aggregate>::Error> {
+    let undisputed = account
+        .disputes
+        .get(transaction_id)
+        .map_or(**transaction_amount, |d| d.undisputed());
+
+    match amount {
+        None if undisputed <= Decimal::ZERO => Err(AccountError::DuplicateDispute),
+        None => Ok(Amount(undisputed)),
+        Some(amount) if *amount > undisputed => Err(AccountError::DisputeAmountExceeded),
+        Some(amount) => Ok(amount),
+    }
+}
+
+/// The requested part of the held amount, or all of it.
+fn require_held_amount(
+    dispute: &Dispute,
+    amount: Option<Amount>,
+) -> Result<Amount, <Account as Aggregate>::Error> {
+    match amount {
+        None => Ok(Amount(dispute.held)),
+        Some(amount) if *amount > dispute.held => Err(AccountError::DisputeAmountExceeded),
+        Some(amount) => Ok(amount),
+    }
+}
+
 pub fn acc_aggregate_id(id: &str) -> String {
     format!("Account-{}", id)
 }
 
 #[cfg(test)]
 mod tests {
-    use cqrs_es::test::TestFramework;
-    use rust_decimal::dec;
+    use std::sync::Arc;
+
+    use cqrs_es::{Aggregate, test::TestFramework};
+    use rust_decimal::{Decimal, dec};
 
     use crate::domain::{
         account::{
-            aggregate::{Account, AccountServices},
+            aggregate::{Account, AccountServices, DisputePolicy},
             command::{
-                AccountCommand, ChargebackDisputePayload, DepositAccountPayload,
-                DisputeFundsPayload, ResolveDisputePayload, WithdrawAccountPayload,
+                AccountCommand, AuthorizeFundsPayload, CaptureAuthorizationPayload,
+                ChargebackDisputePayload, CollectFeePayload, DepositAccountPayload,
+                DisputeFundsPayload, DisputeWithdrawalPayload, ExpireAuthorizationPayload,
+                FreezeAccountPayload, LockAccountPayload, ReceiveTransferPayload,
+                ResolveDisputePayload, RevertTransferPayload, SendTransferPayload,
+                UnlockAccountPayload, VoidAuthorizationPayload, WithdrawAccountPayload,
             },
             error::AccountError,
             event::{
-                AccountDepositedPayload, AccountEvent, AccountWithdrawnPayload,
-                DisputeChargedbackPayload, DisputeResolvedPayload, FundsDisputedPayload,
+                AccountDepositedPayload, AccountEvent, AccountFrozenPayload, AccountLockedPayload,
+                AccountUnlockedPayload, AccountWithdrawnPayload, AuthorizationCapturedPayload,
+                AuthorizationExpiredPayload, AuthorizationVoidedPayload, DebtRepaidPayload,
+                DisputeChargedbackPayload, DisputeResolvedPayload, FeeChargedPayload,
+                FeeCollectedPayload, FundsAuthorizedPayload, FundsDisputedPayload,
+                TransferReceivedPayload, TransferRevertedPayload, TransferSentPayload,
+                WithdrawalDisputeChargedbackPayload, WithdrawalDisputeResolvedPayload,
+                WithdrawalDisputedPayload,
+            },
+            limits::{LimitPolicy, LimitWindow, WithdrawalLimits},
+            precision::{PrecisionPolicy, PrecisionRule, Rounding, RoundingMode},
+            risk::{
+                HOLD_REASON, RiskDecision, RiskRules, RiskScreen, SCREENING_OPERATOR,
+                ScreenedOperation, Screening,
             },
         },
-        props::{Amount, ClientId, TransactionId},
+        fee::{FeeKind, HOUSE_CLIENT_ID},
+        props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId},
     };
 
     type AccountTestFramework = TestFramework<Account>;
 
     #[test]
     fn test_deposit_fresh_account() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given_no_previous_events()
             .when(AccountCommand::DepositAccount(DepositAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(1.2345)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_events(vec![AccountEvent::AccountDeposited(
                 AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.2345)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )]);
     }
 
     #[test]
     fn test_deposit_zero_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given_no_previous_events()
             .when(AccountCommand::DepositAccount(DepositAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::IllegalAmount);
     }
 
     #[test]
     fn test_deposit_overscale_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given_no_previous_events()
             .when(AccountCommand::DepositAccount(DepositAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(0.12345)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::IllegalAmount);
     }
 
     #[test]
     fn test_deposit_negative_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given_no_previous_events()
             .when(AccountCommand::DepositAccount(DepositAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(-1.04)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::IllegalAmount);
     }
 
     #[test]
     fn test_deposit_locked_account() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
                 AccountEvent::DisputeChargedback(DisputeChargedbackPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::DepositAccount(DepositAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::AccountLocked);
     }
 
     #[test]
     fn test_withdraw_full_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![AccountEvent::AccountDeposited(
                 AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )])
             .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-2".to_owned()),
                 amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_events(vec![AccountEvent::AccountWithdrawn(
                 AccountWithdrawnPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-2".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )]);
     }
 
     #[test]
     fn test_withdraw_partial_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![AccountEvent::AccountDeposited(
                 AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )])
             .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-2".to_owned()),
                 amount: Amount(dec!(0.23)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_events(vec![AccountEvent::AccountWithdrawn(
                 AccountWithdrawnPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-2".to_owned()),
                     amount: Amount(dec!(0.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )]);
     }
 
     #[test]
     fn test_withdraw_insufficient_funds() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![AccountEvent::AccountDeposited(
                 AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )])
             .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-2".to_owned()),
                 amount: Amount(dec!(1.2301)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::InsufficientFunds);
     }
 
     #[test]
     fn test_withdraw_zero_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given_no_previous_events()
             .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::IllegalAmount);
     }
 
     #[test]
     fn test_withdraw_negative_amount() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given_no_previous_events()
             .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
                 amount: Amount(dec!(-1.04)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
             }))
             .then_expect_error(AccountError::IllegalAmount);
     }
 
     #[test]
     fn test_dispute_funds() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![AccountEvent::AccountDeposited(
                 AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )])
             .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
-                amount: Amount(dec!(1.0)),
+                transaction_amount: Amount(dec!(1.0)),
+                amount: None,
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
             }))
             .then_expect_events(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(1.0))),
                 amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
             })]);
     }
 
     #[test]
     fn test_dispute_insufficient_funds() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![AccountEvent::AccountDeposited(
                 AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 },
             )])
             .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-2".to_owned()),
-                amount: Amount(dec!(1.2302)),
+                transaction_amount: Amount(dec!(1.2302)),
+                amount: None,
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
             }))
             .then_expect_error(AccountError::InsufficientFunds);
     }
 
     #[test]
     fn test_dispute_duplicate() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
-                amount: Amount(dec!(0.23)),
+                transaction_amount: Amount(dec!(0.23)),
+                amount: None,
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
             }))
             .then_expect_error(AccountError::DuplicateDispute);
     }
 
     #[test]
     fn test_resolve_dispute() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-1".to_owned()),
+                amount: None,
+                currency: None,
+                payment_id: None,
             }))
             .then_expect_events(vec![AccountEvent::DisputeResolved(
                 DisputeResolvedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 },
             )]);
     }
 
     #[test]
     fn test_resolve_dispute_tx_not_found() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-2".to_owned()),
+                amount: None,
+                currency: None,
+                payment_id: None,
             }))
             .then_expect_error(AccountError::DisputeNotFound);
     }
 
     #[test]
     fn test_resolve_dispute_account_locked() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-2".to_owned()),
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-2".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
                 AccountEvent::DisputeChargedback(DisputeChargedbackPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
                 client_id: ClientId("cl-1".to_owned()),
                 transaction_id: TransactionId("tx-2".to_owned()),
+                amount: None,
+                currency: None,
+                payment_id: None,
             }))
             .then_expect_error(AccountError::AccountLocked);
     }
 
     #[test]
     fn test_chargeback_dispute() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::ChargebackDispute(
                 ChargebackDisputePayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    payment_id: None,
                 },
             ))
             .then_expect_events(vec![AccountEvent::DisputeChargedback(
@@ -608,31 +1881,2031 @@ mod tests {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 },
             )]);
     }
 
     #[test]
     fn test_chargeback_dispute_tx_not_found() {
-        AccountTestFramework::with(AccountServices {})
+        AccountTestFramework::with(AccountServices::default())
             .given(vec![
                 AccountEvent::AccountDeposited(AccountDepositedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
                     amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
                 }),
                 AccountEvent::FundsDisputed(FundsDisputedPayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: None,
                     amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
                 }),
             ])
             .when(AccountCommand::ChargebackDispute(
                 ChargebackDisputePayload {
                     client_id: ClientId("cl-1".to_owned()),
                     transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    payment_id: None,
                 },
             ))
             .then_expect_error(AccountError::DisputeNotFound);
     }
+
+    fn withdrawn_account_events() -> Vec<AccountEvent> {
+        vec![
+            AccountEvent::AccountDeposited(AccountDepositedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+            AccountEvent::AccountWithdrawn(AccountWithdrawnPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_dispute_withdrawal_without_available_funds() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(withdrawn_account_events())
+            .when(AccountCommand::DisputeWithdrawal(
+                DisputeWithdrawalPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    transaction_amount: Amount(dec!(1.23)),
+                    amount: None,
+                    transaction_currency: Currency::default(),
+                    currency: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![AccountEvent::WithdrawalDisputed(
+                WithdrawalDisputedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    transaction_amount: Some(Amount(dec!(1.23))),
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_dispute_withdrawal_duplicate() {
+        let mut events = withdrawn_account_events();
+        events.push(AccountEvent::WithdrawalDisputed(
+            WithdrawalDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                transaction_amount: None,
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            },
+        ));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::DisputeWithdrawal(
+                DisputeWithdrawalPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    transaction_amount: Amount(dec!(1.23)),
+                    amount: None,
+                    transaction_currency: Currency::default(),
+                    currency: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_error(AccountError::DuplicateDispute);
+    }
+
+    #[test]
+    fn test_resolve_withdrawal_dispute() {
+        let mut events = withdrawn_account_events();
+        events.push(AccountEvent::WithdrawalDisputed(
+            WithdrawalDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                transaction_amount: None,
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            },
+        ));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: None,
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::WithdrawalDisputeResolved(
+                WithdrawalDisputeResolvedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_chargeback_withdrawal_dispute() {
+        let mut events = withdrawn_account_events();
+        events.push(AccountEvent::WithdrawalDisputed(
+            WithdrawalDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                transaction_amount: None,
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            },
+        ));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::ChargebackDispute(
+                ChargebackDisputePayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![AccountEvent::WithdrawalDisputeChargedback(
+                WithdrawalDisputeChargedbackPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_deposit_after_withdrawal_dispute_chargeback() {
+        let mut events = withdrawn_account_events();
+        events.extend([
+            AccountEvent::WithdrawalDisputed(WithdrawalDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                transaction_amount: None,
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            }),
+            AccountEvent::WithdrawalDisputeChargedback(WithdrawalDisputeChargedbackPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            }),
+        ]);
+
+        // The client was not at fault, so the account is not locked
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-3".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![AccountEvent::AccountDeposited(
+                AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-3".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                },
+            )]);
+    }
+
+    fn allow_negative() -> AccountServices {
+        AccountServices {
+            dispute_policy: DisputePolicy::AllowNegative,
+            ..AccountServices::default()
+        }
+    }
+
+    fn indebted_account_events() -> Vec<AccountEvent> {
+        vec![
+            AccountEvent::AccountDeposited(AccountDepositedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+            AccountEvent::AccountWithdrawn(AccountWithdrawnPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+            AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: None,
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_dispute_insufficient_funds_allowed_negative() {
+        AccountTestFramework::with(allow_negative())
+            .given(vec![
+                AccountEvent::AccountDeposited(AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                }),
+                AccountEvent::AccountWithdrawn(AccountWithdrawnPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                }),
+            ])
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(1.23)),
+                amount: None,
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(1.23))),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            })]);
+    }
+
+    #[test]
+    fn test_withdraw_with_debt() {
+        AccountTestFramework::with(allow_negative())
+            .given(indebted_account_events())
+            .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-3".to_owned()),
+                amount: Amount(dec!(0.01)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_error(AccountError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_deposit_repays_debt_first() {
+        AccountTestFramework::with(allow_negative())
+            .given(indebted_account_events())
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-3".to_owned()),
+                amount: Amount(dec!(2.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![
+                AccountEvent::AccountDeposited(AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-3".to_owned()),
+                    amount: Amount(dec!(2.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                }),
+                AccountEvent::DebtRepaid(DebtRepaidPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-3".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                }),
+            ]);
+    }
+
+    #[test]
+    fn test_deposit_repays_debt_partially() {
+        let mut events = indebted_account_events();
+        events.push(AccountEvent::DebtRepaid(DebtRepaidPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-3".to_owned()),
+            amount: Amount(dec!(0.4)),
+            currency: Currency::default(),
+        }));
+
+        AccountTestFramework::with(allow_negative())
+            .given(events)
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-4".to_owned()),
+                amount: Amount(dec!(0.5)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![
+                AccountEvent::AccountDeposited(AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-4".to_owned()),
+                    amount: Amount(dec!(0.5)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                }),
+                AccountEvent::DebtRepaid(DebtRepaidPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-4".to_owned()),
+                    amount: Amount(dec!(0.5)),
+                    currency: Currency::default(),
+                }),
+            ]);
+    }
+
+    fn partially_disputed_account_events() -> Vec<AccountEvent> {
+        vec![
+            AccountEvent::AccountDeposited(AccountDepositedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(10.0)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+            AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(10.0))),
+                amount: Amount(dec!(4.0)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_dispute_remaining_part() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(partially_disputed_account_events())
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(10.0)),
+                amount: None,
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(10.0))),
+                amount: Amount(dec!(6.0)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            })]);
+    }
+
+    #[test]
+    fn test_dispute_parts_exceeding_transaction() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(partially_disputed_account_events())
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(10.0)),
+                amount: Some(Amount(dec!(6.01))),
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_error(AccountError::DisputeAmountExceeded);
+    }
+
+    #[test]
+    fn test_resolve_dispute_part() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(partially_disputed_account_events())
+            .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Some(Amount(dec!(1.5))),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::DisputeResolved(
+                DisputeResolvedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(1.5)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_resolve_more_than_disputed() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(partially_disputed_account_events())
+            .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Some(Amount(dec!(4.5))),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_error(AccountError::DisputeAmountExceeded);
+    }
+
+    #[test]
+    fn test_dispute_again_after_partial_resolve() {
+        let mut events = partially_disputed_account_events();
+        events.push(AccountEvent::DisputeResolved(DisputeResolvedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(4.0)),
+            currency: Currency::default(),
+            rounding: None,
+            payment_id: None,
+        }));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(10.0)),
+                amount: None,
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(10.0))),
+                amount: Amount(dec!(10.0)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            })]);
+    }
+
+    #[test]
+    fn test_chargeback_dispute_part() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(partially_disputed_account_events())
+            .when(AccountCommand::ChargebackDispute(
+                ChargebackDisputePayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Some(Amount(dec!(2.5))),
+                    currency: None,
+                    fee: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![AccountEvent::DisputeChargedback(
+                DisputeChargedbackPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(2.5)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_dispute_part_retried_by_payment() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(vec![
+                AccountEvent::AccountDeposited(AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(10.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                }),
+                AccountEvent::FundsDisputed(FundsDisputedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    transaction_amount: Some(Amount(dec!(10.0))),
+                    amount: Amount(dec!(2.5)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    payment_id: Some("tx-1:dispute:2".to_owned()),
+                }),
+            ])
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(10.0)),
+                amount: Some(Amount(dec!(2.5))),
+                transaction_currency: Currency::default(),
+                currency: None,
+                payment_id: Some("tx-1:dispute:2".to_owned()),
+            }))
+            .then_expect_events(vec![]);
+    }
+
+    #[test]
+    fn test_chargeback_retried_by_payment() {
+        let mut events = chargedback_account_events();
+        if let Some(AccountEvent::DisputeChargedback(p)) = events.last_mut() {
+            p.payment_id = Some("tx-1:chargeback:3".to_owned());
+        }
+
+        // The account is locked by the chargeback, the retried step is still taken
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::ChargebackDispute(
+                ChargebackDisputePayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    payment_id: Some("tx-1:chargeback:3".to_owned()),
+                },
+            ))
+            .then_expect_events(vec![]);
+    }
+
+    fn chargedback_account_events() -> Vec<AccountEvent> {
+        vec![
+            AccountEvent::AccountDeposited(AccountDepositedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+            AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: None,
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            }),
+            AccountEvent::DisputeChargedback(DisputeChargedbackPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_lock_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given_no_previous_events()
+            .when(AccountCommand::LockAccount(LockAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "kyc".to_owned(),
+                operator_id: "op-1".to_owned(),
+            }))
+            .then_expect_events(vec![AccountEvent::AccountLocked(AccountLockedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "kyc".to_owned(),
+                operator_id: "op-1".to_owned(),
+            })]);
+    }
+
+    #[test]
+    fn test_unlock_chargedback_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(chargedback_account_events())
+            .when(AccountCommand::UnlockAccount(UnlockAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "investigated".to_owned(),
+                operator_id: "op-1".to_owned(),
+            }))
+            .then_expect_events(vec![AccountEvent::AccountUnlocked(
+                AccountUnlockedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    reason: "investigated".to_owned(),
+                    operator_id: "op-1".to_owned(),
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_deposit_after_unlock() {
+        let mut events = chargedback_account_events();
+        events.push(AccountEvent::AccountUnlocked(AccountUnlockedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            reason: "investigated".to_owned(),
+            operator_id: "op-1".to_owned(),
+        }));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![AccountEvent::AccountDeposited(
+                AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_unlock_active_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given_no_previous_events()
+            .when(AccountCommand::UnlockAccount(UnlockAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "mistake".to_owned(),
+                operator_id: "op-1".to_owned(),
+            }))
+            .then_expect_error(AccountError::AccountNotLocked);
+    }
+
+    fn frozen_account_events() -> Vec<AccountEvent> {
+        vec![
+            AccountEvent::AccountDeposited(AccountDepositedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            }),
+            AccountEvent::AccountFrozen(AccountFrozenPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "aml".to_owned(),
+                operator_id: "op-1".to_owned(),
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_freeze_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given_no_previous_events()
+            .when(AccountCommand::FreezeAccount(FreezeAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "aml".to_owned(),
+                operator_id: "op-1".to_owned(),
+            }))
+            .then_expect_events(vec![AccountEvent::AccountFrozen(AccountFrozenPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "aml".to_owned(),
+                operator_id: "op-1".to_owned(),
+            })]);
+    }
+
+    #[test]
+    fn test_withdraw_frozen_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(frozen_account_events())
+            .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_error(AccountError::AccountFrozen);
+    }
+
+    #[test]
+    fn test_deposit_frozen_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(frozen_account_events())
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![AccountEvent::AccountDeposited(
+                AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_lock_locked_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(chargedback_account_events())
+            .when(AccountCommand::LockAccount(LockAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: "kyc".to_owned(),
+                operator_id: "op-1".to_owned(),
+            }))
+            .then_expect_error(AccountError::AccountLocked);
+    }
+
+    #[test]
+    fn test_send_transfer() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(frozen_account_events()[..1].to_vec())
+            .when(AccountCommand::SendTransfer(SendTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                clock: None,
+            }))
+            .then_expect_events(vec![AccountEvent::TransferSent(TransferSentPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+            })]);
+    }
+
+    #[test]
+    fn test_send_transfer_insufficient_funds() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(frozen_account_events()[..1].to_vec())
+            .when(AccountCommand::SendTransfer(SendTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(2.0)),
+                currency: Currency::default(),
+                clock: None,
+            }))
+            .then_expect_error(AccountError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_send_transfer_frozen_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(frozen_account_events())
+            .when(AccountCommand::SendTransfer(SendTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                clock: None,
+            }))
+            .then_expect_error(AccountError::AccountFrozen);
+    }
+
+    #[test]
+    fn test_receive_transfer_repays_debt() {
+        AccountTestFramework::with(allow_negative())
+            .given(indebted_account_events())
+            .when(AccountCommand::ReceiveTransfer(ReceiveTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-9".to_owned()),
+                from_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(5.0)),
+                currency: Currency::default(),
+            }))
+            .then_expect_events(vec![
+                AccountEvent::TransferReceived(TransferReceivedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-9".to_owned()),
+                    from_client_id: ClientId("cl-2".to_owned()),
+                    amount: Amount(dec!(5.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                }),
+                AccountEvent::DebtRepaid(DebtRepaidPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-9".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                }),
+            ]);
+    }
+
+    #[test]
+    fn test_receive_transfer_locked_account() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(chargedback_account_events())
+            .when(AccountCommand::ReceiveTransfer(ReceiveTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                from_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+            }))
+            .then_expect_error(AccountError::AccountLocked);
+    }
+
+    #[test]
+    fn test_revert_transfer() {
+        let mut events = frozen_account_events()[..1].to_vec();
+        events.push(AccountEvent::TransferSent(TransferSentPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(dec!(1.0)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: None,
+        }));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::RevertTransfer(RevertTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+            }))
+            .then_expect_events(vec![AccountEvent::TransferReverted(
+                TransferRevertedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    to_client_id: ClientId("cl-2".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_deposit_applied_once() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(frozen_account_events()[..1].to_vec())
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.23)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![]);
+    }
+
+    #[test]
+    fn test_revert_transfer_applied_once() {
+        let mut events = frozen_account_events()[..1].to_vec();
+        events.push(AccountEvent::TransferSent(TransferSentPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(dec!(1.0)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: None,
+        }));
+        events.push(AccountEvent::TransferReverted(TransferRevertedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(dec!(1.0)),
+            currency: Currency::default(),
+            rounding: None,
+        }));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::RevertTransfer(RevertTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+            }))
+            .then_expect_events(vec![]);
+    }
+
+    #[test]
+    fn test_reverted_transfer_not_sent_again() {
+        let mut events = frozen_account_events()[..1].to_vec();
+        events.push(AccountEvent::TransferSent(TransferSentPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(dec!(1.0)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: None,
+        }));
+        events.push(AccountEvent::TransferReverted(TransferRevertedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(dec!(1.0)),
+            currency: Currency::default(),
+            rounding: None,
+        }));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::SendTransfer(SendTransferPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                to_client_id: ClientId("cl-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                clock: None,
+            }))
+            .then_expect_events(vec![]);
+    }
+
+    fn eur_deposited_events() -> Vec<AccountEvent> {
+        vec![AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(5.0)),
+            currency: Currency::new("EUR"),
+            rounding: None,
+            clock: None,
+        })]
+    }
+
+    #[test]
+    fn test_withdraw_other_currency() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(eur_deposited_events())
+            .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_error(AccountError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_dispute_in_transaction_currency() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(eur_deposited_events())
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(5.0)),
+                amount: None,
+                transaction_currency: Currency::new("EUR"),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(5.0))),
+                amount: Amount(dec!(5.0)),
+                currency: Currency::new("EUR"),
+                rounding: None,
+                payment_id: None,
+            })]);
+    }
+
+    #[test]
+    fn test_dispute_currency_mismatch() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(eur_deposited_events())
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(5.0)),
+                amount: None,
+                transaction_currency: Currency::new("EUR"),
+                currency: Some(Currency::default()),
+                payment_id: None,
+            }))
+            .then_expect_error(AccountError::CurrencyMismatch);
+    }
+
+    #[test]
+    fn test_resolve_currency_mismatch() {
+        let mut events = eur_deposited_events();
+        events.push(AccountEvent::FundsDisputed(FundsDisputedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            transaction_amount: Some(Amount(dec!(5.0))),
+            amount: Amount(dec!(5.0)),
+            currency: Currency::new("EUR"),
+            rounding: None,
+            payment_id: None,
+        }));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::ResolveDispute(ResolveDisputePayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: None,
+                currency: Some(Currency::default()),
+                payment_id: None,
+            }))
+            .then_expect_error(AccountError::CurrencyMismatch);
+    }
+
+    fn half_even_cents() -> AccountServices {
+        AccountServices {
+            precision: PrecisionPolicy::default().with_currency(
+                Currency::new("EUR"),
+                PrecisionRule {
+                    minor_units: 2,
+                    rounding: RoundingMode::HalfEven,
+                },
+            ),
+            ..AccountServices::default()
+        }
+    }
+
+    #[test]
+    fn test_deposit_rounded_amount() {
+        AccountTestFramework::with(half_even_cents())
+            .given_no_previous_events()
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(5.125)),
+                currency: Currency::new("EUR"),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_events(vec![AccountEvent::AccountDeposited(
+                AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(5.12)),
+                    currency: Currency::new("EUR"),
+                    rounding: Some(Rounding {
+                        requested: Amount(dec!(5.125)),
+                        minor_units: 2,
+                        mode: RoundingMode::HalfEven,
+                    }),
+                    clock: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_deposit_overscale_amount_other_currency() {
+        AccountTestFramework::with(half_even_cents())
+            .given_no_previous_events()
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(0.12345)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_error(AccountError::IllegalAmount);
+    }
+
+    #[test]
+    fn test_dispute_rounded_transaction() {
+        AccountTestFramework::with(half_even_cents())
+            .given(vec![AccountEvent::AccountDeposited(
+                AccountDepositedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(5.12)),
+                    currency: Currency::new("EUR"),
+                    rounding: None,
+                    clock: None,
+                },
+            )])
+            .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Amount(dec!(5.125)),
+                amount: None,
+                transaction_currency: Currency::new("EUR"),
+                currency: None,
+                payment_id: None,
+            }))
+            .then_expect_events(vec![AccountEvent::FundsDisputed(FundsDisputedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                transaction_amount: Some(Amount(dec!(5.12))),
+                amount: Amount(dec!(5.12)),
+                currency: Currency::new("EUR"),
+                rounding: None,
+                payment_id: None,
+            })]);
+    }
+
+    fn deposited_events(amount: Amount) -> Vec<AccountEvent> {
+        vec![AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount,
+            currency: Currency::default(),
+            rounding: None,
+            clock: None,
+        })]
+    }
+
+    #[test]
+    fn test_withdraw_with_fee() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(deposited_events(Amount(dec!(10.0))))
+            .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(9.0)),
+                currency: Currency::default(),
+                fee: Some(Amount(dec!(0.5))),
+                clock: None,
+            }))
+            .then_expect_events(vec![
+                AccountEvent::AccountWithdrawn(AccountWithdrawnPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(9.0)),
+                    currency: Currency::default(),
+                    rounding: None,
+                    clock: None,
+                }),
+                AccountEvent::FeeCharged(FeeChargedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    kind: FeeKind::Withdrawal,
+                    amount: Amount(dec!(0.5)),
+                    currency: Currency::default(),
+                    rounding: None,
+                }),
+            ]);
+    }
+
+    #[test]
+    fn test_withdraw_fee_insufficient_funds() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(deposited_events(Amount(dec!(10.0))))
+            .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(10.0)),
+                currency: Currency::default(),
+                fee: Some(Amount(dec!(0.5))),
+                clock: None,
+            }))
+            .then_expect_error(AccountError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_deposit_fee_larger_than_deposit() {
+        AccountTestFramework::with(AccountServices::default())
+            .given_no_previous_events()
+            .when(AccountCommand::DepositAccount(DepositAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(0.5)),
+                currency: Currency::default(),
+                fee: Some(Amount(dec!(1.0))),
+                clock: None,
+            }))
+            .then_expect_error(AccountError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_chargeback_fee_becomes_debt() {
+        let mut events = deposited_events(Amount(dec!(10.0)));
+        events.push(AccountEvent::FundsDisputed(FundsDisputedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            transaction_amount: Some(Amount(dec!(10.0))),
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            rounding: None,
+            payment_id: None,
+        }));
+        let chargedback = AccountEvent::DisputeChargedback(DisputeChargedbackPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            rounding: None,
+            payment_id: None,
+        });
+        let fee = AccountEvent::FeeCharged(FeeChargedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            kind: FeeKind::Chargeback,
+            amount: Amount(dec!(15)),
+            currency: Currency::default(),
+            rounding: None,
+        });
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events.clone())
+            .when(AccountCommand::ChargebackDispute(
+                ChargebackDisputePayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: Some(Amount(dec!(15))),
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![chargedback.clone(), fee.clone()]);
+
+        let mut account = Account::default();
+        events.extend([chargedback, fee]);
+        for event in events {
+            account.apply(event);
+        }
+        let balance = account.balance(&Currency::default());
+        assert_eq!(balance.available, dec!(-15));
+        assert_eq!(balance.debt, dec!(15));
+    }
+
+    fn authorized_events() -> Vec<AccountEvent> {
+        let mut events = deposited_events(Amount(dec!(10.0)));
+        events.push(AccountEvent::FundsAuthorized(FundsAuthorizedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            amount: Amount(dec!(4.0)),
+            currency: Currency::default(),
+            expiry: Expiry {
+                after_position: Some(5),
+                at_time: None,
+            },
+            rounding: None,
+        }));
+        events
+    }
+
+    #[test]
+    fn test_authorize_funds() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(deposited_events(Amount(dec!(10.0))))
+            .when(AccountCommand::AuthorizeFunds(AuthorizeFundsPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(4.0)),
+                currency: Currency::default(),
+                expiry: Expiry {
+                    after_position: Some(5),
+                    at_time: None,
+                },
+            }))
+            .then_expect_events(authorized_events()[1..].to_vec());
+    }
+
+    #[test]
+    fn test_withdraw_authorized_funds() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(authorized_events())
+            .when(AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-3".to_owned()),
+                amount: Amount(dec!(7.0)),
+                currency: Currency::default(),
+                fee: None,
+                clock: None,
+            }))
+            .then_expect_error(AccountError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_capture_part_of_authorization() {
+        let captured = AccountEvent::AuthorizationCaptured(AuthorizationCapturedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            amount: Amount(dec!(2.5)),
+            released: Amount(dec!(1.5)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: None,
+            payment_id: None,
+        });
+        let fee = AccountEvent::FeeCharged(FeeChargedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            kind: FeeKind::Withdrawal,
+            amount: Amount(dec!(0.5)),
+            currency: Currency::default(),
+            rounding: None,
+        });
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(authorized_events())
+            .when(AccountCommand::CaptureAuthorization(
+                CaptureAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Some(Amount(dec!(2.5))),
+                    currency: None,
+                    fee: Some(Amount(dec!(0.5))),
+                    clock: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![captured.clone(), fee.clone()]);
+
+        let mut account = Account::default();
+        for event in authorized_events().into_iter().chain([captured, fee]) {
+            account.apply(event);
+        }
+        let balance = account.balance(&Currency::default());
+        assert_eq!(balance.available, dec!(7.0));
+        assert_eq!(balance.authorized, dec!(0.0));
+    }
+
+    #[test]
+    fn test_capture_more_than_authorized() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(authorized_events())
+            .when(AccountCommand::CaptureAuthorization(
+                CaptureAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Some(Amount(dec!(4.5))),
+                    currency: None,
+                    fee: None,
+                    clock: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_error(AccountError::CaptureAmountExceeded);
+    }
+
+    #[test]
+    fn test_capture_not_authorized() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(deposited_events(Amount(dec!(10.0))))
+            .when(AccountCommand::CaptureAuthorization(
+                CaptureAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    clock: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_error(AccountError::AuthorizationNotFound);
+    }
+
+    #[test]
+    fn test_capture_retried_by_payment() {
+        let mut events = authorized_events();
+        events.push(AccountEvent::AuthorizationCaptured(
+            AuthorizationCapturedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(4.0)),
+                released: Amount(dec!(0.0)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: None,
+                payment_id: Some("tx-2:Capture:3".to_owned()),
+            },
+        ));
+
+        // The authorization is gone by now, the retried step is still taken
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::CaptureAuthorization(
+                CaptureAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    clock: None,
+                    payment_id: Some("tx-2:Capture:3".to_owned()),
+                },
+            ))
+            .then_expect_events(vec![]);
+    }
+
+    #[test]
+    fn test_void_authorization() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(authorized_events())
+            .when(AccountCommand::VoidAuthorization(
+                VoidAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![AccountEvent::AuthorizationVoided(
+                AuthorizationVoidedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(4.0)),
+                    currency: Currency::default(),
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    /// Authorized funds with the rest of the deposit disputed, 4.0 short of it.
+    fn indebted_authorized_events() -> Vec<AccountEvent> {
+        let mut events = authorized_events();
+        events.push(AccountEvent::FundsDisputed(FundsDisputedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            transaction_amount: None,
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            rounding: None,
+            payment_id: None,
+        }));
+        events
+    }
+
+    #[test]
+    fn test_void_authorization_repays_debt() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(indebted_authorized_events())
+            .when(AccountCommand::VoidAuthorization(
+                VoidAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![
+                AccountEvent::AuthorizationVoided(AuthorizationVoidedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(4.0)),
+                    currency: Currency::default(),
+                    payment_id: None,
+                }),
+                AccountEvent::DebtRepaid(DebtRepaidPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(4.0)),
+                    currency: Currency::default(),
+                }),
+            ]);
+    }
+
+    fn expire(position: u64) -> AccountCommand {
+        AccountCommand::ExpireAuthorization(ExpireAuthorizationPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            clock: InputClock {
+                position,
+                time: None,
+            },
+        })
+    }
+
+    #[test]
+    fn test_expire_authorization() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(authorized_events())
+            .when(expire(5))
+            .then_expect_events(vec![]);
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(authorized_events())
+            .when(expire(6))
+            .then_expect_events(vec![AccountEvent::AuthorizationExpired(
+                AuthorizationExpiredPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(4.0)),
+                    currency: Currency::default(),
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_expire_authorization_repays_debt() {
+        AccountTestFramework::with(AccountServices::default())
+            .given(indebted_authorized_events())
+            .when(expire(6))
+            .then_expect_events(vec![
+                AccountEvent::AuthorizationExpired(AuthorizationExpiredPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(4.0)),
+                    currency: Currency::default(),
+                }),
+                AccountEvent::DebtRepaid(DebtRepaidPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: Amount(dec!(4.0)),
+                    currency: Currency::default(),
+                }),
+            ]);
+    }
+
+    #[test]
+    fn test_capture_expired_authorization() {
+        let mut events = authorized_events();
+        events.push(AccountEvent::AuthorizationExpired(
+            AuthorizationExpiredPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(4.0)),
+                currency: Currency::default(),
+            },
+        ));
+
+        AccountTestFramework::with(AccountServices::default())
+            .given(events)
+            .when(AccountCommand::CaptureAuthorization(
+                CaptureAuthorizationPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    amount: None,
+                    currency: None,
+                    fee: None,
+                    clock: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_error(AccountError::AuthorizationExpired);
+    }
+
+    #[test]
+    fn test_collect_fee() {
+        let collected = AccountEvent::FeeCollected(FeeCollectedPayload {
+            client_id: ClientId(HOUSE_CLIENT_ID.to_owned()),
+            from_client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            kind: FeeKind::Deposit,
+            amount: Amount(dec!(0.5)),
+            currency: Currency::default(),
+            payment_id: "tx-1".to_owned(),
+        });
+        let collect = AccountCommand::CollectFee(CollectFeePayload {
+            client_id: ClientId(HOUSE_CLIENT_ID.to_owned()),
+            from_client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            kind: FeeKind::Deposit,
+            amount: Amount(dec!(0.5)),
+            currency: Currency::default(),
+            payment_id: "tx-1".to_owned(),
+        });
+
+        AccountTestFramework::with(AccountServices::default())
+            .given_no_previous_events()
+            .when(collect.clone())
+            .then_expect_events(vec![collected.clone()]);
+        AccountTestFramework::with(AccountServices::default())
+            .given(vec![collected])
+            .when(collect)
+            .then_expect_events(vec![]);
+    }
+
+    fn limited(limits: WithdrawalLimits) -> AccountServices {
+        AccountServices {
+            limits: LimitPolicy {
+                default: limits,
+                ..LimitPolicy::default()
+            },
+            ..AccountServices::default()
+        }
+    }
+
+    fn withdraw_at(tx_id: &str, amount: Decimal, position: u64) -> AccountCommand {
+        AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId(tx_id.to_owned()),
+            amount: Amount(amount),
+            currency: Currency::default(),
+            fee: None,
+            clock: Some(InputClock {
+                position,
+                time: None,
+            }),
+        })
+    }
+
+    fn withdrawn_at(tx_id: &str, amount: Decimal, position: u64) -> AccountEvent {
+        AccountEvent::AccountWithdrawn(AccountWithdrawnPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId(tx_id.to_owned()),
+            amount: Amount(amount),
+            currency: Currency::default(),
+            rounding: None,
+            clock: Some(InputClock {
+                position,
+                time: None,
+            }),
+        })
+    }
+
+    fn deposited_ten() -> AccountEvent {
+        AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: None,
+        })
+    }
+
+    #[test]
+    fn test_withdraw_above_max_amount() {
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            max_amount: Some(dec!(5)),
+            ..WithdrawalLimits::default()
+        }))
+        .given(vec![deposited_ten()])
+        .when(withdraw_at("tx-2", dec!(5.01), 2))
+        .then_expect_error(AccountError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_withdraw_above_window_amount() {
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            window: Some(LimitWindow::Rows(3)),
+            max_window_amount: Some(dec!(5)),
+            ..WithdrawalLimits::default()
+        }))
+        .given(vec![deposited_ten(), withdrawn_at("tx-2", dec!(3), 2)])
+        .when(withdraw_at("tx-3", dec!(2.5), 4))
+        .then_expect_error(AccountError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_withdraw_above_window_count() {
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            max_window_count: Some(2),
+            ..WithdrawalLimits::default()
+        }))
+        .given(vec![
+            deposited_ten(),
+            withdrawn_at("tx-2", dec!(1), 2),
+            withdrawn_at("tx-3", dec!(1), 30),
+        ])
+        .when(withdraw_at("tx-4", dec!(1), 90))
+        .then_expect_error(AccountError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_withdraw_after_window_slides() {
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            max_amount: Some(dec!(5)),
+            window: Some(LimitWindow::Rows(3)),
+            max_window_amount: Some(dec!(5)),
+            max_window_count: Some(1),
+        }))
+        .given(vec![deposited_ten(), withdrawn_at("tx-2", dec!(3), 2)])
+        .when(withdraw_at("tx-3", dec!(2.5), 5))
+        .then_expect_events(vec![withdrawn_at("tx-3", dec!(2.5), 5)]);
+    }
+
+    fn capture_at(position: u64) -> AccountCommand {
+        AccountCommand::CaptureAuthorization(CaptureAuthorizationPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            amount: None,
+            currency: None,
+            fee: None,
+            clock: Some(InputClock {
+                position,
+                time: None,
+            }),
+            payment_id: None,
+        })
+    }
+
+    #[test]
+    fn test_capture_above_max_amount() {
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            max_amount: Some(dec!(3)),
+            ..WithdrawalLimits::default()
+        }))
+        .given(authorized_events())
+        .when(capture_at(3))
+        .then_expect_error(AccountError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_withdraw_above_window_amount_with_capture() {
+        let mut events = authorized_events();
+        events.push(AccountEvent::AuthorizationCaptured(
+            AuthorizationCapturedPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                transaction_id: TransactionId("tx-2".to_owned()),
+                amount: Amount(dec!(4.0)),
+                released: Amount(dec!(0.0)),
+                currency: Currency::default(),
+                rounding: None,
+                clock: Some(InputClock {
+                    position: 3,
+                    time: None,
+                }),
+                payment_id: None,
+            },
+        ));
+
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            window: Some(LimitWindow::Rows(3)),
+            max_window_amount: Some(dec!(5)),
+            ..WithdrawalLimits::default()
+        }))
+        .given(events)
+        .when(withdraw_at("tx-3", dec!(2), 4))
+        .then_expect_error(AccountError::LimitExceeded);
+    }
+
+    fn transfer_at(amount: Decimal, position: u64) -> AccountCommand {
+        AccountCommand::SendTransfer(SendTransferPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(amount),
+            currency: Currency::default(),
+            clock: Some(InputClock {
+                position,
+                time: None,
+            }),
+        })
+    }
+
+    fn transfer_sent_at(amount: Decimal, position: u64) -> AccountEvent {
+        AccountEvent::TransferSent(TransferSentPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-2".to_owned()),
+            to_client_id: ClientId("cl-2".to_owned()),
+            amount: Amount(amount),
+            currency: Currency::default(),
+            rounding: None,
+            clock: Some(InputClock {
+                position,
+                time: None,
+            }),
+        })
+    }
+
+    #[test]
+    fn test_transfer_above_max_amount() {
+        AccountTestFramework::with(limited(WithdrawalLimits {
+            max_amount: Some(dec!(5)),
+            ..WithdrawalLimits::default()
+        }))
+        .given(vec![deposited_ten()])
+        .when(transfer_at(dec!(5.01), 2))
+        .then_expect_error(AccountError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_withdraw_above_window_amount_with_transfer() {
+        let limits = WithdrawalLimits {
+            window: Some(LimitWindow::Rows(3)),
+            max_window_amount: Some(dec!(5)),
+            ..WithdrawalLimits::default()
+        };
+
+        AccountTestFramework::with(limited(limits.clone()))
+            .given(vec![deposited_ten(), transfer_sent_at(dec!(3), 2)])
+            .when(withdraw_at("tx-3", dec!(2.5), 4))
+            .then_expect_error(AccountError::LimitExceeded);
+
+        // A reverted transfer no longer counts
+        AccountTestFramework::with(limited(limits))
+            .given(vec![
+                deposited_ten(),
+                transfer_sent_at(dec!(3), 2),
+                AccountEvent::TransferReverted(TransferRevertedPayload {
+                    client_id: ClientId("cl-1".to_owned()),
+                    transaction_id: TransactionId("tx-2".to_owned()),
+                    to_client_id: ClientId("cl-2".to_owned()),
+                    amount: Amount(dec!(3)),
+                    currency: Currency::default(),
+                    rounding: None,
+                }),
+            ])
+            .when(withdraw_at("tx-3", dec!(2.5), 4))
+            .then_expect_events(vec![withdrawn_at("tx-3", dec!(2.5), 4)]);
+    }
+
+    fn screened(rules: &str) -> AccountServices {
+        AccountServices {
+            risk: Arc::new(serde_json::from_str::<RiskRules>(rules).unwrap()),
+            ..AccountServices::default()
+        }
+    }
+
+    #[test]
+    fn test_deposit_flagged_and_held() {
+        let deposited = AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: Some(InputClock {
+                position: 1,
+                time: None,
+            }),
+        });
+
+        AccountTestFramework::with(screened(
+            r#"{"thresholds": [{"operation": "deposit", "above": 5, "decision": "flag_and_hold"}]}"#,
+        ))
+        .given_no_previous_events()
+        .when(AccountCommand::DepositAccount(DepositAccountPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            fee: None,
+            clock: Some(InputClock {
+                position: 1,
+                time: None,
+            }),
+        }))
+        .then_expect_events(vec![
+            deposited,
+            AccountEvent::AccountFrozen(AccountFrozenPayload {
+                client_id: ClientId("cl-1".to_owned()),
+                reason: HOLD_REASON.to_owned(),
+                operator_id: SCREENING_OPERATOR.to_owned(),
+            }),
+        ]);
+    }
+
+    #[test]
+    fn test_withdraw_soon_after_deposit_held() {
+        let deposited = AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(10.0)),
+            currency: Currency::default(),
+            rounding: None,
+            clock: Some(InputClock {
+                position: 1,
+                time: None,
+            }),
+        });
+        let rules = r#"{
+            "deposit_then_withdrawal": {
+                "window": {"rows": 5}, "min_share": 0.5, "decision": "flag_and_hold"
+            }
+        }"#;
+
+        AccountTestFramework::with(screened(rules))
+            .given(vec![deposited.clone()])
+            .when(withdraw_at("tx-2", dec!(6), 3))
+            .then_expect_error(AccountError::RiskHeld);
+        AccountTestFramework::with(screened(rules))
+            .given(vec![deposited])
+            .when(withdraw_at("tx-2", dec!(6), 6))
+            .then_expect_events(vec![withdrawn_at("tx-2", dec!(6), 6)]);
+    }
+
+    #[test]
+    fn test_capture_screened_as_withdrawal() {
+        AccountTestFramework::with(screened(r#"{"blocked_clients": ["cl-1"]}"#))
+            .given(authorized_events())
+            .when(capture_at(3))
+            .then_expect_error(AccountError::RiskRejected);
+        AccountTestFramework::with(screened(
+            r#"{"thresholds": [{"operation": "withdrawal", "above": 3, "decision": "flag_and_hold"}]}"#,
+        ))
+        .given(authorized_events())
+        .when(capture_at(3))
+        .then_expect_error(AccountError::RiskHeld);
+    }
+
+    #[test]
+    fn test_transfer_screened_as_withdrawal() {
+        AccountTestFramework::with(screened(r#"{"blocked_clients": ["cl-1"]}"#))
+            .given(vec![deposited_ten()])
+            .when(transfer_at(dec!(4), 2))
+            .then_expect_error(AccountError::RiskRejected);
+        AccountTestFramework::with(screened(
+            r#"{"thresholds": [{"operation": "withdrawal", "above": 3, "decision": "flag_and_hold"}]}"#,
+        ))
+        .given(vec![deposited_ten()])
+        .when(transfer_at(dec!(4), 2))
+        .then_expect_error(AccountError::RiskHeld);
+    }
+
+    struct RejectDisputes;
+
+    impl RiskScreen for RejectDisputes {
+        fn screen(&self, screening: &Screening) -> RiskDecision {
+            if screening.operation == ScreenedOperation::Dispute {
+                RiskDecision::Reject
+            } else {
+                RiskDecision::Approve
+            }
+        }
+    }
+
+    #[test]
+    fn test_dispute_rejected_by_screen() {
+        AccountTestFramework::with(AccountServices {
+            risk: Arc::new(RejectDisputes),
+            ..AccountServices::default()
+        })
+        .given(vec![deposited_ten()])
+        .when(AccountCommand::DisputeFunds(DisputeFundsPayload {
+            client_id: ClientId("cl-1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            transaction_amount: Amount(dec!(10.0)),
+            amount: None,
+            transaction_currency: Currency::default(),
+            currency: None,
+            payment_id: None,
+        }))
+        .then_expect_error(AccountError::RiskRejected);
+    }
 }
@@ -1,14 +1,29 @@
 use serde::Deserialize;
 
-use crate::domain::props::{Amount, ClientId, TransactionId};
+use crate::domain::{
+    fee::FeeKind,
+    props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId},
+};
 
 #[derive(Debug, Clone, Deserialize)]
 pub enum AccountCommand {
     DepositAccount(DepositAccountPayload),
     WithdrawAccount(WithdrawAccountPayload),
     DisputeFunds(DisputeFundsPayload),
+    DisputeWithdrawal(DisputeWithdrawalPayload),
     ResolveDispute(ResolveDisputePayload),
     ChargebackDispute(ChargebackDisputePayload),
+    LockAccount(LockAccountPayload),
+    UnlockAccount(UnlockAccountPayload),
+    FreezeAccount(FreezeAccountPayload),
+    SendTransfer(SendTransferPayload),
+    ReceiveTransfer(ReceiveTransferPayload),
+    RevertTransfer(RevertTransferPayload),
+    AuthorizeFunds(AuthorizeFundsPayload),
+    CaptureAuthorization(CaptureAuthorizationPayload),
+    VoidAuthorization(VoidAuthorizationPayload),
+    ExpireAuthorization(ExpireAuthorizationPayload),
+    CollectFee(CollectFeePayload),
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -16,6 +31,11 @@ pub struct DepositAccountPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Amount,
+    pub currency: Currency,
+    /// Fee charged together with the command
+    pub fee: Option<Amount>,
+    /// Clock of the row, risk screening looks back from it
+    pub clock: Option<InputClock>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -23,23 +43,171 @@ pub struct WithdrawAccountPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Amount,
+    pub currency: Currency,
+    /// Fee charged together with the command
+    pub fee: Option<Amount>,
+    /// Clock of the row, withdrawal limits look back from it
+    pub clock: Option<InputClock>,
 }
 
+/// Disputes the given part of the transaction, or all of it which is not disputed yet.
 #[derive(Debug, Clone, Deserialize)]
 pub struct DisputeFundsPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
-    pub amount: Amount,
+    pub transaction_amount: Amount,
+    pub amount: Option<Amount>,
+    pub transaction_currency: Currency,
+    /// Currency named by the dispute, it has to be the one of the transaction
+    pub currency: Option<Currency>,
+    /// Payment taking the step, a retried step of the same payment is not applied again
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DisputeWithdrawalPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub transaction_amount: Amount,
+    pub amount: Option<Amount>,
+    pub transaction_currency: Currency,
+    /// Currency named by the dispute, it has to be the one of the transaction
+    pub currency: Option<Currency>,
+    pub payment_id: Option<String>,
 }
 
+/// Resolves the given part of the held amount, or all of it.
 #[derive(Debug, Clone, Deserialize)]
 pub struct ResolveDisputePayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
+    pub amount: Option<Amount>,
+    /// Currency named by the row, it has to be the one of the dispute
+    pub currency: Option<Currency>,
+    pub payment_id: Option<String>,
 }
 
+/// Charges back the given part of the held amount, or all of it.
 #[derive(Debug, Clone, Deserialize)]
 pub struct ChargebackDisputePayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
+    pub amount: Option<Amount>,
+    /// Currency named by the row, it has to be the one of the dispute
+    pub currency: Option<Currency>,
+    /// Fee charged together with the chargeback of a deposit
+    pub fee: Option<Amount>,
+    pub payment_id: Option<String>,
+}
+
+/// Administrative lock, blocking all transactions of the account.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LockAccountPayload {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub operator_id: String,
+}
+
+/// Lifts a lock (also one set by a chargeback) or a freeze.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnlockAccountPayload {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub operator_id: String,
+}
+
+/// Administrative freeze, blocking only funds leaving the account.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FreezeAccountPayload {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub operator_id: String,
+}
+
+/// Debit side of a transfer to another client.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SendTransferPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub to_client_id: ClientId,
+    pub amount: Amount,
+    pub currency: Currency,
+    /// Clock of the row, withdrawal limits look back from it
+    pub clock: Option<InputClock>,
+}
+
+/// Credit side of a transfer from another client.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReceiveTransferPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub from_client_id: ClientId,
+    pub amount: Amount,
+    pub currency: Currency,
+}
+
+/// Gives a sent transfer back to the sender, when it could not be credited.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RevertTransferPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub to_client_id: ClientId,
+    pub amount: Amount,
+    pub currency: Currency,
+}
+
+/// Reserves funds until the authorization is captured, voided or expires.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorizeFundsPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub expiry: Expiry,
+}
+
+/// Withdraws the given part of the authorized funds, or all of them, the rest is released.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CaptureAuthorizationPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Option<Amount>,
+    /// Currency named by the row, it has to be the one of the authorization
+    pub currency: Option<Currency>,
+    /// Withdrawal fee charged together with the capture
+    pub fee: Option<Amount>,
+    /// Clock of the row, withdrawal limits look back from it
+    pub clock: Option<InputClock>,
+    /// Payment of the capture, a retried step of it is not applied again
+    pub payment_id: Option<String>,
+}
+
+/// Releases the authorized funds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoidAuthorizationPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub payment_id: Option<String>,
+}
+
+/// Releases the authorized funds, when the authorization is due by the clock.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpireAuthorizationPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub clock: InputClock,
+}
+
+/// Credits the house account with a fee charged to a client.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectFeePayload {
+    pub client_id: ClientId,
+    /// Client the fee was charged to
+    pub from_client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub kind: FeeKind,
+    pub amount: Amount,
+    pub currency: Currency,
+    /// Payment the fee was charged by, a retried step of it is not applied again
+    pub payment_id: String,
 }
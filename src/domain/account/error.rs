@@ -7,6 +7,39 @@ pub enum AccountError {
     AccountLocked,
     DisputeNotFound,
     DuplicateDispute,
+    DisputeAmountExceeded,
+    AccountFrozen,
+    AccountNotLocked,
+    CurrencyMismatch,
+    AuthorizationNotFound,
+    AuthorizationExpired,
+    CaptureAmountExceeded,
+    LimitExceeded,
+    RiskRejected,
+    RiskHeld,
+}
+
+impl AccountError {
+    /// Machine readable reason, used in rejection reports.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountError::InsufficientFunds => "insufficient_funds",
+            AccountError::IllegalAmount => "illegal_amount",
+            AccountError::AccountLocked => "account_locked",
+            AccountError::DisputeNotFound => "dispute_not_found",
+            AccountError::DuplicateDispute => "duplicate_dispute",
+            AccountError::DisputeAmountExceeded => "dispute_amount_exceeded",
+            AccountError::AccountFrozen => "account_frozen",
+            AccountError::AccountNotLocked => "account_not_locked",
+            AccountError::CurrencyMismatch => "currency_mismatch",
+            AccountError::AuthorizationNotFound => "authorization_not_found",
+            AccountError::AuthorizationExpired => "authorization_expired",
+            AccountError::CaptureAmountExceeded => "capture_amount_exceeded",
+            AccountError::LimitExceeded => "limit_exceeded",
+            AccountError::RiskRejected => "risk_rejected",
+            AccountError::RiskHeld => "risk_held",
+        }
+    }
 }
 
 impl std::error::Error for AccountError {}
@@ -1,8 +1,15 @@
 use cqrs_es::DomainEvent;
 use serde::{Deserialize, Serialize};
 
-use crate::domain::props::{Amount, ClientId, TransactionId};
+use crate::domain::{
+    account::precision::Rounding,
+    fee::FeeKind,
+    props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId},
+};
 
+// Payloads moving funds carry their currency, which events recorded before multi-currency accounts
+// do not have, so it defaults for those. Amounts are the ones moved, with the rounding of an over-precise
+// requested amount next to them.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum AccountEvent {
     AccountDeposited(AccountDepositedPayload),
@@ -10,6 +17,22 @@ pub enum AccountEvent {
     FundsDisputed(FundsDisputedPayload),
     DisputeResolved(DisputeResolvedPayload),
     DisputeChargedback(DisputeChargedbackPayload),
+    WithdrawalDisputed(WithdrawalDisputedPayload),
+    WithdrawalDisputeResolved(WithdrawalDisputeResolvedPayload),
+    WithdrawalDisputeChargedback(WithdrawalDisputeChargedbackPayload),
+    DebtRepaid(DebtRepaidPayload),
+    AccountLocked(AccountLockedPayload),
+    AccountUnlocked(AccountUnlockedPayload),
+    AccountFrozen(AccountFrozenPayload),
+    TransferSent(TransferSentPayload),
+    TransferReceived(TransferReceivedPayload),
+    TransferReverted(TransferRevertedPayload),
+    FeeCharged(FeeChargedPayload),
+    FundsAuthorized(FundsAuthorizedPayload),
+    AuthorizationCaptured(AuthorizationCapturedPayload),
+    AuthorizationVoided(AuthorizationVoidedPayload),
+    AuthorizationExpired(AuthorizationExpiredPayload),
+    FeeCollected(FeeCollectedPayload),
 }
 
 impl DomainEvent for AccountEvent {
@@ -20,6 +43,22 @@ impl DomainEvent for AccountEvent {
             AccountEvent::FundsDisputed(_) => "FundsDisputed",
             AccountEvent::DisputeResolved(_) => "DisputeResolved",
             AccountEvent::DisputeChargedback(_) => "DisputeChargedback",
+            AccountEvent::WithdrawalDisputed(_) => "WithdrawalDisputed",
+            AccountEvent::WithdrawalDisputeResolved(_) => "WithdrawalDisputeResolved",
+            AccountEvent::WithdrawalDisputeChargedback(_) => "WithdrawalDisputeChargedback",
+            AccountEvent::DebtRepaid(_) => "DebtRepaid",
+            AccountEvent::AccountLocked(_) => "AccountLocked",
+            AccountEvent::AccountUnlocked(_) => "AccountUnlocked",
+            AccountEvent::AccountFrozen(_) => "AccountFrozen",
+            AccountEvent::TransferSent(_) => "TransferSent",
+            AccountEvent::TransferReceived(_) => "TransferReceived",
+            AccountEvent::TransferReverted(_) => "TransferReverted",
+            AccountEvent::FeeCharged(_) => "FeeCharged",
+            AccountEvent::FundsAuthorized(_) => "FundsAuthorized",
+            AccountEvent::AuthorizationCaptured(_) => "AuthorizationCaptured",
+            AccountEvent::AuthorizationVoided(_) => "AuthorizationVoided",
+            AccountEvent::AuthorizationExpired(_) => "AuthorizationExpired",
+            AccountEvent::FeeCollected(_) => "FeeCollected",
         };
         event_type.to_string()
     }
@@ -34,6 +73,13 @@ pub struct AccountDepositedPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    /// When the deposit was made, deposits without it are not screened against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<InputClock>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -41,13 +87,30 @@ pub struct AccountWithdrawnPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    /// When the withdrawal was made, withdrawals without it do not count towards window limits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<InputClock>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FundsDisputedPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
+    /// Amount of the disputed transaction, missing in events recorded before partial disputes
+    #[serde(default)]
+    pub transaction_amount: Option<Amount>,
     pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    /// Payment which made the change, missing in events recorded before disputes ran as payments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -55,6 +118,12 @@ pub struct DisputeResolvedPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -62,4 +131,202 @@ pub struct DisputeChargedbackPayload {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+/// Withdrawn amount provisionally credited back to the client and held until the dispute ends.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WithdrawalDisputedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    #[serde(default)]
+    pub transaction_amount: Option<Amount>,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+/// Withdrawal stands, the provisional credit is reversed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WithdrawalDisputeResolvedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+/// Withdrawal is reversed, the provisional credit becomes available to the client.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WithdrawalDisputeChargedbackPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+/// Part of a credit which went to the debt left by a dispute, recorded right after the credit itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DebtRepaidPayload {
+    pub client_id: ClientId,
+    /// Transaction which brought the funds
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AccountLockedPayload {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub operator_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AccountUnlockedPayload {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub operator_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AccountFrozenPayload {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub operator_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransferSentPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub to_client_id: ClientId,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    /// When the transfer was sent, it counts towards window limits like a withdrawal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<InputClock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransferReceivedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub from_client_id: ClientId,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransferRevertedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub to_client_id: ClientId,
+    pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+}
+
+/// Fee taken from available funds for the house, recorded right after the event it is charged for.
+/// The house account collects it in a later step of the payment (`FeeCollected`).
+/// A chargeback fee is charged even without enough available funds, the shortfall becomes debt.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeeChargedPayload {
+    pub client_id: ClientId,
+    /// Transaction the fee is charged for
+    pub transaction_id: TransactionId,
+    pub kind: FeeKind,
+    pub amount: Amount,
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+}
+
+/// Fee charged to a client, credited to the available funds of the house account.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeeCollectedPayload {
+    pub client_id: ClientId,
+    /// Client the fee was charged to
+    pub from_client_id: ClientId,
+    /// Transaction the fee was charged for
+    pub transaction_id: TransactionId,
+    pub kind: FeeKind,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub payment_id: String,
+}
+
+/// Available funds moved to the authorized ones, separate from funds held by disputes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FundsAuthorizedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub expiry: Expiry,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+}
+
+/// Captured part of the authorized funds leaves the account, the released rest becomes available again.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthorizationCapturedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    pub released: Amount,
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rounding: Option<Rounding>,
+    /// When the capture was made, captures without it do not count towards window limits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<InputClock>,
+    /// Missing in events recorded before captures ran as payments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthorizationVoidedPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    pub currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthorizationExpiredPayload {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub amount: Amount,
+    pub currency: Currency,
 }
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, fs};
+
+use color_eyre::eyre::{Result, eyre};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::domain::props::{ClientId, InputClock};
+
+/// Latest withdrawals cumulative limits apply to, the current one included.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitWindow {
+    /// Input rows up to the current one
+    Rows(u64),
+    /// Seconds up to the time of the current row
+    Seconds(i64),
+}
+
+impl LimitWindow {
+    /// Whether a withdrawal made at the clock is still in the window, it is when there is no time to compare.
+    pub fn contains(&self, at: &InputClock, now: &InputClock) -> bool {
+        match self {
+            LimitWindow::Rows(rows) => now.position.saturating_sub(at.position) < *rows,
+            LimitWindow::Seconds(seconds) => match (at.time, now.time) {
+                (Some(at), Some(now)) => now - at < *seconds,
+                _ => true,
+            },
+        }
+    }
+}
+
+/// Limits of the withdrawals of a client, in the currency of each withdrawal.
+/// Without a window, the cumulative limits apply to all of the client's withdrawals.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WithdrawalLimits {
+    /// Largest single withdrawal
+    #[serde(default)]
+    pub max_amount: Option<Decimal>,
+    #[serde(default)]
+    pub window: Option<LimitWindow>,
+    /// Largest total withdrawn within the window
+    #[serde(default)]
+    pub max_window_amount: Option<Decimal>,
+    /// Most withdrawals within the window
+    #[serde(default)]
+    pub max_window_count: Option<usize>,
+}
+
+impl WithdrawalLimits {
+    pub fn in_window(&self, at: &InputClock, now: &InputClock) -> bool {
+        self.window.is_none_or(|window| window.contains(at, now))
+    }
+}
+
+/// Withdrawal limits loaded from a JSON file, the default ones apply to clients without limits of their own, e.g.
+/// `{"default": {"max_amount": 1000}, "clients": {"7": {"window": {"rows": 100}, "max_window_count": 2}}}`.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LimitPolicy {
+    #[serde(default)]
+    pub default: WithdrawalLimits,
+    #[serde(default)]
+    pub clients: HashMap<String, WithdrawalLimits>,
+}
+
+impl LimitPolicy {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| eyre!("Could not read withdrawal limits: {}", e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| eyre!("Invalid withdrawal limits {}: {}", path, e))
+    }
+
+    pub fn limits(&self, client_id: &ClientId) -> &WithdrawalLimits {
+        self.clients
+            .get(client_id.as_str())
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use crate::domain::{
+        account::limits::{LimitPolicy, LimitWindow, WithdrawalLimits},
+        props::{ClientId, InputClock},
+    };
+
+    fn clock(position: u64, time: Option<i64>) -> InputClock {
+        InputClock { position, time }
+    }
+
+    #[test]
+    fn slides_windows() {
+        let rows = LimitWindow::Rows(3);
+        let seconds = LimitWindow::Seconds(60);
+
+        assert!(rows.contains(&clock(2, None), &clock(4, None)));
+        assert!(!rows.contains(&clock(1, None), &clock(4, None)));
+        assert!(seconds.contains(&clock(1, Some(100)), &clock(9, Some(159))));
+        assert!(!seconds.contains(&clock(1, Some(100)), &clock(2, Some(160))));
+        assert!(seconds.contains(&clock(1, None), &clock(2, Some(160))));
+    }
+
+    #[test]
+    fn falls_back_to_default_limits() {
+        let policy: LimitPolicy = serde_json::from_str(
+            r#"{
+                "default": {"max_amount": 100},
+                "clients": {"7": {"window": {"seconds": 86400}, "max_window_count": 2}}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy.limits(&ClientId("1".to_owned())).max_amount,
+            Some(dec!(100))
+        );
+        assert_eq!(
+            policy.limits(&ClientId("7".to_owned())),
+            &WithdrawalLimits {
+                max_amount: None,
+                window: Some(LimitWindow::Seconds(86400)),
+                max_window_amount: None,
+                max_window_count: Some(2),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_limits() {
+        assert!(serde_json::from_str::<LimitPolicy>(r#"{"default": {"max": 1}}"#).is_err());
+        assert!(
+            serde_json::from_str::<LimitPolicy>(r#"{"default": {"window": {"days": 1}}}"#).is_err()
+        );
+    }
+}
@@ -2,3 +2,6 @@ pub mod aggregate;
 pub mod command;
 pub mod error;
 pub mod event;
+pub mod limits;
+pub mod precision;
+pub mod risk;
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, str::FromStr};
+
+use clap::ValueEnum;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    account::error::AccountError,
+    props::{Amount, Currency},
+};
+
+/// Minor units of currencies without a rule of their own.
+pub const DEFAULT_MINOR_UNITS: u32 = 4;
+
+/// What happens to an amount with more decimal places than the minor units of its currency.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum RoundingMode {
+    /// Reject the command with `illegal_amount`
+    #[default]
+    Reject,
+    /// Round to the nearest minor unit, ties to even
+    HalfEven,
+    /// Drop the extra decimal places
+    Truncate,
+}
+
+/// Rounding applied to an over-precise amount, recorded in events for audit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Rounding {
+    /// Amount as given in the command
+    pub requested: Amount,
+    pub minor_units: u32,
+    pub mode: RoundingMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrecisionRule {
+    pub minor_units: u32,
+    pub rounding: RoundingMode,
+}
+
+impl Default for PrecisionRule {
+    fn default() -> Self {
+        PrecisionRule {
+            minor_units: DEFAULT_MINOR_UNITS,
+            rounding: RoundingMode::default(),
+        }
+    }
+}
+
+impl PrecisionRule {
+    /// Amount the command moves, along with the rounding when it differs from the requested one.
+    pub fn apply(&self, amount: Amount) -> Result<(Amount, Option<Rounding>), AccountError> {
+        if amount.0 <= Decimal::ZERO {
+            return Err(AccountError::IllegalAmount);
+        }
+        if amount.scale() <= self.minor_units {
+            return Ok((amount, None));
+        }
+
+        let rounded = match self.rounding {
+            RoundingMode::Reject => return Err(AccountError::IllegalAmount),
+            RoundingMode::HalfEven => amount
+                .round_dp_with_strategy(self.minor_units, RoundingStrategy::MidpointNearestEven),
+            RoundingMode::Truncate => {
+                amount.round_dp_with_strategy(self.minor_units, RoundingStrategy::ToZero)
+            }
+        };
+        // Nothing left to move, e.g. a truncated amount smaller than a minor unit
+        if rounded <= Decimal::ZERO {
+            return Err(AccountError::IllegalAmount);
+        }
+
+        Ok((
+            Amount(rounded),
+            Some(Rounding {
+                requested: amount,
+                minor_units: self.minor_units,
+                mode: self.rounding,
+            }),
+        ))
+    }
+}
+
+/// Precision rules per currency (or other asset), falling back to the default rule.
+#[derive(Clone, Debug, Default)]
+pub struct PrecisionPolicy {
+    default: PrecisionRule,
+    currencies: HashMap<Currency, PrecisionRule>,
+}
+
+impl PrecisionPolicy {
+    pub fn new(default: PrecisionRule) -> Self {
+        PrecisionPolicy {
+            default,
+            currencies: HashMap::new(),
+        }
+    }
+
+    pub fn with_currency(mut self, currency: Currency, rule: PrecisionRule) -> Self {
+        self.currencies.insert(currency, rule);
+        self
+    }
+
+    pub fn rule(&self, currency: &Currency) -> PrecisionRule {
+        self.currencies
+            .get(currency)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Rule of a single currency as passed on the command line, `<CODE>=<MINOR_UNITS>[:<ROUNDING>]`, e.g. `JPY=0:half-even`.
+/// Without a rounding mode of its own, the default one is used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurrencyPrecision {
+    pub currency: Currency,
+    pub minor_units: u32,
+    pub rounding: Option<RoundingMode>,
+}
+
+impl FromStr for CurrencyPrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, rule) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <CODE>=<MINOR_UNITS>[:<ROUNDING>], got `{s}`"))?;
+        let (minor_units, rounding) = match rule.split_once(':') {
+            Some((minor_units, rounding)) => {
+                (minor_units, Some(RoundingMode::from_str(rounding, true)?))
+            }
+            None => (rule, None),
+        };
+        if code.trim().is_empty() {
+            return Err(format!("missing currency code in `{s}`"));
+        }
+
+        Ok(CurrencyPrecision {
+            currency: Currency::new(code.trim()),
+            minor_units: minor_units
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid minor units in `{s}`"))?,
+            rounding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use crate::domain::{
+        account::{
+            error::AccountError,
+            precision::{
+                CurrencyPrecision, PrecisionPolicy, PrecisionRule, Rounding, RoundingMode,
+            },
+        },
+        props::{Amount, Currency},
+    };
+
+    fn rule(minor_units: u32, rounding: RoundingMode) -> PrecisionRule {
+        PrecisionRule {
+            minor_units,
+            rounding,
+        }
+    }
+
+    #[test]
+    fn keeps_precise_amounts() {
+        assert_eq!(
+            rule(2, RoundingMode::Reject).apply(Amount(dec!(1.25))),
+            Ok((Amount(dec!(1.25)), None))
+        );
+    }
+
+    #[test]
+    fn rejects_over_precise_amounts() {
+        assert_eq!(
+            rule(2, RoundingMode::Reject).apply(Amount(dec!(1.255))),
+            Err(AccountError::IllegalAmount)
+        );
+        assert_eq!(
+            rule(2, RoundingMode::HalfEven).apply(Amount(dec!(-1.255))),
+            Err(AccountError::IllegalAmount)
+        );
+    }
+
+    #[test]
+    fn rounds_half_even() {
+        assert_eq!(
+            rule(2, RoundingMode::HalfEven).apply(Amount(dec!(1.245))),
+            Ok((
+                Amount(dec!(1.24)),
+                Some(Rounding {
+                    requested: Amount(dec!(1.245)),
+                    minor_units: 2,
+                    mode: RoundingMode::HalfEven,
+                })
+            ))
+        );
+        assert_eq!(
+            rule(0, RoundingMode::HalfEven).apply(Amount(dec!(2.5))),
+            Ok((
+                Amount(dec!(2)),
+                Some(Rounding {
+                    requested: Amount(dec!(2.5)),
+                    minor_units: 0,
+                    mode: RoundingMode::HalfEven,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn truncates() {
+        assert_eq!(
+            rule(2, RoundingMode::Truncate)
+                .apply(Amount(dec!(1.259)))
+                .map(|(amount, _)| amount),
+            Ok(Amount(dec!(1.25)))
+        );
+        assert_eq!(
+            rule(2, RoundingMode::Truncate).apply(Amount(dec!(0.009))),
+            Err(AccountError::IllegalAmount)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_default_rule() {
+        let policy = PrecisionPolicy::default()
+            .with_currency(Currency::new("JPY"), rule(0, RoundingMode::Truncate));
+
+        assert_eq!(
+            policy.rule(&Currency::new("jpy")),
+            rule(0, RoundingMode::Truncate)
+        );
+        assert_eq!(
+            policy.rule(&Currency::default()),
+            rule(4, RoundingMode::Reject)
+        );
+    }
+
+    #[test]
+    fn parses_currency_precision() {
+        assert_eq!(
+            "eur=2".parse(),
+            Ok(CurrencyPrecision {
+                currency: Currency::new("EUR"),
+                minor_units: 2,
+                rounding: None,
+            })
+        );
+        assert_eq!(
+            "BTC=8:truncate".parse(),
+            Ok(CurrencyPrecision {
+                currency: Currency::new("BTC"),
+                minor_units: 8,
+                rounding: Some(RoundingMode::Truncate),
+            })
+        );
+        assert!("EUR".parse::<CurrencyPrecision>().is_err());
+        assert!("EUR=two".parse::<CurrencyPrecision>().is_err());
+        assert!("EUR=2:ceil".parse::<CurrencyPrecision>().is_err());
+    }
+}
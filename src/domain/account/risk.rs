@@ -0,0 +1,238 @@
+use std::{collections::HashSet, fs};
+
+use color_eyre::eyre::{Result, eyre};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::domain::{
+    account::{aggregate::Movement, limits::LimitWindow},
+    props::{ClientId, Currency, InputClock},
+};
+
+/// Reason of the freeze of an account with a flagged operation.
+pub const HOLD_REASON: &str = "Held for review by risk screening";
+/// Operator of the freeze of an account with a flagged operation.
+pub const SCREENING_OPERATOR: &str = "risk-screening";
+
+/// Account operations screened before they are carried out.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenedOperation {
+    Deposit,
+    /// Withdrawal, capture of an authorization or sent transfer
+    Withdrawal,
+    Dispute,
+}
+
+/// Outcome of a screening, ordered by severity.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskDecision {
+    #[default]
+    Approve,
+    /// The account is frozen for review, a deposit is still credited but nothing more leaves the account
+    FlagAndHold,
+    Reject,
+}
+
+/// Operation to screen, with the history of the account it needs.
+#[derive(Debug)]
+pub struct Screening<'a> {
+    pub operation: ScreenedOperation,
+    pub client_id: &'a ClientId,
+    pub currency: &'a Currency,
+    pub amount: Decimal,
+    /// Clock of the row, when known
+    pub clock: Option<&'a InputClock>,
+    /// Deposits made at a known clock
+    pub deposits: &'a [Movement],
+}
+
+/// Decides on account operations before they are carried out, e.g. by fraud rules or an external service.
+pub trait RiskScreen: Send + Sync {
+    fn screen(&self, screening: &Screening) -> RiskDecision;
+}
+
+/// Operations of any client above the amount, of the given operation or all of them.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AmountThreshold {
+    #[serde(default)]
+    pub operation: Option<ScreenedOperation>,
+    pub above: Decimal,
+    pub decision: RiskDecision,
+}
+
+/// Withdrawals of at least a share of the funds deposited within the window before them, in the same currency.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DepositThenWithdrawal {
+    pub window: LimitWindow,
+    pub min_share: Decimal,
+    pub decision: RiskDecision,
+}
+
+/// Risk rules loaded from a JSON file, the most severe decision of the matching rules is taken, e.g.
+/// `{"blocked_clients": ["13"], "thresholds": [{"operation": "withdrawal", "above": 5000, "decision": "flag_and_hold"}]}`.
+/// Operations matching no rule are approved.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RiskRules {
+    /// Clients all operations of which are rejected
+    #[serde(default)]
+    pub blocked_clients: HashSet<String>,
+    #[serde(default)]
+    pub thresholds: Vec<AmountThreshold>,
+    #[serde(default)]
+    pub deposit_then_withdrawal: Option<DepositThenWithdrawal>,
+}
+
+impl RiskRules {
+    pub fn load(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).map_err(|e| eyre!("Could not read risk rules: {}", e))?;
+        serde_json::from_str(&content).map_err(|e| eyre!("Invalid risk rules {}: {}", path, e))
+    }
+
+    fn deposit_then_withdrawal(&self, s: &Screening) -> RiskDecision {
+        let (Some(rule), Some(now)) = (&self.deposit_then_withdrawal, s.clock) else {
+            return RiskDecision::Approve;
+        };
+        if s.operation != ScreenedOperation::Withdrawal {
+            return RiskDecision::Approve;
+        }
+
+        let deposited: Decimal = s
+            .deposits
+            .iter()
+            .filter(|d| &d.currency == s.currency && rule.window.contains(&d.clock, now))
+            .map(|d| d.amount)
+            .sum();
+        if deposited > Decimal::ZERO && s.amount >= deposited * rule.min_share {
+            rule.decision
+        } else {
+            RiskDecision::Approve
+        }
+    }
+}
+
+impl RiskScreen for RiskRules {
+    fn screen(&self, s: &Screening) -> RiskDecision {
+        if self.blocked_clients.contains(s.client_id.as_str()) {
+            debug!("Client {} is blocked", s.client_id);
+            return RiskDecision::Reject;
+        }
+
+        let decision = self
+            .thresholds
+            .iter()
+            .filter(|t| t.operation.is_none_or(|op| op == s.operation) && s.amount > t.above)
+            .map(|t| t.decision)
+            .chain([self.deposit_then_withdrawal(s)])
+            .max()
+            .unwrap_or_default();
+        if decision != RiskDecision::Approve {
+            debug!(
+                "{:?} of {} screened as {:?}",
+                s.operation, s.client_id, decision
+            );
+        }
+
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::{Decimal, dec};
+
+    use crate::domain::{
+        account::{
+            aggregate::Movement,
+            risk::{RiskDecision, RiskRules, RiskScreen, ScreenedOperation, Screening},
+        },
+        props::{ClientId, Currency, InputClock},
+    };
+
+    fn rules() -> RiskRules {
+        serde_json::from_str(
+            r#"{
+                "blocked_clients": ["13"],
+                "thresholds": [
+                    {"above": 1000, "decision": "flag_and_hold"},
+                    {"operation": "withdrawal", "above": 5000, "decision": "reject"}
+                ],
+                "deposit_then_withdrawal": {
+                    "window": {"rows": 10}, "min_share": 0.9, "decision": "flag_and_hold"
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn screen(
+        operation: ScreenedOperation,
+        client_id: &str,
+        amount: Decimal,
+        position: u64,
+    ) -> RiskDecision {
+        let deposits = [Movement {
+            currency: Currency::default(),
+            amount: dec!(100),
+            clock: InputClock {
+                position: 1,
+                time: None,
+            },
+            transaction_id: None,
+        }];
+        rules().screen(&Screening {
+            operation,
+            client_id: &ClientId(client_id.to_owned()),
+            currency: &Currency::default(),
+            amount,
+            clock: Some(&InputClock {
+                position,
+                time: None,
+            }),
+            deposits: &deposits,
+        })
+    }
+
+    #[test]
+    fn takes_most_severe_decision() {
+        use ScreenedOperation::*;
+
+        assert_eq!(screen(Deposit, "1", dec!(1000), 2), RiskDecision::Approve);
+        assert_eq!(screen(Deposit, "13", dec!(1), 2), RiskDecision::Reject);
+        assert_eq!(
+            screen(Dispute, "1", dec!(6000), 2),
+            RiskDecision::FlagAndHold
+        );
+        assert_eq!(screen(Withdrawal, "1", dec!(6000), 2), RiskDecision::Reject);
+    }
+
+    #[test]
+    fn flags_withdrawal_soon_after_deposit() {
+        use ScreenedOperation::*;
+
+        assert_eq!(
+            screen(Withdrawal, "1", dec!(90), 10),
+            RiskDecision::FlagAndHold
+        );
+        assert_eq!(screen(Withdrawal, "1", dec!(89), 10), RiskDecision::Approve);
+        assert_eq!(screen(Withdrawal, "1", dec!(90), 11), RiskDecision::Approve);
+        assert_eq!(screen(Deposit, "1", dec!(90), 10), RiskDecision::Approve);
+    }
+
+    #[test]
+    fn rejects_unknown_rules() {
+        assert!(serde_json::from_str::<RiskRules>(r#"{"allowed_clients": []}"#).is_err());
+        assert!(
+            serde_json::from_str::<RiskRules>(
+                r#"{"thresholds": [{"above": 1, "decision": "ignore"}]}"#
+            )
+            .is_err()
+        );
+    }
+}
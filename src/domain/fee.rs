@@ -0,0 +1,150 @@
+use std::fs;
+
+use color_eyre::eyre::{Result, eyre};
+use derive_more::Display;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::props::Amount;
+
+/// Client id of the house account charged fees are collected to, input rows can not use it.
+pub const HOUSE_CLIENT_ID: &str = "house";
+
+/// What a fee is charged for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Display, PartialEq)]
+pub enum FeeKind {
+    Deposit,
+    Withdrawal,
+    Chargeback,
+}
+
+/// How a fee is calculated from the amount it is charged on, in the currency of that amount.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Fee {
+    Flat {
+        amount: Decimal,
+    },
+    /// Percentage of the amount
+    Percent {
+        rate: Decimal,
+    },
+    /// Fee of the first tier the amount is not above, a tier without `up_to` takes all the rest
+    Tiered {
+        tiers: Vec<FeeTier>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct FeeTier {
+    #[serde(default)]
+    pub up_to: Option<Decimal>,
+    pub fee: Fee,
+}
+
+impl Fee {
+    pub fn amount(&self, base: Decimal) -> Decimal {
+        match self {
+            Fee::Flat { amount } => *amount,
+            Fee::Percent { rate } => base * rate / Decimal::ONE_HUNDRED,
+            Fee::Tiered { tiers } => tiers
+                .iter()
+                .find(|tier| tier.up_to.is_none_or(|up_to| base <= up_to))
+                .map_or(Decimal::ZERO, |tier| tier.fee.amount(base)),
+        }
+    }
+}
+
+/// Fees per transaction type, loaded from a JSON file, e.g.
+/// `{"withdrawal": {"type": "percent", "rate": 0.5}, "chargeback": {"type": "flat", "amount": 15}}`.
+/// Types without a fee are free.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSchedule {
+    #[serde(default)]
+    pub deposit: Option<Fee>,
+    #[serde(default)]
+    pub withdrawal: Option<Fee>,
+    #[serde(default)]
+    pub chargeback: Option<Fee>,
+}
+
+impl FeeSchedule {
+    pub fn load(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).map_err(|e| eyre!("Could not read fee schedule: {}", e))?;
+        serde_json::from_str(&content).map_err(|e| eyre!("Invalid fee schedule {}: {}", path, e))
+    }
+
+    /// Fee on the amount rounded half-even to the minor units of its currency, none when nothing is left to charge.
+    pub fn charge(&self, kind: FeeKind, base: Decimal, minor_units: u32) -> Option<Amount> {
+        let fee = match kind {
+            FeeKind::Deposit => self.deposit.as_ref(),
+            FeeKind::Withdrawal => self.withdrawal.as_ref(),
+            FeeKind::Chargeback => self.chargeback.as_ref(),
+        }?;
+        let amount = fee
+            .amount(base)
+            .round_dp_with_strategy(minor_units, RoundingStrategy::MidpointNearestEven);
+
+        (amount > Decimal::ZERO).then_some(Amount(amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use crate::domain::{
+        fee::{Fee, FeeKind, FeeSchedule, FeeTier},
+        props::Amount,
+    };
+
+    #[test]
+    fn calculates_fees() {
+        let tiered = Fee::Tiered {
+            tiers: vec![
+                FeeTier {
+                    up_to: Some(dec!(100)),
+                    fee: Fee::Flat { amount: dec!(1) },
+                },
+                FeeTier {
+                    up_to: None,
+                    fee: Fee::Percent { rate: dec!(0.5) },
+                },
+            ],
+        };
+
+        assert_eq!(Fee::Percent { rate: dec!(1.5) }.amount(dec!(20)), dec!(0.3));
+        assert_eq!(tiered.amount(dec!(100)), dec!(1));
+        assert_eq!(tiered.amount(dec!(1000)), dec!(5));
+    }
+
+    #[test]
+    fn charges_rounded_fees() {
+        let schedule: FeeSchedule = serde_json::from_str(
+            r#"{
+                "deposit": {"type": "percent", "rate": 0.25},
+                "chargeback": {"type": "flat", "amount": 15}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schedule.charge(FeeKind::Deposit, dec!(10.1), 2),
+            Some(Amount(dec!(0.03)))
+        );
+        assert_eq!(schedule.charge(FeeKind::Deposit, dec!(1), 2), None);
+        assert_eq!(schedule.charge(FeeKind::Withdrawal, dec!(10), 2), None);
+        assert_eq!(
+            schedule.charge(FeeKind::Chargeback, dec!(1), 2),
+            Some(Amount(dec!(15)))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_schedule_fields() {
+        assert!(serde_json::from_str::<FeeSchedule>(r#"{"refund": null}"#).is_err());
+        assert!(serde_json::from_str::<FeeSchedule>(r#"{"deposit": {"type": "ceil"}}"#).is_err());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod account;
+pub mod fee;
+pub mod payment;
 pub mod props;
 pub mod transaction;
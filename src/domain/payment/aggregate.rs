@@ -0,0 +1,608 @@
+use async_trait::async_trait;
+use cqrs_es::Aggregate;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::domain::{
+    payment::{
+        command::{
+            CompensatePaymentPayload, CompleteStepPayload, FinishPaymentPayload, PaymentCommand,
+            StartPaymentPayload,
+        },
+        error::PaymentError,
+        event::{
+            CompensationStartedPayload, PaymentCompletedPayload, PaymentEvent,
+            PaymentFailedPayload, PaymentStartedPayload, StepCompletedPayload,
+        },
+    },
+    props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId, TxType},
+};
+
+/// Process manager state of a deposit, withdrawal, transfer or authorization, or of an action on one (dispute,
+/// resolve, chargeback, capture or void), spanning Transaction and Account aggregates.
+/// Every completed step is persisted, so a payment interrupted by a crash can be resumed from its next step.
+#[derive(Debug, Serialize, Default, Deserialize)]
+pub struct Payment {
+    pub id: Option<TransactionId>,
+    pub client_id: Option<ClientId>,
+    /// Type of the transaction, the one acted on for an action
+    pub tx_type: Option<TxType>,
+    /// Without an amount, an action covers all of what it can
+    pub amount: Option<Amount>,
+    pub currency: Currency,
+    pub counterparty_id: Option<ClientId>,
+    /// Charged with the deposit, withdrawal, capture or chargeback
+    pub fee: Option<Amount>,
+    /// When the authorized funds are released, if never captured
+    pub expiry: Option<Expiry>,
+    /// Clock of the row that started the payment
+    pub clock: Option<InputClock>,
+    /// Action on the transaction, None for the transaction itself
+    pub action: Option<PaymentAction>,
+    completed_steps: Vec<PaymentStep>,
+    /// Reason of the rejected step, once compensating
+    pub failure: Option<String>,
+    finished: bool,
+}
+
+/// Steps of a payment, each of them can be retried without being applied twice.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PaymentStep {
+    RecordTransaction,
+    /// Deposit, withdrawal, authorization or transfer debit of the client account
+    ApplyToAccount,
+    /// Transfer credit of the recipient account
+    CreditRecipient,
+    /// Transaction moved from pending to applied
+    MarkApplied,
+    /// Transaction follows the action taken on the account
+    UpdateTransaction,
+    /// Fee charged to the client credited to the house account
+    CollectFee,
+    // Compensations
+    RevertTransfer,
+    VoidTransaction,
+}
+
+/// Action run as a payment on an existing transaction, a dispute-family one or the end of an authorization.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Display, PartialEq)]
+pub enum PaymentAction {
+    Dispute,
+    Resolve,
+    Chargeback,
+    Capture,
+    Void,
+}
+
+// Interface to the outside world, not used in this case.
+pub struct PaymentServices {}
+
+#[async_trait]
+impl Aggregate for Payment {
+    type Command = PaymentCommand;
+    type Event = PaymentEvent;
+    type Error = PaymentError;
+    type Services = PaymentServices;
+
+    fn aggregate_type() -> String {
+        "Payment".to_string()
+    }
+
+    async fn handle(
+        &self,
+        command: Self::Command,
+        _services: &Self::Services,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        match command {
+            PaymentCommand::StartPayment(p) => self.start(p).await,
+            PaymentCommand::CompleteStep(p) => self.complete_step(p).await,
+            PaymentCommand::CompensatePayment(p) => self.compensate(p).await,
+            PaymentCommand::FinishPayment(p) => self.finish(p).await,
+        }
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            PaymentEvent::PaymentStarted(p) => {
+                self.id = Some(p.id);
+                self.client_id = Some(p.client_id);
+                self.tx_type = Some(p.tx_type);
+                self.amount = p.amount;
+                self.currency = p.currency;
+                self.counterparty_id = p.counterparty_id;
+                self.fee = p.fee;
+                self.expiry = p.expiry;
+                self.clock = p.clock;
+                self.action = p.action;
+            }
+            PaymentEvent::StepCompleted(p) => {
+                self.completed_steps.push(p.step);
+            }
+            PaymentEvent::CompensationStarted(p) => {
+                self.failure = Some(p.reason);
+            }
+            PaymentEvent::PaymentCompleted(_) | PaymentEvent::PaymentFailed(_) => {
+                self.finished = true;
+            }
+        }
+    }
+}
+
+impl Payment {
+    async fn start(
+        &self,
+        p: StartPaymentPayload,
+    ) -> Result<Vec<<Payment as Aggregate>::Event>, <Payment as Aggregate>::Error> {
+        debug!("Starting {} payment {}", p.tx_type, p.id);
+
+        if self.id.is_some() {
+            return Err(PaymentError::DuplicatePayment);
+        }
+
+        Ok(vec![PaymentEvent::PaymentStarted(PaymentStartedPayload {
+            id: p.id,
+            client_id: p.client_id,
+            tx_type: p.tx_type,
+            amount: p.amount,
+            currency: p.currency,
+            counterparty_id: p.counterparty_id,
+            fee: p.fee,
+            expiry: p.expiry,
+            clock: p.clock,
+            action: p.action,
+        })])
+    }
+
+    async fn complete_step(
+        &self,
+        p: CompleteStepPayload,
+    ) -> Result<Vec<<Payment as Aggregate>::Event>, <Payment as Aggregate>::Error> {
+        debug!("Completing {:?} of payment {}", p.step, p.id);
+
+        require_running(self)?;
+        if self.next_step() != Some(p.step) {
+            return Err(PaymentError::UnexpectedStep);
+        }
+
+        Ok(vec![PaymentEvent::StepCompleted(StepCompletedPayload {
+            id: p.id,
+            step: p.step,
+        })])
+    }
+
+    async fn compensate(
+        &self,
+        p: CompensatePaymentPayload,
+    ) -> Result<Vec<<Payment as Aggregate>::Event>, <Payment as Aggregate>::Error> {
+        debug!("Compensating payment {}: {}", p.id, p.reason);
+
+        require_running(self)?;
+        if !self.can_compensate() {
+            return Err(PaymentError::UnexpectedStep);
+        }
+
+        Ok(vec![PaymentEvent::CompensationStarted(
+            CompensationStartedPayload {
+                id: p.id,
+                reason: p.reason,
+            },
+        )])
+    }
+
+    async fn finish(
+        &self,
+        p: FinishPaymentPayload,
+    ) -> Result<Vec<<Payment as Aggregate>::Event>, <Payment as Aggregate>::Error> {
+        debug!("Finishing payment {}", p.id);
+
+        require_running(self)?;
+        if self.next_step().is_some() {
+            return Err(PaymentError::UnexpectedStep);
+        }
+
+        match &self.failure {
+            None => Ok(vec![PaymentEvent::PaymentCompleted(
+                PaymentCompletedPayload { id: p.id },
+            )]),
+            Some(reason) => Ok(vec![PaymentEvent::PaymentFailed(PaymentFailedPayload {
+                id: p.id,
+                reason: reason.to_owned(),
+            })]),
+        }
+    }
+
+    /// Steps go forward until the payment is applied and its fee collected, once compensating the completed ones
+    /// are undone in reverse.
+    /// An action is checked against the transaction before it starts, so only its first step, on the account,
+    /// can be rejected and there is nothing to undo.
+    pub fn next_step(&self) -> Option<PaymentStep> {
+        let is_transfer = self.tx_type == Some(TxType::Transfer);
+        let done = |step: PaymentStep| self.completed_steps.contains(&step);
+        let has_fee = self.fee.is_some();
+
+        if self.action.is_some() {
+            if self.is_compensating() {
+                return None;
+            }
+            return [
+                PaymentStep::ApplyToAccount,
+                PaymentStep::UpdateTransaction,
+                PaymentStep::CollectFee,
+            ]
+            .into_iter()
+            .filter(|step| has_fee || *step != PaymentStep::CollectFee)
+            .find(|step| !done(*step));
+        }
+        if !self.is_compensating() {
+            return [
+                PaymentStep::RecordTransaction,
+                PaymentStep::ApplyToAccount,
+                PaymentStep::CreditRecipient,
+                PaymentStep::MarkApplied,
+                PaymentStep::CollectFee,
+            ]
+            .into_iter()
+            .filter(|step| is_transfer || *step != PaymentStep::CreditRecipient)
+            .filter(|step| has_fee || *step != PaymentStep::CollectFee)
+            .find(|step| !done(*step));
+        }
+
+        if is_transfer && done(PaymentStep::ApplyToAccount) && !done(PaymentStep::RevertTransfer) {
+            return Some(PaymentStep::RevertTransfer);
+        }
+        if done(PaymentStep::RecordTransaction) && !done(PaymentStep::VoidTransaction) {
+            return Some(PaymentStep::VoidTransaction);
+        }
+
+        None
+    }
+
+    pub fn is_compensating(&self) -> bool {
+        self.failure.is_some()
+    }
+
+    /// Compensations only undo steps up to the last one on an account, a step rejected after it
+    /// is left for the payment to be resumed, as the account can not be restored.
+    pub fn can_compensate(&self) -> bool {
+        let last_account_step = if self.tx_type == Some(TxType::Transfer) && self.action.is_none() {
+            PaymentStep::CreditRecipient
+        } else {
+            PaymentStep::ApplyToAccount
+        };
+
+        !self.is_compensating() && !self.completed_steps.contains(&last_account_step)
+    }
+}
+
+fn require_running(payment: &Payment) -> Result<(), <Payment as Aggregate>::Error> {
+    if payment.id.is_none() {
+        return Err(PaymentError::PaymentNotFound);
+    }
+    if payment.finished {
+        return Err(PaymentError::PaymentFinished);
+    }
+
+    Ok(())
+}
+
+pub const PAYMENT_AGGREGATE_PREFIX: &str = "Payment-";
+
+pub fn payment_aggregate_id(id: &str) -> String {
+    format!("{}{}", PAYMENT_AGGREGATE_PREFIX, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use cqrs_es::{Aggregate, test::TestFramework};
+    use rust_decimal::dec;
+
+    use crate::domain::{
+        payment::{
+            aggregate::{Payment, PaymentAction, PaymentServices, PaymentStep},
+            command::{
+                CompensatePaymentPayload, CompleteStepPayload, FinishPaymentPayload, PaymentCommand,
+            },
+            error::PaymentError,
+            event::{
+                CompensationStartedPayload, PaymentCompletedPayload, PaymentEvent,
+                PaymentFailedPayload, PaymentStartedPayload, StepCompletedPayload,
+            },
+        },
+        props::{Amount, ClientId, Currency, TransactionId, TxType},
+    };
+
+    type PaymentTestFramework = TestFramework<Payment>;
+
+    fn started(tx_type: TxType) -> PaymentEvent {
+        PaymentEvent::PaymentStarted(PaymentStartedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            client_id: ClientId("cl-1".to_owned()),
+            tx_type,
+            amount: Some(Amount(dec!(1.23))),
+            currency: Currency::default(),
+            counterparty_id: (tx_type == TxType::Transfer).then(|| ClientId("cl-2".to_owned())),
+            fee: None,
+            expiry: None,
+            clock: None,
+            action: None,
+        })
+    }
+
+    fn action_started(action: PaymentAction) -> PaymentEvent {
+        PaymentEvent::PaymentStarted(PaymentStartedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            client_id: ClientId("cl-1".to_owned()),
+            tx_type: TxType::Deposit,
+            amount: None,
+            currency: Currency::default(),
+            counterparty_id: None,
+            fee: None,
+            expiry: None,
+            clock: None,
+            action: Some(action),
+        })
+    }
+
+    fn step_completed(step: PaymentStep) -> PaymentEvent {
+        PaymentEvent::StepCompleted(StepCompletedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            step,
+        })
+    }
+
+    fn compensation_started() -> PaymentEvent {
+        PaymentEvent::CompensationStarted(CompensationStartedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            reason: "account_locked".to_owned(),
+        })
+    }
+
+    fn payment(events: Vec<PaymentEvent>) -> Payment {
+        let mut payment = Payment::default();
+        for event in events {
+            payment.apply(event);
+        }
+        payment
+    }
+
+    #[test]
+    fn test_deposit_steps() {
+        assert_eq!(
+            payment(vec![started(TxType::Deposit)]).next_step(),
+            Some(PaymentStep::RecordTransaction)
+        );
+        assert_eq!(
+            payment(vec![
+                started(TxType::Deposit),
+                step_completed(PaymentStep::RecordTransaction)
+            ])
+            .next_step(),
+            Some(PaymentStep::ApplyToAccount)
+        );
+        assert_eq!(
+            payment(vec![
+                started(TxType::Deposit),
+                step_completed(PaymentStep::RecordTransaction),
+                step_completed(PaymentStep::ApplyToAccount)
+            ])
+            .next_step(),
+            Some(PaymentStep::MarkApplied)
+        );
+        assert_eq!(
+            payment(vec![
+                started(TxType::Deposit),
+                step_completed(PaymentStep::RecordTransaction),
+                step_completed(PaymentStep::ApplyToAccount),
+                step_completed(PaymentStep::MarkApplied)
+            ])
+            .next_step(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fee_collected_last() {
+        let mut events = vec![PaymentEvent::PaymentStarted(PaymentStartedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            client_id: ClientId("cl-1".to_owned()),
+            tx_type: TxType::Withdrawal,
+            amount: Some(Amount(dec!(1.23))),
+            currency: Currency::default(),
+            counterparty_id: None,
+            fee: Some(Amount(dec!(0.1))),
+            expiry: None,
+            clock: None,
+            action: None,
+        })];
+        events.extend(
+            [
+                PaymentStep::RecordTransaction,
+                PaymentStep::ApplyToAccount,
+                PaymentStep::MarkApplied,
+            ]
+            .map(step_completed),
+        );
+        assert_eq!(
+            payment(events.clone()).next_step(),
+            Some(PaymentStep::CollectFee)
+        );
+
+        events.push(step_completed(PaymentStep::CollectFee));
+        assert_eq!(payment(events).next_step(), None);
+    }
+
+    #[test]
+    fn test_rejected_deposit_voided() {
+        let rejected = vec![
+            started(TxType::Deposit),
+            step_completed(PaymentStep::RecordTransaction),
+            compensation_started(),
+        ];
+        assert_eq!(
+            payment(rejected.clone()).next_step(),
+            Some(PaymentStep::VoidTransaction)
+        );
+
+        let mut voided = rejected;
+        voided.push(step_completed(PaymentStep::VoidTransaction));
+        assert_eq!(payment(voided).next_step(), None);
+    }
+
+    #[test]
+    fn test_rejected_transfer_credit_reverted() {
+        let rejected = vec![
+            started(TxType::Transfer),
+            step_completed(PaymentStep::RecordTransaction),
+            step_completed(PaymentStep::ApplyToAccount),
+            compensation_started(),
+        ];
+        assert_eq!(
+            payment(rejected.clone()).next_step(),
+            Some(PaymentStep::RevertTransfer)
+        );
+
+        let mut reverted = rejected;
+        reverted.push(step_completed(PaymentStep::RevertTransfer));
+        assert_eq!(
+            payment(reverted).next_step(),
+            Some(PaymentStep::VoidTransaction)
+        );
+    }
+
+    #[test]
+    fn test_action_steps() {
+        assert_eq!(
+            payment(vec![action_started(PaymentAction::Dispute)]).next_step(),
+            Some(PaymentStep::ApplyToAccount)
+        );
+        assert_eq!(
+            payment(vec![
+                action_started(PaymentAction::Resolve),
+                step_completed(PaymentStep::ApplyToAccount)
+            ])
+            .next_step(),
+            Some(PaymentStep::UpdateTransaction)
+        );
+        assert_eq!(
+            payment(vec![
+                action_started(PaymentAction::Chargeback),
+                step_completed(PaymentStep::ApplyToAccount),
+                step_completed(PaymentStep::UpdateTransaction)
+            ])
+            .next_step(),
+            None
+        );
+        assert_eq!(
+            payment(vec![
+                action_started(PaymentAction::Capture),
+                step_completed(PaymentStep::ApplyToAccount)
+            ])
+            .next_step(),
+            Some(PaymentStep::UpdateTransaction)
+        );
+    }
+
+    #[test]
+    fn test_rejected_dispute_action_not_undone() {
+        assert_eq!(
+            payment(vec![
+                action_started(PaymentAction::Dispute),
+                compensation_started()
+            ])
+            .next_step(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_compensate_after_account_applied() {
+        PaymentTestFramework::with(PaymentServices {})
+            .given(vec![
+                action_started(PaymentAction::Capture),
+                step_completed(PaymentStep::ApplyToAccount),
+            ])
+            .when(PaymentCommand::CompensatePayment(
+                CompensatePaymentPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    reason: "transaction_not_found".to_owned(),
+                },
+            ))
+            .then_expect_error(PaymentError::UnexpectedStep);
+
+        PaymentTestFramework::with(PaymentServices {})
+            .given(vec![
+                started(TxType::Deposit),
+                step_completed(PaymentStep::RecordTransaction),
+                step_completed(PaymentStep::ApplyToAccount),
+            ])
+            .when(PaymentCommand::CompensatePayment(
+                CompensatePaymentPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    reason: "transaction_not_found".to_owned(),
+                },
+            ))
+            .then_expect_error(PaymentError::UnexpectedStep);
+    }
+
+    #[test]
+    fn test_complete_unexpected_step() {
+        PaymentTestFramework::with(PaymentServices {})
+            .given(vec![started(TxType::Deposit)])
+            .when(PaymentCommand::CompleteStep(CompleteStepPayload {
+                id: TransactionId("tx-1".to_owned()),
+                step: PaymentStep::ApplyToAccount,
+            }))
+            .then_expect_error(PaymentError::UnexpectedStep);
+    }
+
+    #[test]
+    fn test_finish_payment() {
+        PaymentTestFramework::with(PaymentServices {})
+            .given(vec![
+                started(TxType::Withdrawal),
+                step_completed(PaymentStep::RecordTransaction),
+                step_completed(PaymentStep::ApplyToAccount),
+                step_completed(PaymentStep::MarkApplied),
+            ])
+            .when(PaymentCommand::FinishPayment(FinishPaymentPayload {
+                id: TransactionId("tx-1".to_owned()),
+            }))
+            .then_expect_events(vec![PaymentEvent::PaymentCompleted(
+                PaymentCompletedPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_finish_compensated_payment() {
+        PaymentTestFramework::with(PaymentServices {})
+            .given(vec![
+                started(TxType::Withdrawal),
+                step_completed(PaymentStep::RecordTransaction),
+                compensation_started(),
+                step_completed(PaymentStep::VoidTransaction),
+            ])
+            .when(PaymentCommand::FinishPayment(FinishPaymentPayload {
+                id: TransactionId("tx-1".to_owned()),
+            }))
+            .then_expect_events(vec![PaymentEvent::PaymentFailed(PaymentFailedPayload {
+                id: TransactionId("tx-1".to_owned()),
+                reason: "account_locked".to_owned(),
+            })]);
+    }
+
+    #[test]
+    fn test_compensate_twice() {
+        PaymentTestFramework::with(PaymentServices {})
+            .given(vec![started(TxType::Deposit), compensation_started()])
+            .when(PaymentCommand::CompensatePayment(
+                CompensatePaymentPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    reason: "account_locked".to_owned(),
+                },
+            ))
+            .then_expect_error(PaymentError::UnexpectedStep);
+    }
+}
@@ -0,0 +1,51 @@
+use serde::Deserialize;
+
+use crate::domain::{
+    payment::aggregate::{PaymentAction, PaymentStep},
+    props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId, TxType},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum PaymentCommand {
+    StartPayment(StartPaymentPayload),
+    CompleteStep(CompleteStepPayload),
+    CompensatePayment(CompensatePaymentPayload),
+    FinishPayment(FinishPaymentPayload),
+}
+
+/// Persists the payment before any of its steps are taken.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartPaymentPayload {
+    pub id: TransactionId,
+    pub client_id: ClientId,
+    pub tx_type: TxType,
+    pub amount: Option<Amount>,
+    pub currency: Currency,
+    pub counterparty_id: Option<ClientId>,
+    /// Fee of the schedule at the start, so a resumed payment charges the same one
+    pub fee: Option<Amount>,
+    /// When an authorization expires, fixed at the start like the fee
+    pub expiry: Option<Expiry>,
+    /// Clock of the row, withdrawal limits look back from it
+    pub clock: Option<InputClock>,
+    /// Action on the transaction, instead of the transaction itself
+    pub action: Option<PaymentAction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompleteStepPayload {
+    pub id: TransactionId,
+    pub step: PaymentStep,
+}
+
+/// A step was rejected, so the completed ones are undone from now on.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompensatePaymentPayload {
+    pub id: TransactionId,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FinishPaymentPayload {
+    pub id: TransactionId,
+}
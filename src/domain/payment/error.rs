@@ -0,0 +1,23 @@
+use derive_more::Display;
+
+#[derive(Debug, PartialEq, Display)]
+pub enum PaymentError {
+    DuplicatePayment,
+    PaymentNotFound,
+    PaymentFinished,
+    UnexpectedStep,
+}
+
+impl PaymentError {
+    /// Machine readable reason, used in rejection reports.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PaymentError::DuplicatePayment => "duplicate_payment",
+            PaymentError::PaymentNotFound => "payment_not_found",
+            PaymentError::PaymentFinished => "payment_finished",
+            PaymentError::UnexpectedStep => "unexpected_step",
+        }
+    }
+}
+
+impl std::error::Error for PaymentError {}
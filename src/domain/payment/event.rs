@@ -0,0 +1,75 @@
+use cqrs_es::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    payment::aggregate::{PaymentAction, PaymentStep},
+    props::{Amount, ClientId, Currency, Expiry, InputClock, TransactionId, TxType},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PaymentEvent {
+    PaymentStarted(PaymentStartedPayload),
+    StepCompleted(StepCompletedPayload),
+    CompensationStarted(CompensationStartedPayload),
+    PaymentCompleted(PaymentCompletedPayload),
+    PaymentFailed(PaymentFailedPayload),
+}
+
+impl DomainEvent for PaymentEvent {
+    fn event_type(&self) -> String {
+        let event_type: &str = match self {
+            PaymentEvent::PaymentStarted(_) => "PaymentStarted",
+            PaymentEvent::StepCompleted(_) => "StepCompleted",
+            PaymentEvent::CompensationStarted(_) => "CompensationStarted",
+            PaymentEvent::PaymentCompleted(_) => "PaymentCompleted",
+            PaymentEvent::PaymentFailed(_) => "PaymentFailed",
+        };
+        event_type.to_string()
+    }
+
+    fn event_version(&self) -> String {
+        "1.0".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaymentStartedPayload {
+    pub id: TransactionId,
+    pub client_id: ClientId,
+    pub tx_type: TxType,
+    pub amount: Option<Amount>,
+    #[serde(default)]
+    pub currency: Currency,
+    pub counterparty_id: Option<ClientId>,
+    #[serde(default)]
+    pub fee: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<Expiry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<InputClock>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<PaymentAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StepCompletedPayload {
+    pub id: TransactionId,
+    pub step: PaymentStep,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CompensationStartedPayload {
+    pub id: TransactionId,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaymentCompletedPayload {
+    pub id: TransactionId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaymentFailedPayload {
+    pub id: TransactionId,
+    pub reason: String,
+}
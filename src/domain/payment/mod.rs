@@ -0,0 +1,4 @@
+pub mod aggregate;
+pub mod command;
+pub mod error;
+pub mod event;
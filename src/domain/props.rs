@@ -12,8 +12,101 @@ pub struct TransactionId(pub String);
 #[derive(Shrinkwrap, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Display, Hash)]
 pub struct Amount(pub Decimal);
 
-#[derive(Debug, Serialize, Deserialize, Display, PartialEq)]
+/// Rows and events without a currency are in this one.
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Upper case code of the currency (or other asset) amounts are in, e.g. `EUR`.
+#[derive(
+    Shrinkwrap, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Display, Hash, PartialOrd, Ord,
+)]
+pub struct Currency(pub String);
+
+impl Currency {
+    /// Codes are case insensitive, so `eur` and `EUR` are the same currency.
+    pub fn new(code: &str) -> Self {
+        Currency(code.to_uppercase())
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency(DEFAULT_CURRENCY.to_owned())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Display, PartialEq)]
 pub enum TxType {
     Deposit,
     Withdrawal,
+    Transfer,
+    /// Funds reserved until captured, voided or expired
+    Authorization,
+}
+
+/// Position of a row in the input (across runs against the same store) and the latest timestamp seen up to it.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct InputClock {
+    pub position: u64,
+    pub time: Option<i64>,
+}
+
+impl InputClock {
+    /// Moves the clock to the next row, rows without a timestamp keep the time.
+    pub fn tick(&mut self, timestamp: Option<i64>) {
+        self.position += 1;
+        self.time = self.time.max(timestamp);
+    }
+}
+
+/// When an authorization expires: after the row at the position, or once the time is reached.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct Expiry {
+    pub after_position: Option<u64>,
+    pub at_time: Option<i64>,
+}
+
+impl Expiry {
+    pub fn is_due(&self, clock: &InputClock) -> bool {
+        self.after_position
+            .is_some_and(|position| clock.position > position)
+            || self
+                .at_time
+                .is_some_and(|time| clock.time.is_some_and(|now| now >= time))
+    }
+
+    /// Expiry due as soon as either of the two is.
+    pub fn earliest(&self, other: &Expiry) -> Expiry {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        Expiry {
+            after_position: min(self.after_position, other.after_position),
+            at_time: min(self.at_time, other.at_time),
+        }
+    }
+}
+
+/// How long authorizations stay open when never captured, they do not expire when neither is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AuthorizationTtl {
+    /// Input rows after the authorizing one
+    pub rows: Option<u64>,
+    /// Seconds after the time of the authorizing row
+    pub seconds: Option<u32>,
+}
+
+impl AuthorizationTtl {
+    /// Expiry of an authorization made at the clock.
+    pub fn expiry(&self, clock: &InputClock) -> Expiry {
+        Expiry {
+            after_position: self.rows.map(|rows| clock.position + rows),
+            at_time: self
+                .seconds
+                .and_then(|seconds| clock.time.map(|time| time + i64::from(seconds))),
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use async_trait::async_trait;
 use cqrs_es::Aggregate;
 use rust_decimal::Decimal;
@@ -5,20 +7,63 @@ use serde::{Deserialize, Serialize};
 use tracing::debug;
 
 use crate::domain::{
-    props::TxType,
+    props::{Amount, ClientId, Currency, TxType},
     transaction::{
-        command::{RecordTransactionPayload, TransactionCommand},
+        command::{
+            ApplyTransactionPayload, CaptureTransactionAuthorizationPayload,
+            ChargebackTransactionDisputePayload, DisputeTransactionPayload,
+            ExpireTransactionAuthorizationPayload, RecordTransactionPayload,
+            ResolveTransactionDisputePayload, TransactionCommand,
+            VoidTransactionAuthorizationPayload, VoidTransactionPayload,
+        },
         error::TransactionError,
-        event::{TransactionEvent, TransactionRecordedPayload},
+        event::{
+            AuthorizationCapturedPayload, AuthorizationExpiredPayload, AuthorizationVoidedPayload,
+            DisputeChargedBackPayload, DisputeResolvedPayload, TransactionAppliedPayload,
+            TransactionDisputedPayload, TransactionEvent, TransactionRecordedPayload,
+            TransactionVoidedPayload,
+        },
     },
 };
 
 // Aggregate
 #[derive(Debug, Serialize, Default, Deserialize)]
 pub struct Transaction {
-    recorded: bool,
+    /// None until recorded
+    pub status: Option<TransactionStatus>,
+    pub client_id: Option<ClientId>,
     pub tx_type: Option<TxType>,
     pub amount: Decimal,
+    pub currency: Currency,
+    /// Disputed and neither resolved nor charged back yet
+    pub disputed: Decimal,
+    charged_back: Decimal,
+    /// Disputes, resolves and chargebacks applied by payments, so retried steps are not applied twice
+    #[serde(default)]
+    dispute_payments: HashSet<String>,
+}
+
+/// Lifecycle of a transaction: Pending → Applied / Rejected → Disputed → Resolved / ChargedBack.
+/// Resolved parts can be disputed again, so a transaction goes back and forth between those.
+/// An applied authorization ends up Captured, Voided or Expired instead.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TransactionStatus {
+    /// Recorded, not applied to the account yet
+    Pending,
+    Applied,
+    /// Recorded, but never applied to the account
+    Rejected,
+    /// Some of it is held by an open dispute
+    Disputed,
+    Resolved,
+    /// Disputes are over, some of them were charged back
+    ChargedBack,
+    /// Authorization captured, what was not captured is released
+    Captured,
+    /// Authorization released by the client
+    Voided,
+    /// Authorization released once due
+    Expired,
 }
 
 // Interface to the outside world, not used in this case.
@@ -42,14 +87,56 @@ impl Aggregate for Transaction {
     ) -> Result<Vec<Self::Event>, Self::Error> {
         match command {
             TransactionCommand::RecordTransaction(p) => self.record(p).await,
+            TransactionCommand::VoidTransaction(p) => self.void(p).await,
+            TransactionCommand::ApplyTransaction(p) => self.apply_transaction(p).await,
+            TransactionCommand::DisputeTransaction(p) => self.dispute(p).await,
+            TransactionCommand::ResolveTransactionDispute(p) => self.resolve(p).await,
+            TransactionCommand::ChargebackTransactionDispute(p) => self.chargeback(p).await,
+            TransactionCommand::CaptureTransactionAuthorization(p) => self.capture(p).await,
+            TransactionCommand::VoidTransactionAuthorization(p) => self.void_authorization(p).await,
+            TransactionCommand::ExpireTransactionAuthorization(p) => self.expire(p).await,
         }
     }
 
     fn apply(&mut self, event: Self::Event) {
         match event {
             TransactionEvent::TransactionRecorded(p) => {
-                self.recorded = true;
+                self.status = Some(TransactionStatus::Pending);
+                self.client_id = Some(p.client_id);
+                self.tx_type = Some(p.tx_type);
                 self.amount = *p.amount;
+                self.currency = p.currency;
+            }
+            TransactionEvent::TransactionVoided(_) => {
+                self.status = Some(TransactionStatus::Rejected);
+            }
+            TransactionEvent::TransactionApplied(_) => {
+                self.status = Some(TransactionStatus::Applied);
+            }
+            TransactionEvent::TransactionDisputed(p) => {
+                self.dispute_payments.extend(p.payment_id);
+                self.disputed += *p.amount;
+                self.status = Some(TransactionStatus::Disputed);
+            }
+            TransactionEvent::DisputeResolved(p) => {
+                self.dispute_payments.extend(p.payment_id);
+                self.disputed -= *p.amount;
+                self.close_dispute();
+            }
+            TransactionEvent::DisputeChargedBack(p) => {
+                self.dispute_payments.extend(p.payment_id);
+                self.disputed -= *p.amount;
+                self.charged_back += *p.amount;
+                self.close_dispute();
+            }
+            TransactionEvent::AuthorizationCaptured(_) => {
+                self.status = Some(TransactionStatus::Captured);
+            }
+            TransactionEvent::AuthorizationVoided(_) => {
+                self.status = Some(TransactionStatus::Voided);
+            }
+            TransactionEvent::AuthorizationExpired(_) => {
+                self.status = Some(TransactionStatus::Expired);
             }
         }
     }
@@ -68,20 +155,771 @@ impl Transaction {
             TransactionRecordedPayload {
                 id: p.id,
                 client_id: p.client_id,
+                tx_type: p.tx_type,
                 amount: p.amount,
+                currency: p.currency,
+                counterparty_id: p.counterparty_id,
             },
         )])
     }
+
+    /// Voiding an already voided transaction is a no-op, so compensation can be retried.
+    async fn void(
+        &self,
+        p: VoidTransactionPayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Voiding {}: {}", p.id, p.reason);
+
+        match self.status {
+            None => return Err(TransactionError::TransactionNotFound),
+            Some(TransactionStatus::Pending) => {}
+            Some(TransactionStatus::Rejected) => return Ok(vec![]),
+            Some(_) => return Err(TransactionError::InvalidTransition),
+        }
+
+        Ok(vec![TransactionEvent::TransactionVoided(
+            TransactionVoidedPayload {
+                id: p.id,
+                reason: p.reason,
+            },
+        )])
+    }
+
+    /// Applying an already applied transaction is a no-op, so the payment step can be retried.
+    async fn apply_transaction(
+        &self,
+        p: ApplyTransactionPayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Applying {}", p.id);
+
+        match self.status {
+            None => return Err(TransactionError::TransactionNotFound),
+            Some(TransactionStatus::Pending) => {}
+            Some(TransactionStatus::Rejected) => return Err(TransactionError::TransactionVoided),
+            Some(_) => return Ok(vec![]),
+        }
+
+        Ok(vec![TransactionEvent::TransactionApplied(
+            TransactionAppliedPayload { id: p.id },
+        )])
+    }
+
+    /// Without an amount, all of the transaction which is not disputed yet is disputed.
+    async fn dispute(
+        &self,
+        p: DisputeTransactionPayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Disputing {}", p.id);
+
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
+        let amount = self.require_dispute_part(p.amount)?;
+
+        Ok(vec![TransactionEvent::TransactionDisputed(
+            TransactionDisputedPayload {
+                id: p.id,
+                amount,
+                payment_id: p.payment_id,
+            },
+        )])
+    }
+
+    /// Without an amount, all of the disputed part is resolved.
+    async fn resolve(
+        &self,
+        p: ResolveTransactionDisputePayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Resolving {}", p.id);
+
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
+        let amount = self.require_disputed_part(p.amount)?;
+
+        Ok(vec![TransactionEvent::DisputeResolved(
+            DisputeResolvedPayload {
+                id: p.id,
+                amount,
+                payment_id: p.payment_id,
+            },
+        )])
+    }
+
+    /// Without an amount, all of the disputed part is charged back.
+    async fn chargeback(
+        &self,
+        p: ChargebackTransactionDisputePayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Charging back {}", p.id);
+
+        if self.is_payment_applied(&p.payment_id) {
+            return Ok(vec![]);
+        }
+        let amount = self.require_disputed_part(p.amount)?;
+
+        Ok(vec![TransactionEvent::DisputeChargedBack(
+            DisputeChargedBackPayload {
+                id: p.id,
+                amount,
+                payment_id: p.payment_id,
+            },
+        )])
+    }
+
+    /// Follows the account capture, capturing an already captured authorization is a no-op, so it can be retried.
+    async fn capture(
+        &self,
+        p: CaptureTransactionAuthorizationPayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Capturing {} of {}", p.amount, p.id);
+
+        if self.is_authorization_closed_as(TransactionStatus::Captured) {
+            return Ok(vec![]);
+        }
+        self.require_open_authorization()?;
+
+        Ok(vec![TransactionEvent::AuthorizationCaptured(
+            AuthorizationCapturedPayload {
+                id: p.id,
+                amount: p.amount,
+            },
+        )])
+    }
+
+    async fn void_authorization(
+        &self,
+        p: VoidTransactionAuthorizationPayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Voiding authorization {}", p.id);
+
+        if self.is_authorization_closed_as(TransactionStatus::Voided) {
+            return Ok(vec![]);
+        }
+        self.require_open_authorization()?;
+
+        Ok(vec![TransactionEvent::AuthorizationVoided(
+            AuthorizationVoidedPayload { id: p.id },
+        )])
+    }
+
+    async fn expire(
+        &self,
+        p: ExpireTransactionAuthorizationPayload,
+    ) -> Result<Vec<<Transaction as Aggregate>::Event>, <Transaction as Aggregate>::Error> {
+        debug!("Expiring authorization {}", p.id);
+
+        if self.is_authorization_closed_as(TransactionStatus::Expired) {
+            return Ok(vec![]);
+        }
+        self.require_open_authorization()?;
+
+        Ok(vec![TransactionEvent::AuthorizationExpired(
+            AuthorizationExpiredPayload { id: p.id },
+        )])
+    }
+
+    /// Once nothing is held anymore, the dispute ends with the outcome of its parts.
+    fn close_dispute(&mut self) {
+        if self.disputed > Decimal::ZERO {
+            return;
+        }
+        self.status = Some(if self.charged_back > Decimal::ZERO {
+            TransactionStatus::ChargedBack
+        } else {
+            TransactionStatus::Resolved
+        });
+    }
+
+    pub fn is_recorded(&self) -> bool {
+        self.status.is_some()
+    }
+
+    /// Some of the transaction is held by an open dispute.
+    pub fn is_disputed(&self) -> bool {
+        self.status == Some(TransactionStatus::Disputed)
+    }
+
+    /// Dispute-family commands are only allowed for the client who owns the transaction.
+    pub fn require_owned_by(
+        &self,
+        client_id: &ClientId,
+    ) -> Result<(), <Transaction as Aggregate>::Error> {
+        if !self.is_recorded() {
+            return Err(TransactionError::TransactionNotFound);
+        }
+
+        if self.client_id.as_ref() != Some(client_id) {
+            return Err(TransactionError::ClientMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Transfers are between clients of the engine, so there is no card network to dispute them with.
+    /// Voided transactions never moved any funds, pending ones did not yet.
+    pub fn require_disputable(&self) -> Result<(), <Transaction as Aggregate>::Error> {
+        match self.status {
+            None => return Err(TransactionError::TransactionNotFound),
+            Some(TransactionStatus::Rejected) => return Err(TransactionError::TransactionVoided),
+            Some(TransactionStatus::Pending) => return Err(TransactionError::TransactionPending),
+            Some(_) => {}
+        }
+        if matches!(self.tx_type, Some(TxType::Transfer | TxType::Authorization)) {
+            return Err(TransactionError::NotDisputable);
+        }
+
+        Ok(())
+    }
+
+    /// Part of the transaction the dispute holds, checked before the account is disputed.
+    /// Without an amount, all of the transaction which is not disputed yet.
+    pub fn require_dispute_part(
+        &self,
+        amount: Option<Amount>,
+    ) -> Result<Amount, <Transaction as Aggregate>::Error> {
+        self.require_disputable()?;
+        require_part(amount, self.amount - self.disputed - self.charged_back)
+    }
+
+    /// Part of the disputed amount a resolve or chargeback ends, checked before the account is changed.
+    /// Without an amount, all of the disputed part.
+    pub fn require_disputed_part(
+        &self,
+        amount: Option<Amount>,
+    ) -> Result<Amount, <Transaction as Aggregate>::Error> {
+        require_disputed(self)?;
+        require_part(amount, self.disputed)
+    }
+
+    /// Captures and voids are checked against the transaction before the account is changed.
+    /// Rejected the same way as by the account, which does not know closed authorizations apart from expired ones.
+    pub fn require_open_authorization(&self) -> Result<(), <Transaction as Aggregate>::Error> {
+        match self.status {
+            None => Err(TransactionError::TransactionNotFound),
+            Some(_) if self.tx_type != Some(TxType::Authorization) => {
+                Err(TransactionError::AuthorizationNotFound)
+            }
+            Some(TransactionStatus::Pending) => Err(TransactionError::TransactionPending),
+            Some(TransactionStatus::Applied) => Ok(()),
+            Some(TransactionStatus::Expired) => Err(TransactionError::AuthorizationExpired),
+            Some(_) => Err(TransactionError::AuthorizationNotFound),
+        }
+    }
+
+    /// The authorization already ended this way, so closing it again is a retry.
+    fn is_authorization_closed_as(&self, closed: TransactionStatus) -> bool {
+        self.tx_type == Some(TxType::Authorization) && self.status == Some(closed)
+    }
+
+    fn is_payment_applied(&self, payment_id: &Option<String>) -> bool {
+        payment_id
+            .as_ref()
+            .is_some_and(|id| self.dispute_payments.contains(id))
+    }
 }
 
 fn require_new(transaction: &Transaction) -> Result<(), <Transaction as Aggregate>::Error> {
-    if transaction.recorded {
+    if transaction.is_recorded() {
         return Err(TransactionError::DuplicateTransaction);
     }
 
     Ok(())
 }
 
+fn require_disputed(transaction: &Transaction) -> Result<(), <Transaction as Aggregate>::Error> {
+    if !transaction.is_disputed() {
+        return Err(TransactionError::DisputeNotFound);
+    }
+
+    Ok(())
+}
+
+/// The requested part of what is available, or all of it.
+fn require_part(
+    amount: Option<Amount>,
+    available: Decimal,
+) -> Result<Amount, <Transaction as Aggregate>::Error> {
+    match amount {
+        None if available <= Decimal::ZERO => Err(TransactionError::DuplicateDispute),
+        None => Ok(Amount(available)),
+        Some(amount) if *amount > available => Err(TransactionError::DisputeAmountExceeded),
+        Some(amount) => Ok(amount),
+    }
+}
+
+pub const TX_AGGREGATE_PREFIX: &str = "Transaction-";
+
 pub fn tx_aggregate_id(id: &str) -> String {
-    format!("Transaction-{}", id)
+    format!("{}{}", TX_AGGREGATE_PREFIX, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use cqrs_es::{Aggregate, test::TestFramework};
+    use rust_decimal::{Decimal, dec};
+
+    use crate::domain::{
+        props::{Amount, ClientId, Currency, TransactionId, TxType},
+        transaction::{
+            aggregate::{Transaction, TransactionServices, TransactionStatus},
+            command::{
+                ApplyTransactionPayload, CaptureTransactionAuthorizationPayload,
+                ChargebackTransactionDisputePayload, DisputeTransactionPayload,
+                ExpireTransactionAuthorizationPayload, RecordTransactionPayload,
+                ResolveTransactionDisputePayload, TransactionCommand,
+                VoidTransactionAuthorizationPayload, VoidTransactionPayload,
+            },
+            error::TransactionError,
+            event::{
+                AuthorizationCapturedPayload, AuthorizationExpiredPayload,
+                AuthorizationVoidedPayload, DisputeChargedBackPayload, DisputeResolvedPayload,
+                TransactionAppliedPayload, TransactionDisputedPayload, TransactionEvent,
+                TransactionRecordedPayload, TransactionVoidedPayload,
+            },
+        },
+    };
+
+    type TransactionTestFramework = TestFramework<Transaction>;
+
+    #[test]
+    fn test_record_transaction() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given_no_previous_events()
+            .when(TransactionCommand::RecordTransaction(
+                RecordTransactionPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    client_id: ClientId("cl-1".to_owned()),
+                    tx_type: TxType::Withdrawal,
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    counterparty_id: None,
+                },
+            ))
+            .then_expect_events(vec![TransactionEvent::TransactionRecorded(
+                TransactionRecordedPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    client_id: ClientId("cl-1".to_owned()),
+                    tx_type: TxType::Withdrawal,
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    counterparty_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_record_duplicate_transaction() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![TransactionEvent::TransactionRecorded(
+                TransactionRecordedPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    client_id: ClientId("cl-1".to_owned()),
+                    tx_type: TxType::Deposit,
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    counterparty_id: None,
+                },
+            )])
+            .when(TransactionCommand::RecordTransaction(
+                RecordTransactionPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    client_id: ClientId("cl-1".to_owned()),
+                    tx_type: TxType::Deposit,
+                    amount: Amount(dec!(1.23)),
+                    currency: Currency::default(),
+                    counterparty_id: None,
+                },
+            ))
+            .then_expect_error(TransactionError::DuplicateTransaction);
+    }
+
+    #[test]
+    fn test_void_transaction() {
+        let recorded = TransactionEvent::TransactionRecorded(TransactionRecordedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            client_id: ClientId("cl-1".to_owned()),
+            tx_type: TxType::Deposit,
+            amount: Amount(dec!(1.23)),
+            currency: Currency::default(),
+            counterparty_id: None,
+        });
+        let voided = TransactionEvent::TransactionVoided(TransactionVoidedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            reason: "account_locked".to_owned(),
+        });
+        let void = TransactionCommand::VoidTransaction(VoidTransactionPayload {
+            id: TransactionId("tx-1".to_owned()),
+            reason: "account_locked".to_owned(),
+        });
+
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded.clone()])
+            .when(void.clone())
+            .then_expect_events(vec![voided.clone()]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded, voided])
+            .when(void.clone())
+            .then_expect_events(vec![]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given_no_previous_events()
+            .when(void)
+            .then_expect_error(TransactionError::TransactionNotFound);
+    }
+
+    #[test]
+    fn test_voided_not_disputable() {
+        let mut transaction = recorded(TxType::Deposit);
+        transaction.apply(TransactionEvent::TransactionVoided(
+            TransactionVoidedPayload {
+                id: TransactionId("tx-1".to_owned()),
+                reason: "account_locked".to_owned(),
+            },
+        ));
+
+        assert_eq!(
+            transaction.require_disputable(),
+            Err(TransactionError::TransactionVoided)
+        );
+    }
+
+    #[test]
+    fn test_tx_type_recorded() {
+        assert_eq!(recorded(TxType::Deposit).tx_type, Some(TxType::Deposit));
+        assert_eq!(
+            recorded(TxType::Withdrawal).tx_type,
+            Some(TxType::Withdrawal)
+        );
+    }
+
+    #[test]
+    fn test_owner_allowed() {
+        let transaction = recorded(TxType::Deposit);
+
+        assert_eq!(
+            transaction.require_owned_by(&ClientId("cl-1".to_owned())),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_other_client_rejected() {
+        let transaction = recorded(TxType::Deposit);
+
+        assert_eq!(
+            transaction.require_owned_by(&ClientId("cl-2".to_owned())),
+            Err(TransactionError::ClientMismatch)
+        );
+    }
+
+    #[test]
+    fn test_transfer_not_disputable() {
+        assert_eq!(applied(TxType::Deposit).require_disputable(), Ok(()));
+        assert_eq!(
+            applied(TxType::Transfer).require_disputable(),
+            Err(TransactionError::NotDisputable)
+        );
+    }
+
+    #[test]
+    fn test_pending_not_disputable() {
+        assert_eq!(
+            recorded(TxType::Deposit).require_disputable(),
+            Err(TransactionError::TransactionPending)
+        );
+    }
+
+    #[test]
+    fn test_apply_transaction() {
+        let apply = TransactionCommand::ApplyTransaction(ApplyTransactionPayload {
+            id: TransactionId("tx-1".to_owned()),
+        });
+
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit)])
+            .when(apply.clone())
+            .then_expect_events(vec![applied_event()]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit), applied_event()])
+            .when(apply.clone())
+            .then_expect_events(vec![]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit), voided_event()])
+            .when(apply)
+            .then_expect_error(TransactionError::TransactionVoided);
+    }
+
+    #[test]
+    fn test_void_applied_transaction() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit), applied_event()])
+            .when(TransactionCommand::VoidTransaction(
+                VoidTransactionPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    reason: "account_locked".to_owned(),
+                },
+            ))
+            .then_expect_error(TransactionError::InvalidTransition);
+    }
+
+    #[test]
+    fn test_dispute_transaction() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit), applied_event()])
+            .when(TransactionCommand::DisputeTransaction(
+                DisputeTransactionPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![TransactionEvent::TransactionDisputed(
+                TransactionDisputedPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(1.23)),
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_dispute_more_than_undisputed() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![
+                recorded_event(TxType::Deposit),
+                applied_event(),
+                disputed_event(dec!(1.0)),
+            ])
+            .when(TransactionCommand::DisputeTransaction(
+                DisputeTransactionPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    amount: Some(Amount(dec!(0.5))),
+                    payment_id: None,
+                },
+            ))
+            .then_expect_error(TransactionError::DisputeAmountExceeded);
+    }
+
+    #[test]
+    fn test_resolve_not_disputed() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit), applied_event()])
+            .when(TransactionCommand::ResolveTransactionDispute(
+                ResolveTransactionDisputePayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_error(TransactionError::DisputeNotFound);
+    }
+
+    #[test]
+    fn test_chargeback_all_disputed() {
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![
+                recorded_event(TxType::Deposit),
+                applied_event(),
+                disputed_event(dec!(1.0)),
+            ])
+            .when(TransactionCommand::ChargebackTransactionDispute(
+                ChargebackTransactionDisputePayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    amount: None,
+                    payment_id: None,
+                },
+            ))
+            .then_expect_events(vec![TransactionEvent::DisputeChargedBack(
+                DisputeChargedBackPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                    amount: Amount(dec!(1.0)),
+                    payment_id: None,
+                },
+            )]);
+    }
+
+    #[test]
+    fn test_lifecycle_status() {
+        let mut transaction = Transaction::default();
+        assert_eq!(transaction.status, None);
+
+        transaction.apply(recorded_event(TxType::Deposit));
+        assert_eq!(transaction.status, Some(TransactionStatus::Pending));
+
+        transaction.apply(applied_event());
+        assert_eq!(transaction.status, Some(TransactionStatus::Applied));
+
+        transaction.apply(disputed_event(dec!(1.0)));
+        assert!(transaction.is_disputed());
+
+        // Still disputed while a part is held
+        transaction.apply(resolved_event(dec!(0.4)));
+        assert_eq!(transaction.status, Some(TransactionStatus::Disputed));
+
+        transaction.apply(resolved_event(dec!(0.6)));
+        assert_eq!(transaction.status, Some(TransactionStatus::Resolved));
+        assert!(!transaction.is_disputed());
+
+        transaction.apply(disputed_event(dec!(0.5)));
+        transaction.apply(TransactionEvent::DisputeChargedBack(
+            DisputeChargedBackPayload {
+                id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(0.5)),
+                payment_id: None,
+            },
+        ));
+        assert_eq!(transaction.status, Some(TransactionStatus::ChargedBack));
+    }
+
+    #[test]
+    fn test_capture_authorization() {
+        let capture = TransactionCommand::CaptureTransactionAuthorization(
+            CaptureTransactionAuthorizationPayload {
+                id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.0)),
+            },
+        );
+        let captured = TransactionEvent::AuthorizationCaptured(AuthorizationCapturedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            amount: Amount(dec!(1.0)),
+        });
+
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Authorization), applied_event()])
+            .when(capture.clone())
+            .then_expect_events(vec![captured.clone()]);
+        // Retried once the account took it
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![
+                recorded_event(TxType::Authorization),
+                applied_event(),
+                captured,
+            ])
+            .when(capture.clone())
+            .then_expect_events(vec![]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Deposit), applied_event()])
+            .when(capture)
+            .then_expect_error(TransactionError::AuthorizationNotFound);
+    }
+
+    #[test]
+    fn test_void_authorization() {
+        let void =
+            TransactionCommand::VoidTransactionAuthorization(VoidTransactionAuthorizationPayload {
+                id: TransactionId("tx-1".to_owned()),
+            });
+
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Authorization), applied_event()])
+            .when(void.clone())
+            .then_expect_events(vec![TransactionEvent::AuthorizationVoided(
+                AuthorizationVoidedPayload {
+                    id: TransactionId("tx-1".to_owned()),
+                },
+            )]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![
+                recorded_event(TxType::Authorization),
+                applied_event(),
+                expired_event(),
+            ])
+            .when(void)
+            .then_expect_error(TransactionError::AuthorizationExpired);
+    }
+
+    #[test]
+    fn test_expire_authorization() {
+        let expire = TransactionCommand::ExpireTransactionAuthorization(
+            ExpireTransactionAuthorizationPayload {
+                id: TransactionId("tx-1".to_owned()),
+            },
+        );
+
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Authorization), applied_event()])
+            .when(expire.clone())
+            .then_expect_events(vec![expired_event()]);
+        // Retried once the transaction went through, before the account did
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![
+                recorded_event(TxType::Authorization),
+                applied_event(),
+                expired_event(),
+            ])
+            .when(expire.clone())
+            .then_expect_events(vec![]);
+        TransactionTestFramework::with(TransactionServices {})
+            .given(vec![recorded_event(TxType::Authorization)])
+            .when(expire)
+            .then_expect_error(TransactionError::TransactionPending);
+    }
+
+    #[test]
+    fn test_not_recorded_rejected() {
+        let transaction = Transaction::default();
+
+        assert_eq!(
+            transaction.require_owned_by(&ClientId("cl-1".to_owned())),
+            Err(TransactionError::TransactionNotFound)
+        );
+    }
+
+    fn recorded_event(tx_type: TxType) -> TransactionEvent {
+        TransactionEvent::TransactionRecorded(TransactionRecordedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            client_id: ClientId("cl-1".to_owned()),
+            tx_type,
+            amount: Amount(dec!(1.23)),
+            currency: Currency::default(),
+            counterparty_id: None,
+        })
+    }
+
+    fn applied_event() -> TransactionEvent {
+        TransactionEvent::TransactionApplied(TransactionAppliedPayload {
+            id: TransactionId("tx-1".to_owned()),
+        })
+    }
+
+    fn voided_event() -> TransactionEvent {
+        TransactionEvent::TransactionVoided(TransactionVoidedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            reason: "account_locked".to_owned(),
+        })
+    }
+
+    fn expired_event() -> TransactionEvent {
+        TransactionEvent::AuthorizationExpired(AuthorizationExpiredPayload {
+            id: TransactionId("tx-1".to_owned()),
+        })
+    }
+
+    fn disputed_event(amount: Decimal) -> TransactionEvent {
+        TransactionEvent::TransactionDisputed(TransactionDisputedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            amount: Amount(amount),
+            payment_id: None,
+        })
+    }
+
+    fn resolved_event(amount: Decimal) -> TransactionEvent {
+        TransactionEvent::DisputeResolved(DisputeResolvedPayload {
+            id: TransactionId("tx-1".to_owned()),
+            amount: Amount(amount),
+            payment_id: None,
+        })
+    }
+
+    fn recorded(tx_type: TxType) -> Transaction {
+        let mut transaction = Transaction::default();
+        transaction.apply(recorded_event(tx_type));
+        transaction
+    }
+
+    fn applied(tx_type: TxType) -> Transaction {
+        let mut transaction = recorded(tx_type);
+        transaction.apply(applied_event());
+        transaction
+    }
 }
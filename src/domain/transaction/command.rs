@@ -1,15 +1,81 @@
 use serde::Deserialize;
 
-use crate::domain::props::{Amount, ClientId, TransactionId};
+use crate::domain::props::{Amount, ClientId, Currency, TransactionId, TxType};
 
 #[derive(Debug, Clone, Deserialize)]
 pub enum TransactionCommand {
     RecordTransaction(RecordTransactionPayload),
+    VoidTransaction(VoidTransactionPayload),
+    ApplyTransaction(ApplyTransactionPayload),
+    DisputeTransaction(DisputeTransactionPayload),
+    ResolveTransactionDispute(ResolveTransactionDisputePayload),
+    ChargebackTransactionDispute(ChargebackTransactionDisputePayload),
+    CaptureTransactionAuthorization(CaptureTransactionAuthorizationPayload),
+    VoidTransactionAuthorization(VoidTransactionAuthorizationPayload),
+    ExpireTransactionAuthorization(ExpireTransactionAuthorizationPayload),
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RecordTransactionPayload {
     pub id: TransactionId,
     pub client_id: ClientId,
+    pub tx_type: TxType,
     pub amount: Amount,
+    pub currency: Currency,
+    /// Credited client of a transfer
+    #[serde(default)]
+    pub counterparty_id: Option<ClientId>,
+}
+
+/// Compensation of a recorded transaction, which could not be applied to the account.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoidTransactionPayload {
+    pub id: TransactionId,
+    pub reason: String,
+}
+
+/// The transaction went through on the account(s).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApplyTransactionPayload {
+    pub id: TransactionId,
+}
+
+/// Follows the account dispute, without an amount the whole undisputed part.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DisputeTransactionPayload {
+    pub id: TransactionId,
+    pub amount: Option<Amount>,
+    /// Payment the transaction follows, a retried step of it is not applied again
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResolveTransactionDisputePayload {
+    pub id: TransactionId,
+    pub amount: Option<Amount>,
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChargebackTransactionDisputePayload {
+    pub id: TransactionId,
+    pub amount: Option<Amount>,
+    pub payment_id: Option<String>,
+}
+
+/// Follows the account capture, with the captured part of the authorization.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CaptureTransactionAuthorizationPayload {
+    pub id: TransactionId,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoidTransactionAuthorizationPayload {
+    pub id: TransactionId,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpireTransactionAuthorizationPayload {
+    pub id: TransactionId,
 }
@@ -3,6 +3,38 @@ use derive_more::Display;
 #[derive(Debug, PartialEq, Display)]
 pub enum TransactionError {
     DuplicateTransaction,
+    TransactionNotFound,
+    ClientMismatch,
+    NotDisputable,
+    TransactionVoided,
+    TransactionPending,
+    InvalidTransition,
+    DisputeNotFound,
+    DuplicateDispute,
+    DisputeAmountExceeded,
+    AuthorizationNotFound,
+    AuthorizationExpired,
+}
+
+impl TransactionError {
+    /// Machine readable reason, used in rejection reports.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionError::DuplicateTransaction => "duplicate_transaction",
+            TransactionError::TransactionNotFound => "transaction_not_found",
+            TransactionError::ClientMismatch => "client_mismatch",
+            TransactionError::NotDisputable => "not_disputable",
+            TransactionError::TransactionVoided => "transaction_voided",
+            TransactionError::TransactionPending => "transaction_pending",
+            TransactionError::InvalidTransition => "invalid_transition",
+            // Same reasons as the account gives, the transaction is checked before it
+            TransactionError::DisputeNotFound => "dispute_not_found",
+            TransactionError::DuplicateDispute => "duplicate_dispute",
+            TransactionError::DisputeAmountExceeded => "dispute_amount_exceeded",
+            TransactionError::AuthorizationNotFound => "authorization_not_found",
+            TransactionError::AuthorizationExpired => "authorization_expired",
+        }
+    }
 }
 
 impl std::error::Error for TransactionError {}
@@ -1,17 +1,33 @@
 use cqrs_es::DomainEvent;
 use serde::{Deserialize, Serialize};
 
-use crate::domain::props::{Amount, ClientId, TransactionId};
+use crate::domain::props::{Amount, ClientId, Currency, TransactionId, TxType};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TransactionEvent {
     TransactionRecorded(TransactionRecordedPayload),
+    TransactionVoided(TransactionVoidedPayload),
+    TransactionApplied(TransactionAppliedPayload),
+    TransactionDisputed(TransactionDisputedPayload),
+    DisputeResolved(DisputeResolvedPayload),
+    DisputeChargedBack(DisputeChargedBackPayload),
+    AuthorizationCaptured(AuthorizationCapturedPayload),
+    AuthorizationVoided(AuthorizationVoidedPayload),
+    AuthorizationExpired(AuthorizationExpiredPayload),
 }
 
 impl DomainEvent for TransactionEvent {
     fn event_type(&self) -> String {
         let event_type: &str = match self {
             TransactionEvent::TransactionRecorded(_) => "TransactionRecorded",
+            TransactionEvent::TransactionVoided(_) => "TransactionVoided",
+            TransactionEvent::TransactionApplied(_) => "TransactionApplied",
+            TransactionEvent::TransactionDisputed(_) => "TransactionDisputed",
+            TransactionEvent::DisputeResolved(_) => "DisputeResolved",
+            TransactionEvent::DisputeChargedBack(_) => "DisputeChargedBack",
+            TransactionEvent::AuthorizationCaptured(_) => "AuthorizationCaptured",
+            TransactionEvent::AuthorizationVoided(_) => "AuthorizationVoided",
+            TransactionEvent::AuthorizationExpired(_) => "AuthorizationExpired",
         };
         event_type.to_string()
     }
@@ -21,11 +37,69 @@ impl DomainEvent for TransactionEvent {
     }
 }
 
-// This implementation is naive for the purpose of exercise.
-// In real world scenario transaction event should include 2 accounts - debit & credit.
+// Only transfers have a second (credited) account, deposits and withdrawals
+// are between the client and the outside world.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TransactionRecordedPayload {
     pub id: TransactionId,
     pub client_id: ClientId,
+    pub tx_type: TxType,
     pub amount: Amount,
+    #[serde(default)]
+    pub currency: Currency,
+    /// Credited client of a transfer
+    #[serde(default)]
+    pub counterparty_id: Option<ClientId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionVoidedPayload {
+    pub id: TransactionId,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionAppliedPayload {
+    pub id: TransactionId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionDisputedPayload {
+    pub id: TransactionId,
+    pub amount: Amount,
+    /// Missing in events recorded before disputes ran as payments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DisputeResolvedPayload {
+    pub id: TransactionId,
+    pub amount: Amount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DisputeChargedBackPayload {
+    pub id: TransactionId,
+    pub amount: Amount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthorizationCapturedPayload {
+    pub id: TransactionId,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthorizationVoidedPayload {
+    pub id: TransactionId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuthorizationExpiredPayload {
+    pub id: TransactionId,
 }
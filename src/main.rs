@@ -2,173 +2,389 @@
 #![cfg_attr(test, allow(clippy::panic, clippy::unwrap_used, clippy::expect_used))]
 
 use std::{
-    fs,
-    path::Path,
-    str::FromStr,
+    sync::Arc,
     thread::{self, available_parallelism},
-    time::SystemTime,
 };
 
-use color_eyre::eyre::{OptionExt, Result, eyre};
-use crossbeam::channel::{Receiver, Sender, bounded};
+use clap::Parser;
+use color_eyre::eyre::{Result, eyre};
 use murmur2::{KAFKA_SEED, murmur2};
-use sqlx::{
-    SqlitePool,
-    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+use sqlx::SqlitePool;
+use tokio::{
+    sync::{
+        Barrier,
+        mpsc::{Receiver, Sender, channel},
+    },
+    task::JoinSet,
 };
-use tokio::task::JoinSet;
 use tracing::debug;
 
 use crate::{
-    cli::CliArgs, csv::CsvPaymentRecord, payments::PaymentsService,
-    query::account::print_accounts_csv,
+    cli::{Cli, Command, ProcessArgs},
+    csv::{CsvPaymentRecord, InputRow, PaymentRow, TxType},
+    domain::{
+        account::{aggregate::AccountServices, limits::LimitPolicy, risk::RiskRules},
+        fee::FeeSchedule,
+        props::InputClock,
+        transaction::error::TransactionError,
+    },
+    payments::PaymentsService,
+    query::account::print_accounts,
+    registry::{RowRegistry, TransactionRegistry, load_input_clock, save_input_clock},
+    rejects::{Rejects, start_rejects_writer},
+    store::Store,
 };
 
 pub(crate) mod cli;
+mod commands;
 mod csv;
 mod domain;
 mod payments;
 mod query;
+mod registry;
+mod rejects;
+mod store;
 
-// Event sourcing with sqlite backed event store will be used.
-// There will be a temp sqlite file generated per core like 'XDB-1761491588862857000-0.db'
-// Result account projections will also be stored in that same sqlite dbs.
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli_args = CliArgs::load()?;
+    match Cli::parse().command() {
+        Command::Process(args) => process(args).await,
+        Command::Accounts(args) => commands::accounts(args).await,
+        Command::History(args) => commands::history(args).await,
+        Command::Replay(args) => commands::replay(args).await,
+        Command::TrialBalance(args) => commands::trial_balance(args).await,
+        Command::Admin(args) => commands::admin(args).await,
+        Command::Validate(args) => commands::validate(args),
+        Command::Generate(args) => commands::generate(args),
+    }
+}
 
-    // We will have a channel per cpy core and will distribute processing in parallel.
+// Event sourcing with sqlite backed event store will be used.
+// There will be a temp sqlite file generated per core like 'XDB-1761491588862857000-0.db',
+// unless a persistent store directory is passed (see: Store).
+// Result account projections will also be stored in that same sqlite dbs.
+async fn process(args: ProcessArgs) -> Result<()> {
+    if args.input_file_paths.is_empty() {
+        return Err(eyre!("Input file not passed"));
+    }
+    let csv_rows = csv::read_input::<CsvPaymentRecord>(&args.input_file_paths, args.strict)?;
+    let (rejects, rejects_writer) =
+        start_rejects_writer(args.rejects.as_deref(), args.rejects_format)?;
+
+    // We will have a channel per cpy core (or per requested worker) and will distribute processing in parallel.
     // There will be 1 sender thread which will read csv and send each csv row to one of the channels (see: get_channel_by_client_id).
     // After the processing, the results from all processors will be printed out in csv format.
     let cpu_cores = available_parallelism()
         .map_err(|_| eyre!("unable to get core count"))?
         .get();
-    let channels: Vec<(Sender<CsvPaymentRecord>, Receiver<CsvPaymentRecord>)> =
-        (0..cpu_cores).map(|_| bounded(100)).collect();
-    let senders = channels
-        .clone()
-        .into_iter()
-        .map(|(s, _)| s)
-        .collect::<Vec<_>>();
-    let receivers = channels.into_iter().map(|(_, r)| r).collect::<Vec<_>>();
+    let store = Store::open(args.store.as_deref())?;
+    let partitions = store.partition_count(args.engine.workers.map(|w| w.get()), cpu_cores)?;
+    let pools = store.connect(partitions).await?;
+
+    let processed = process_partitions(&args, csv_rows, rejects, rejects_writer, &pools).await;
+
+    // Temp dbs have to be removed even when the run failed
+    store.close(&pools).await?;
+
+    processed
+}
+
+/// Processes the input rows against the connected partitions and prints out the resulting accounts.
+async fn process_partitions(
+    args: &ProcessArgs,
+    csv_rows: impl Iterator<Item = InputRow<Result<CsvPaymentRecord>>> + Send + 'static,
+    rejects: Rejects,
+    rejects_writer: Option<thread::JoinHandle<Result<()>>>,
+    pools: &[SqlitePool],
+) -> Result<()> {
+    let partitions = pools.len();
+
+    // Transaction ids already recorded in a persistent store must not be accepted again,
+    // rows already processed against it are skipped.
+    let mut registry = TransactionRegistry::default();
+    let mut row_registry = RowRegistry::default();
+    let account_services = AccountServices {
+        dispute_policy: args.dispute_policy,
+        precision: args.precision_policy(),
+        limits: match &args.limits {
+            Some(path) => LimitPolicy::load(path)?,
+            None => LimitPolicy::default(),
+        },
+        risk: match &args.risk_rules {
+            Some(path) => Arc::new(RiskRules::load(path)?),
+            None => Arc::new(RiskRules::default()),
+        },
+    };
+    let fees = match &args.fees {
+        Some(path) => FeeSchedule::load(path)?,
+        None => FeeSchedule::default(),
+    };
+    let mut services = Vec::with_capacity(partitions);
+    for pool in pools {
+        services.push(
+            PaymentsService::new(
+                pool.clone(),
+                account_services.clone(),
+                fees.clone(),
+                args.authorization_ttl(),
+            )
+            .await,
+        );
+    }
+    // Transfers credit clients of any partition
+    let partition_accounts: Vec<_> = services.iter().map(|s| s.accounts()).collect();
+    for service in &mut services {
+        service.connect_partitions(partition_accounts.clone());
+    }
+    // Payments interrupted in a previous run are finished before any new ones
+    for (service, pool) in services.iter().zip(pools) {
+        service.resume_payments().await?;
+        registry.seed(pool).await?;
+        row_registry.seed(pool).await?;
+    }
+    // Authorizations expire by rows and time counted across the runs against the store
+    let clock = load_input_clock(pools).await?;
+
+    let (senders, receivers): (Vec<Sender<PartitionWork>>, Vec<Receiver<PartitionWork>>) = (0
+        ..partitions)
+        .map(|_| channel(args.engine.channel_capacity.get()))
+        .unzip();
 
     // Start sender thread which reads csv and distributes rows to channels by client_id
-    let sender_thread = start_sender_thread(cli_args, senders, cpu_cores);
+    let sender_thread = start_sender_thread(
+        csv_rows,
+        senders,
+        registry,
+        row_registry,
+        clock,
+        rejects.clone(),
+        args.strict,
+    );
 
-    // Start receiver threads, one per core
-    let receiver_threads = start_receiver_threads(&receivers, cpu_cores)?;
+    // Start receiver threads, one per partition
+    let receiver_threads = start_receiver_threads(receivers, services, rejects);
 
-    sender_thread
+    // In strict mode the sender stops at the first malformed row, what was sent so far is still processed
+    let (clock, sent) = sender_thread
         .join()
-        .map_err(|_| eyre!("Error waiting for senders to finish"))?;
+        .unwrap_or_else(|_| (clock, Err(eyre!("Error waiting for senders to finish"))));
 
-    let receiver_results = receiver_threads.join_all().await;
+    let services = receiver_threads.join_all().await;
+    let finished = finish_partitions(&services, pools, &clock).await;
 
-    // print out all resulting csvs
-    println!("client,available,held,total,locked");
-    for result_db in &receiver_results {
-        print_accounts_csv(result_db).await?;
+    // All rejects handles are dropped by now, so the writer can finish
+    if let Some(rejects_writer) = rejects_writer {
+        rejects_writer
+            .join()
+            .map_err(|_| eyre!("Error waiting for rejects writer to finish"))??;
     }
 
-    cleanup_temp_dbs(&receiver_results)?;
+    // print out all resulting accounts
+    sent.and(finished)?;
+    print_accounts(pools, &args.output).await
+}
 
-    Ok(())
+/// Work of a partition, in input order.
+enum PartitionWork {
+    /// Input row along with the clock once it is read, and the barrier of a transfer to another partition
+    Row(PaymentRow, InputClock, Option<Arc<Barrier>>),
+    /// Place of a transfer in the input, in the partition of its recipient
+    TransferBarrier(Arc<Barrier>),
+    /// Row the sender rejected, recorded as processed in the partition of its client
+    Rejected(PaymentRow),
 }
 
-/// Starts sender thread which reads csv and distributes rows to channels by client_id for receivers to process
+/// Starts sender thread which reads csv and distributes rows to channels by client_id for receivers to process.
+/// Transaction ids are checked for uniqueness here, as receivers only see their own partition.
+/// Rows already processed (in a previous run, or earlier with the same key) are skipped,
+/// rows rejected as duplicates are marked processed as well.
+/// The clock is moved by each row sent, it is returned as it ended up even when the thread failed.
+/// In strict mode a malformed row fails the thread instead of being rejected.
+/// A transfer to a client of another partition is sent to both partitions, see `transfer_barrier`.
 fn start_sender_thread(
-    cli_args: CliArgs,
-    senders: Vec<Sender<CsvPaymentRecord>>,
-    cpu_cores: usize,
-) -> thread::JoinHandle<()> {
+    csv_rows: impl Iterator<Item = InputRow<Result<CsvPaymentRecord>>> + Send + 'static,
+    senders: Vec<Sender<PartitionWork>>,
+    mut registry: TransactionRegistry,
+    mut row_registry: RowRegistry,
+    mut clock: InputClock,
+    rejects: Rejects,
+    strict: bool,
+) -> thread::JoinHandle<(InputClock, Result<()>)> {
     thread::spawn(move || {
-        #[allow(clippy::unwrap_used)]
-        let csv_rows = csv::read_input::<csv::CsvPaymentRecord>(&cli_args.input_file_path).unwrap();
-        for row_result in csv_rows {
-            match row_result {
-                Ok(row) => {
-                    if row.client_id.is_empty() {
-                        debug!("No client_id in a row: {:?}, skipping", row);
+        let sent = 'rows: {
+            for row in csv_rows {
+                let validated = row.record.and_then(|r| {
+                    match strict {
+                        true => r.validate_strict(),
+                        false => r.validate(),
+                    }
+                    .map(|_| r)
+                });
+                let record = match validated {
+                    Ok(record) => record,
+                    Err(e) if strict => {
+                        break 'rows Err(e.wrap_err(format!(
+                            "Invalid row in {} line {}",
+                            row.source.path, row.line
+                        )));
+                    }
+                    Err(e) => {
+                        rejects.reject(&row.source, row.line, &row.raw, &e);
                         continue;
                     }
-                    let client_worker = get_channel_by_client_id(cpu_cores as u32, &row.client_id);
-                    let sender = &senders[client_worker];
+                };
+                let row = PaymentRow {
+                    source: row.source,
+                    line: row.line,
+                    raw: row.raw,
+                    record,
+                };
+                if let Some(row_key) = row.idempotency_key()
+                    && !row_registry.claim(&row_key)
+                {
+                    debug!("Skipping already processed row {}", row_key);
+                    continue;
+                }
+                let record = &row.record;
+                let client_worker =
+                    get_channel_by_client_id(senders.len() as u32, &record.client_id);
+                if matches!(
+                    record.tx_type,
+                    TxType::Deposit | TxType::Withdrawal | TxType::Transfer | TxType::Authorize
+                ) && !registry.claim(&record.tx_id)
+                {
+                    rejects.reject(
+                        &row.source,
+                        row.line,
+                        &row.raw,
+                        &eyre!(TransactionError::DuplicateTransaction),
+                    );
                     #[allow(clippy::unwrap_used)]
-                    sender.send(row).unwrap();
+                    senders[client_worker]
+                        .blocking_send(PartitionWork::Rejected(row))
+                        .unwrap();
+                    continue;
                 }
-                Err(e) => debug!("Error parsing row: {}", e),
+                clock.tick(record.timestamp);
+                let barrier = transfer_barrier(&senders, client_worker, record);
+                let sender = &senders[client_worker];
+                #[allow(clippy::unwrap_used)]
+                sender
+                    .blocking_send(PartitionWork::Row(row, clock, barrier))
+                    .unwrap();
             }
-        }
+
+            Ok(())
+        };
+
+        (clock, sent)
     })
 }
 
-/// Starts receiver threads, one per core, reads csv rows and passes for processing to PaymentService.
-/// Returns a reference to resulting sqlite db.
+/// A transfer credits the recipient from the partition of the sender, so the recipient partition has to be at
+/// the same place in the input while it is done. Its partition gets a barrier in input order, which both wait at
+/// before the transfer and again once it is done, so balances do not depend on the number of partitions.
+fn transfer_barrier(
+    senders: &[Sender<PartitionWork>],
+    client_worker: usize,
+    record: &CsvPaymentRecord,
+) -> Option<Arc<Barrier>> {
+    let to = record
+        .to
+        .as_deref()
+        .filter(|_| record.tx_type == TxType::Transfer)?;
+    let recipient_worker = get_channel_by_client_id(senders.len() as u32, to);
+    if recipient_worker == client_worker {
+        return None;
+    }
+
+    let barrier = Arc::new(Barrier::new(2));
+    #[allow(clippy::unwrap_used)]
+    senders[recipient_worker]
+        .blocking_send(PartitionWork::TransferBarrier(barrier.clone()))
+        .unwrap();
+
+    Some(barrier)
+}
+
+/// Starts receiver threads, one per partition, reads csv rows and passes for processing to PaymentService.
+/// Authorizations due by the clock of a row expire before the row is processed.
+/// Each thread gives its service back once its channel is closed.
 fn start_receiver_threads(
-    receivers: &[Receiver<CsvPaymentRecord>],
-    cpu_cores: usize,
-) -> Result<JoinSet<SqlitePool>> {
+    receivers: Vec<Receiver<PartitionWork>>,
+    services: Vec<PaymentsService>,
+    rejects: Rejects,
+) -> JoinSet<PaymentsService> {
     let mut receiver_threads = JoinSet::new();
-    let db_file_suffix = epoch_nanos()?;
-    for core_idx in 0..cpu_cores {
-        let receivers = receivers.to_owned();
+    for (mut receiver, payments) in receivers.into_iter().zip(services) {
+        let rejects = rejects.clone();
         receiver_threads.spawn(async move {
-            let receiver = &receivers[core_idx];
-
-            #[allow(clippy::unwrap_used)]
-            let pool = sqlite_pool(&sqlite_uri(db_file_suffix, core_idx))
-                .await
-                .unwrap();
-            let payments = PaymentsService::new(pool.clone()).await;
-
-            while let Ok(row) = receiver.recv() {
-                let _ = &payments
-                    .handle(row)
-                    .await
-                    .inspect_err(|e| debug!("Error processing row: {}", e));
+            while let Some(work) = receiver.recv().await {
+                let (row, clock, barrier) = match work {
+                    PartitionWork::Row(row, clock, barrier) => (row, clock, barrier),
+                    // The recipient is held while the sender partition transfers to it
+                    PartitionWork::TransferBarrier(barrier) => {
+                        barrier.wait().await;
+                        barrier.wait().await;
+                        continue;
+                    }
+                    // Rejected by the sender already, it would only be rejected again
+                    PartitionWork::Rejected(row) => {
+                        if let Some(row_key) = row.idempotency_key() {
+                            let _ = payments.mark_processed(&row_key).await.inspect_err(|e| {
+                                rejects.reject(&row.source, row.line, &row.raw, e)
+                            });
+                        }
+                        continue;
+                    }
+                };
+                let row_key = row.idempotency_key();
+                let expired = payments.expire_authorizations(&clock).await;
+                if let Some(barrier) = &barrier {
+                    barrier.wait().await;
+                }
+                let handled = payments
+                    .handle(row.record, &clock, row_key.as_deref())
+                    .await;
+                if let Some(barrier) = &barrier {
+                    barrier.wait().await;
+                }
+                // Rejected rows are processed as well, they would only be rejected again
+                let marked = match &row_key {
+                    Some(row_key) => payments.mark_processed(row_key).await,
+                    None => Ok(()),
+                };
+                let _ = expired
+                    .and(handled)
+                    .and(marked)
+                    .inspect_err(|e| rejects.reject(&row.source, row.line, &row.raw, e));
             }
-            pool
+
+            payments
         });
     }
 
-    Ok(receiver_threads)
+    receiver_threads
 }
 
-async fn sqlite_pool(sqlite_uri: &str) -> Result<SqlitePool> {
-    let opts = SqliteConnectOptions::from_str(sqlite_uri)?
-        .journal_mode(SqliteJournalMode::Wal)
-        .synchronous(sqlx::sqlite::SqliteSynchronous::Off);
-    SqlitePool::connect_with(opts).await.map_err(|e| eyre!(e))
-}
-
-#[allow(clippy::unwrap_used)]
-fn sqlite_uri(suffix: u128, core_idx: usize) -> String {
-    format!("sqlite:XDB-{}-{}.db?mode=rwc", suffix, core_idx)
-}
+/// Partitions only see the clock of their own rows, so authorizations due by the later rows of the others
+/// expire once all rows are processed. The clock is saved for the next run.
+async fn finish_partitions(
+    services: &[PaymentsService],
+    pools: &[SqlitePool],
+    clock: &InputClock,
+) -> Result<()> {
+    for payments in services {
+        payments.expire_authorizations(clock).await?;
+    }
+    for pool in pools {
+        save_input_clock(pool, clock).await?;
+    }
 
-fn epoch_nanos() -> Result<u128> {
-    Ok(SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .map_err(|e| eyre!(e))?
-        .as_nanos())
+    Ok(())
 }
 
 /// Calculate partition/channel for parallelising work and keeping the same client in the same work partition/channel
 fn get_channel_by_client_id(partition_count: u32, client_id: &str) -> usize {
     (murmur2(client_id.as_bytes(), KAFKA_SEED) % partition_count) as usize
 }
-
-fn cleanup_temp_dbs(pools: &[SqlitePool]) -> Result<()> {
-    for pool in pools {
-        let options = pool.connect_options();
-        let db_path = options
-            .get_filename()
-            .to_str()
-            .ok_or_eyre("no db file name")?;
-        let _ = fs::remove_file(Path::new(db_path));
-        let _ = fs::remove_file(Path::new(&format!("{}-shm", db_path)));
-        let _ = fs::remove_file(Path::new(&format!("{}-wal", db_path)));
-    }
-
-    Ok(())
-}
@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
-use color_eyre::eyre::{OptionExt, Result, eyre};
-use cqrs_es::{CqrsFramework, EventStore, persist::PersistedEventStore};
+use color_eyre::eyre::{Report, Result, eyre};
+use cqrs_es::{AggregateError, CqrsFramework, EventStore, persist::PersistedEventStore};
 use rust_decimal::Decimal;
 use sqlite_es::{SqliteEventRepository, SqliteViewRepository, init_tables, sqlite_aggregate_cqrs};
-use sqlx::{Pool, Sqlite};
+use sqlx::{Pool, Row, Sqlite};
+use tokio::sync::Mutex;
 use tracing::debug;
 
 use crate::{
@@ -13,199 +14,936 @@ use crate::{
         account::{
             aggregate::{Account, AccountServices, acc_aggregate_id},
             command::{
-                AccountCommand, ChargebackDisputePayload, DepositAccountPayload,
-                DisputeFundsPayload, ResolveDisputePayload, WithdrawAccountPayload,
+                AccountCommand, AuthorizeFundsPayload, CaptureAuthorizationPayload,
+                ChargebackDisputePayload, CollectFeePayload, DepositAccountPayload,
+                DisputeFundsPayload, DisputeWithdrawalPayload, ExpireAuthorizationPayload,
+                FreezeAccountPayload, ReceiveTransferPayload, ResolveDisputePayload,
+                RevertTransferPayload, SendTransferPayload, VoidAuthorizationPayload,
+                WithdrawAccountPayload,
             },
+            error::AccountError,
+            precision::PrecisionPolicy,
+            risk::{HOLD_REASON, SCREENING_OPERATOR},
+        },
+        fee::{FeeKind, FeeSchedule, HOUSE_CLIENT_ID},
+        payment::{
+            aggregate::{
+                PAYMENT_AGGREGATE_PREFIX, Payment, PaymentAction, PaymentServices, PaymentStep,
+                payment_aggregate_id,
+            },
+            command::{
+                CompensatePaymentPayload, CompleteStepPayload, FinishPaymentPayload,
+                PaymentCommand, StartPaymentPayload,
+            },
+            error::PaymentError,
+        },
+        props::{
+            Amount, AuthorizationTtl, ClientId, Currency, Expiry, InputClock, TransactionId, TxType,
         },
-        props::{Amount, ClientId, TransactionId, TxType},
         transaction::{
             aggregate::{Transaction, TransactionServices, tx_aggregate_id},
-            command::{RecordTransactionPayload, TransactionCommand},
+            command::{
+                ApplyTransactionPayload, CaptureTransactionAuthorizationPayload,
+                ChargebackTransactionDisputePayload, DisputeTransactionPayload,
+                ExpireTransactionAuthorizationPayload, RecordTransactionPayload,
+                ResolveTransactionDisputePayload, TransactionCommand,
+                VoidTransactionAuthorizationPayload, VoidTransactionPayload,
+            },
+            error::TransactionError,
         },
     },
-    query::account::{AccountQueryRepository, AccountView, init_accounts_table},
+    get_channel_by_client_id,
+    query::{
+        account::{AccountQueryRepository, AccountView, init_accounts_table},
+        authorization::{
+            AuthorizationQuery, due_authorizations, earliest_expiry, init_authorizations_table,
+        },
+        journal::{JournalQuery, init_journal_table},
+    },
+    registry::{init_input_clock_table, init_processed_rows_table, mark_processed},
+    rejects::reason_code,
 };
 
+pub type AccountCqrs = CqrsFramework<Account, PersistedEventStore<SqliteEventRepository, Account>>;
+
 /// This is an orchestrator service coordinating actions between 2 domains - Transaction and Account.
-/// It should be treated as a naive SAGAs implementation, so should be improved for a production use -
-/// to have atomic steps and backed by storage for the redundancy.
+/// Deposits, withdrawals, transfers and authorizations, and disputes, resolves, chargebacks, captures and voids of them,
+/// are run as `Payment` processes, persisted step by step with compensations for rejected steps, so an interrupted run
+/// can be resumed (see: resume_payments).
 pub struct PaymentsService {
-    account_cqrs: CqrsFramework<Account, PersistedEventStore<SqliteEventRepository, Account>>,
+    sqlite_pool: Pool<Sqlite>,
+    // Accounts are shared with the other partitions, which credit transfers to them.
+    // Each command holds the lock, so those never race the partition's own commands.
+    account_cqrs: Arc<Mutex<AccountCqrs>>,
+    /// Accounts of all the partitions, in partition order
+    partitions: Vec<Arc<Mutex<AccountCqrs>>>,
     transaction_cqrs:
         CqrsFramework<Transaction, PersistedEventStore<SqliteEventRepository, Transaction>>,
     transactions_store: PersistedEventStore<SqliteEventRepository, Transaction>,
+    payment_cqrs: CqrsFramework<Payment, PersistedEventStore<SqliteEventRepository, Payment>>,
+    payments_store: PersistedEventStore<SqliteEventRepository, Payment>,
+    fees: FeeSchedule,
+    /// Precision of the accounts, fees are rounded to it
+    precision: PrecisionPolicy,
+    authorization_ttl: AuthorizationTtl,
+    /// Earliest expiry of the open authorizations, loaded from the store when not known
+    earliest_expiry: Mutex<Option<Expiry>>,
 }
 
 impl PaymentsService {
-    pub async fn new(sqlite_pool: Pool<Sqlite>) -> Self {
-        #[allow(clippy::expect_used)]
-        init_tables(&sqlite_pool)
-            .await
-            .expect("Failed to initialize DB tables");
+    pub async fn new(
+        sqlite_pool: Pool<Sqlite>,
+        account_services: AccountServices,
+        fees: FeeSchedule,
+        authorization_ttl: AuthorizationTtl,
+    ) -> Self {
+        // A persistent store is initialized only on the first run
+        if !events_table_exists(&sqlite_pool).await {
+            #[allow(clippy::expect_used)]
+            init_tables(&sqlite_pool)
+                .await
+                .expect("Failed to initialize DB tables");
+        }
         init_accounts_table(&sqlite_pool).await;
+        init_journal_table(&sqlite_pool).await;
+        init_processed_rows_table(&sqlite_pool).await;
+        init_authorizations_table(&sqlite_pool).await;
+        init_input_clock_table(&sqlite_pool).await;
 
         let view_repo =
             SqliteViewRepository::<AccountView, Account>::new("accounts", sqlite_pool.clone());
         let account_query = AccountQueryRepository::new(Arc::new(view_repo));
-        let account_cqrs = sqlite_aggregate_cqrs(
+        let precision = account_services.precision.clone();
+        let account_cqrs = Arc::new(Mutex::new(sqlite_aggregate_cqrs(
             sqlite_pool.clone(),
-            vec![Box::new(account_query)],
-            AccountServices {},
-        );
+            vec![
+                Box::new(account_query),
+                Box::new(JournalQuery::new(sqlite_pool.clone())),
+                Box::new(AuthorizationQuery::new(sqlite_pool.clone())),
+            ],
+            account_services,
+        )));
 
         let transaction_cqrs =
             sqlite_aggregate_cqrs(sqlite_pool.clone(), vec![], TransactionServices {});
-        let transactions_store =
-            PersistedEventStore::new_aggregate_store(SqliteEventRepository::new(sqlite_pool));
+        let transactions_store = PersistedEventStore::new_aggregate_store(
+            SqliteEventRepository::new(sqlite_pool.clone()),
+        );
+
+        let payment_cqrs = sqlite_aggregate_cqrs(sqlite_pool.clone(), vec![], PaymentServices {});
+        let payments_store = PersistedEventStore::new_aggregate_store(SqliteEventRepository::new(
+            sqlite_pool.clone(),
+        ));
 
         PaymentsService {
+            sqlite_pool,
+            partitions: vec![account_cqrs.clone()],
             account_cqrs,
             transaction_cqrs,
             transactions_store,
+            payment_cqrs,
+            payments_store,
+            fees,
+            precision,
+            authorization_ttl,
+            earliest_expiry: Mutex::new(None),
         }
     }
 
-    pub async fn handle(&self, r: csv::CsvPaymentRecord) -> Result<()> {
+    pub fn accounts(&self) -> Arc<Mutex<AccountCqrs>> {
+        self.account_cqrs.clone()
+    }
+
+    /// Makes accounts of all the partitions reachable for transfers, the service alone only knows its own.
+    pub fn connect_partitions(&mut self, partitions: Vec<Arc<Mutex<AccountCqrs>>>) {
+        self.partitions = partitions;
+    }
+
+    /// The clock is the one of the row, authorizations made by it expire and withdrawal limits look back relative to it.
+    /// The row key, when the row has one, identifies the actions (e.g. disputes or captures) it starts.
+    pub async fn handle(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
         match r.tx_type {
-            csv::TxType::Deposit => self.handle_deposit(r).await?,
-            csv::TxType::Withdrawal => self.handle_withdrawal(r).await?,
-            csv::TxType::Dispute => self.handle_dispute_funds(r).await?,
-            csv::TxType::Resolve => self.handle_resolve_dispute(r).await?,
-            csv::TxType::Chargeback => self.handle_chargeback_dispute(r).await?,
+            csv::TxType::Deposit => self.handle_deposit(r, clock).await?,
+            csv::TxType::Withdrawal => self.handle_withdrawal(r, clock).await?,
+            csv::TxType::Dispute => self.handle_dispute_funds(r, clock, row_key).await?,
+            csv::TxType::Resolve => self.handle_resolve_dispute(r, clock, row_key).await?,
+            csv::TxType::Chargeback => self.handle_chargeback_dispute(r, clock, row_key).await?,
+            csv::TxType::Transfer => self.handle_transfer(r, clock).await?,
+            csv::TxType::Authorize => self.handle_authorize(r, clock).await?,
+            csv::TxType::Capture => self.handle_capture(r, clock, row_key).await?,
+            csv::TxType::Void => self.handle_void(r, clock, row_key).await?,
         }
 
         Ok(())
     }
 
-    pub async fn handle_deposit(&self, r: csv::CsvPaymentRecord) -> Result<()> {
-        let amount = require_amount(r.amount, &r.tx_id)?;
+    pub async fn handle_deposit(&self, r: csv::CsvPaymentRecord, clock: &InputClock) -> Result<()> {
+        let amount = csv::require_amount(r.amount, &r.tx_id)?;
 
-        // If tx recording fails (e.g. duplicate exists),
-        //   then subsequent account operation will not proceed.
-        self.transaction_cqrs
-            .execute(
-                &format!("Transaction-{}", r.tx_id),
-                TransactionCommand::RecordTransaction(RecordTransactionPayload {
-                    client_id: ClientId(r.client_id.to_owned()),
-                    id: TransactionId(r.tx_id.to_owned()),
-                    amount: Amount(amount),
-                }),
-            )
-            .await?;
+        self.start_payment(&r, TxType::Deposit, amount, None, clock)
+            .await
+    }
 
-        self.account_cqrs
-            .execute(
-                &format!("Account-{}", r.client_id),
-                AccountCommand::DepositAccount(DepositAccountPayload {
-                    client_id: ClientId(r.client_id),
-                    transaction_id: TransactionId(r.tx_id.to_owned()),
-                    amount: Amount(amount),
+    pub async fn handle_withdrawal(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+    ) -> Result<()> {
+        let amount = csv::require_amount(r.amount, &r.tx_id)?;
+
+        self.start_payment(&r, TxType::Withdrawal, amount, None, clock)
+            .await
+    }
+
+    /// Disputes, resolves and chargebacks are checked against the transaction before they start,
+    /// so once the account took one, the transaction can only follow it.
+    pub async fn handle_dispute_funds(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
+        debug!("Handling dispute: {:?}", r);
+        let transaction = require_transaction(&self.transactions_store, &r.tx_id)
+            .await
+            .inspect_err(|e| debug!("Error retrieving tx: {}", e))?;
+
+        transaction.require_owned_by(&ClientId(r.client_id.to_owned()))?;
+        transaction.require_dispute_part(r.amount.map(Amount))?;
+
+        self.start_action_payment(
+            &r,
+            PaymentAction::Dispute,
+            &transaction,
+            None,
+            clock,
+            row_key,
+        )
+        .await
+    }
+
+    pub async fn handle_resolve_dispute(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
+        let transaction = require_transaction(&self.transactions_store, &r.tx_id).await?;
+        transaction.require_owned_by(&ClientId(r.client_id.to_owned()))?;
+        transaction.require_disputed_part(r.amount.map(Amount))?;
+
+        self.start_action_payment(
+            &r,
+            PaymentAction::Resolve,
+            &transaction,
+            None,
+            clock,
+            row_key,
+        )
+        .await
+    }
+
+    pub async fn handle_chargeback_dispute(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
+        let transaction = require_transaction(&self.transactions_store, &r.tx_id).await?;
+        transaction.require_owned_by(&ClientId(r.client_id.to_owned()))?;
+        let amount = transaction.require_disputed_part(r.amount.map(Amount))?;
+
+        // Charged on the part charged back, only a deposit chargeback costs the client
+        let fee = match transaction.tx_type {
+            Some(TxType::Deposit) => self.fee(FeeKind::Chargeback, *amount, &transaction.currency),
+            _ => None,
+        };
+
+        self.start_action_payment(
+            &r,
+            PaymentAction::Chargeback,
+            &transaction,
+            fee,
+            clock,
+            row_key,
+        )
+        .await
+    }
+
+    /// The sender is debited first, then the recipient, which may be in another partition, is credited.
+    /// When the credit fails, the debit is reverted, so both balances end up untouched.
+    pub async fn handle_transfer(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+    ) -> Result<()> {
+        let amount = csv::require_amount(r.amount, &r.tx_id)?;
+        let to = csv::require_recipient(r.to.as_deref(), &r.tx_id)?.to_owned();
+
+        self.start_payment(&r, TxType::Transfer, amount, Some(ClientId(to)), clock)
+            .await
+    }
+
+    pub async fn handle_authorize(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+    ) -> Result<()> {
+        let amount = csv::require_amount(r.amount, &r.tx_id)?;
+
+        self.start_payment(&r, TxType::Authorization, amount, None, clock)
+            .await
+    }
+
+    /// The withdrawal fee is charged on the captured part of the authorization.
+    /// Captures and voids are checked against the transaction before they start, like disputes.
+    pub async fn handle_capture(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
+        let transaction = require_transaction(&self.transactions_store, &r.tx_id).await?;
+        transaction.require_owned_by(&ClientId(r.client_id.to_owned()))?;
+        transaction.require_open_authorization()?;
+        let fee = self.fee(
+            FeeKind::Withdrawal,
+            r.amount.unwrap_or(transaction.amount),
+            &transaction.currency,
+        );
+
+        self.start_action_payment(
+            &r,
+            PaymentAction::Capture,
+            &transaction,
+            fee,
+            clock,
+            row_key,
+        )
+        .await
+    }
+
+    pub async fn handle_void(
+        &self,
+        r: csv::CsvPaymentRecord,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
+        let transaction = require_transaction(&self.transactions_store, &r.tx_id).await?;
+        transaction.require_owned_by(&ClientId(r.client_id.to_owned()))?;
+        transaction.require_open_authorization()?;
+
+        self.start_action_payment(&r, PaymentAction::Void, &transaction, None, clock, row_key)
+            .await
+    }
+
+    /// Releases the funds of the partition authorizations due by the clock.
+    /// The transaction expires first, so one interrupted before the account is still due in the next run.
+    /// None are looked up until the clock passes the earliest expiry, which is loaded again after they are.
+    pub async fn expire_authorizations(&self, clock: &InputClock) -> Result<()> {
+        let mut known_expiry = self.earliest_expiry.lock().await;
+        let expiry = match *known_expiry {
+            Some(expiry) => expiry,
+            None => *known_expiry.insert(earliest_expiry(&self.sqlite_pool).await?),
+        };
+        if !expiry.is_due(clock) {
+            return Ok(());
+        }
+        *known_expiry = None;
+        drop(known_expiry);
+
+        for (client_id, transaction_id) in due_authorizations(&self.sqlite_pool, clock).await? {
+            self.transaction_cqrs
+                .execute(
+                    &tx_aggregate_id(&transaction_id),
+                    TransactionCommand::ExpireTransactionAuthorization(
+                        ExpireTransactionAuthorizationPayload {
+                            id: transaction_id.clone(),
+                        },
+                    ),
+                )
+                .await?;
+            execute_account(
+                &self.account_cqrs,
+                &client_id.to_owned(),
+                AccountCommand::ExpireAuthorization(ExpireAuthorizationPayload {
+                    client_id,
+                    transaction_id,
+                    clock: *clock,
                 }),
             )
             .await?;
+        }
 
         Ok(())
     }
 
-    pub async fn handle_withdrawal(&self, r: csv::CsvPaymentRecord) -> Result<()> {
-        let amount = require_amount(r.amount, &r.tx_id)?;
+    /// Payment is persisted before any of its steps are taken.
+    async fn start_payment(
+        &self,
+        r: &csv::CsvPaymentRecord,
+        tx_type: TxType,
+        amount: Decimal,
+        counterparty_id: Option<ClientId>,
+        clock: &InputClock,
+    ) -> Result<()> {
+        let currency = row_currency(r).unwrap_or_default();
+        let fee = match tx_type {
+            TxType::Deposit => self.fee(FeeKind::Deposit, amount, &currency),
+            TxType::Withdrawal => self.fee(FeeKind::Withdrawal, amount, &currency),
+            // Transfers are free, authorizations are charged when captured
+            TxType::Transfer | TxType::Authorization => None,
+        };
+        let expiry =
+            (tx_type == TxType::Authorization).then(|| self.authorization_ttl.expiry(clock));
+        if let (Some(expiry), Some(known_expiry)) =
+            (&expiry, self.earliest_expiry.lock().await.as_mut())
+        {
+            *known_expiry = known_expiry.earliest(expiry);
+        }
 
-        // If tx recording fails (e.g. duplicate exists),
-        //   then subsequent account operation will not proceed.
-        self.transaction_cqrs
+        self.payment_cqrs
             .execute(
-                &format!("Transaction-{}", r.tx_id),
-                TransactionCommand::RecordTransaction(RecordTransactionPayload {
-                    client_id: ClientId(r.client_id.to_owned()),
+                &payment_aggregate_id(&r.tx_id),
+                PaymentCommand::StartPayment(StartPaymentPayload {
                     id: TransactionId(r.tx_id.to_owned()),
-                    amount: Amount(amount),
+                    client_id: ClientId(r.client_id.to_owned()),
+                    tx_type,
+                    amount: Some(Amount(amount)),
+                    currency,
+                    counterparty_id,
+                    fee,
+                    expiry,
+                    clock: Some(*clock),
+                    action: None,
                 }),
             )
             .await?;
 
-        let _ = self
-            .account_cqrs
+        self.run_payment(&r.tx_id).await
+    }
+
+    /// An action acts in the currency of the transaction, unless the row names another one to be rejected.
+    /// A row processed again after a crash, before it was marked processed, finds its payment already started
+    /// (and resumed at the start of the run), so it goes through once.
+    async fn start_action_payment(
+        &self,
+        r: &csv::CsvPaymentRecord,
+        action: PaymentAction,
+        transaction: &Transaction,
+        fee: Option<Amount>,
+        clock: &InputClock,
+        row_key: Option<&str>,
+    ) -> Result<()> {
+        let payment_id = action_payment_id(&r.tx_id, action, row_key, clock);
+
+        let started = self
+            .payment_cqrs
             .execute(
-                &format!("Account-{}", r.client_id),
-                AccountCommand::WithdrawAccount(WithdrawAccountPayload {
-                    client_id: ClientId(r.client_id),
-                    transaction_id: TransactionId(r.tx_id.to_owned()),
-                    amount: Amount(amount),
+                &payment_aggregate_id(&payment_id),
+                PaymentCommand::StartPayment(StartPaymentPayload {
+                    id: TransactionId(r.tx_id.to_owned()),
+                    client_id: ClientId(r.client_id.to_owned()),
+                    tx_type: transaction
+                        .tx_type
+                        .ok_or(eyre!(TransactionError::TransactionNotFound))?,
+                    amount: r.amount.map(Amount),
+                    currency: row_currency(r).unwrap_or_else(|| transaction.currency.clone()),
+                    counterparty_id: None,
+                    fee,
+                    expiry: None,
+                    clock: Some(*clock),
+                    action: Some(action),
                 }),
             )
             .await;
+        match started {
+            Err(AggregateError::UserError(PaymentError::DuplicatePayment)) if row_key.is_some() => {
+                debug!("Payment {} of an already processed row", payment_id);
+                return Ok(());
+            }
+            started => started?,
+        }
 
-        Ok(())
+        self.run_payment(&payment_id).await
     }
 
-    pub async fn handle_dispute_funds(&self, r: csv::CsvPaymentRecord) -> Result<()> {
-        debug!("Handling dispute: {:?}", r);
-        let transaction = require_transaction(&self.transactions_store, &r.tx_id)
-            .await
-            .inspect_err(|e| debug!("Error retrieving tx: {}", e))?;
+    fn fee(&self, kind: FeeKind, base: Decimal, currency: &Currency) -> Option<Amount> {
+        self.fees
+            .charge(kind, base, self.precision.rule(currency).minor_units)
+    }
 
-        #[allow(clippy::collapsible_if)] // collapsable 'if' can be unstable
-        if let Some(tx_type) = transaction.tx_type {
-            if let TxType::Withdrawal = tx_type {
-                return Err(eyre!("Dispute not allowed for type={}", tx_type));
-            }
-        }
+    /// Runs the remaining steps of the payment, each completed step is persisted.
+    /// When a step is rejected, the completed ones are compensated and the rejection returned.
+    /// Other errors (e.g. storage) leave the payment as it is, to be resumed.
+    async fn run_payment(&self, payment_id: &str) -> Result<()> {
+        let aggregate_id = payment_aggregate_id(payment_id);
+        let mut rejection = None;
 
-        let amount = transaction.amount;
+        let id = loop {
+            let payment = self.load_payment(&aggregate_id).await?;
+            let id = payment
+                .id
+                .clone()
+                .ok_or(eyre!(PaymentError::PaymentNotFound))?;
+            let Some(step) = payment.next_step() else {
+                break id;
+            };
 
-        self.account_cqrs
+            let command = match self.run_step(&payment, payment_id, step).await {
+                Ok(()) => PaymentCommand::CompleteStep(CompleteStepPayload { id, step }),
+                // Rejected after the account steps, the payment stays open to be resumed
+                Err(e) if is_rejection(&e) && payment.can_compensate() => {
+                    debug!("Payment {} rejected at {:?}: {}", payment_id, step, e);
+                    let command = PaymentCommand::CompensatePayment(CompensatePaymentPayload {
+                        id,
+                        reason: reason_code(&e).to_owned(),
+                    });
+                    rejection = Some(e);
+                    command
+                }
+                Err(e) => return Err(e),
+            };
+            self.payment_cqrs.execute(&aggregate_id, command).await?;
+        };
+
+        self.payment_cqrs
             .execute(
-                &acc_aggregate_id(&r.client_id),
-                AccountCommand::DisputeFunds(DisputeFundsPayload {
-                    client_id: ClientId(r.client_id),
-                    transaction_id: TransactionId(r.tx_id.to_owned()),
-                    amount: Amount(amount),
-                }),
+                &aggregate_id,
+                PaymentCommand::FinishPayment(FinishPaymentPayload { id }),
             )
             .await?;
 
-        Ok(())
+        rejection.map_or(Ok(()), Err)
     }
 
-    pub async fn handle_resolve_dispute(&self, r: csv::CsvPaymentRecord) -> Result<()> {
-        let _ = require_transaction(&self.transactions_store, &r.tx_id).await?;
+    /// Steps are idempotent, so a step interrupted after its command went through is simply taken again.
+    async fn run_step(&self, payment: &Payment, payment_id: &str, step: PaymentStep) -> Result<()> {
+        let (Some(id), Some(client_id), Some(tx_type)) = (
+            payment.id.clone(),
+            payment.client_id.clone(),
+            payment.tx_type,
+        ) else {
+            return Err(eyre!(PaymentError::PaymentNotFound));
+        };
+        let amount = || {
+            payment
+                .amount
+                .clone()
+                .ok_or(eyre!(PaymentError::PaymentNotFound))
+        };
+        let counterparty_id = || {
+            payment
+                .counterparty_id
+                .clone()
+                .ok_or(eyre!(PaymentError::PaymentNotFound))
+        };
 
-        // If there was no open dispute, this will fail as expected.
-        let _ = self
-            .account_cqrs
-            .execute(
-                &format!("Account-{}", r.client_id),
-                AccountCommand::ResolveDispute(ResolveDisputePayload {
-                    client_id: ClientId(r.client_id),
-                    transaction_id: TransactionId(r.tx_id.to_owned()),
-                }),
-            )
-            .await;
+        match step {
+            PaymentStep::RecordTransaction => {
+                if require_transaction(&self.transactions_store, &id)
+                    .await?
+                    .is_recorded()
+                {
+                    return Ok(());
+                }
+
+                self.transaction_cqrs
+                    .execute(
+                        &tx_aggregate_id(&id),
+                        TransactionCommand::RecordTransaction(RecordTransactionPayload {
+                            id,
+                            client_id,
+                            tx_type,
+                            amount: amount()?,
+                            currency: payment.currency.clone(),
+                            counterparty_id: payment.counterparty_id.clone(),
+                        }),
+                    )
+                    .await?;
+            }
+            PaymentStep::ApplyToAccount => {
+                let command = match (payment.action, tx_type) {
+                    (Some(action), _) => {
+                        self.action_account_command(payment, payment_id, action, id, &client_id)
+                            .await?
+                    }
+                    (None, TxType::Deposit) => {
+                        AccountCommand::DepositAccount(DepositAccountPayload {
+                            client_id: client_id.clone(),
+                            transaction_id: id,
+                            amount: amount()?,
+                            currency: payment.currency.clone(),
+                            fee: payment.fee.clone(),
+                            clock: payment.clock,
+                        })
+                    }
+                    (None, TxType::Withdrawal) => {
+                        AccountCommand::WithdrawAccount(WithdrawAccountPayload {
+                            client_id: client_id.clone(),
+                            transaction_id: id,
+                            amount: amount()?,
+                            currency: payment.currency.clone(),
+                            fee: payment.fee.clone(),
+                            clock: payment.clock,
+                        })
+                    }
+                    (None, TxType::Transfer) => AccountCommand::SendTransfer(SendTransferPayload {
+                        client_id: client_id.clone(),
+                        transaction_id: id,
+                        to_client_id: counterparty_id()?,
+                        amount: amount()?,
+                        currency: payment.currency.clone(),
+                        clock: payment.clock,
+                    }),
+                    (None, TxType::Authorization) => {
+                        AccountCommand::AuthorizeFunds(AuthorizeFundsPayload {
+                            client_id: client_id.clone(),
+                            transaction_id: id,
+                            amount: amount()?,
+                            currency: payment.currency.clone(),
+                            expiry: payment.expiry.unwrap_or_default(),
+                        })
+                    }
+                };
+                execute_account(&self.account_cqrs, &client_id, command).await?;
+            }
+            PaymentStep::CreditRecipient => {
+                let to = counterparty_id()?;
+                let recipient_partition =
+                    get_channel_by_client_id(self.partitions.len() as u32, &to);
+                execute_account(
+                    &self.partitions[recipient_partition],
+                    &to.to_owned(),
+                    AccountCommand::ReceiveTransfer(ReceiveTransferPayload {
+                        client_id: to,
+                        transaction_id: id,
+                        from_client_id: client_id,
+                        amount: amount()?,
+                        currency: payment.currency.clone(),
+                    }),
+                )
+                .await?;
+            }
+            PaymentStep::CollectFee => {
+                let (Some(fee), Some(kind)) = (payment.fee.clone(), fee_kind(payment)) else {
+                    return Err(eyre!(PaymentError::UnexpectedStep));
+                };
+                let house = ClientId(HOUSE_CLIENT_ID.to_owned());
+                let house_partition =
+                    get_channel_by_client_id(self.partitions.len() as u32, &house);
+                execute_account(
+                    &self.partitions[house_partition],
+                    HOUSE_CLIENT_ID,
+                    AccountCommand::CollectFee(CollectFeePayload {
+                        client_id: house,
+                        from_client_id: client_id,
+                        transaction_id: id,
+                        kind,
+                        amount: fee,
+                        currency: payment.currency.clone(),
+                        payment_id: payment_id.to_owned(),
+                    }),
+                )
+                .await?;
+            }
+            PaymentStep::MarkApplied => {
+                self.transaction_cqrs
+                    .execute(
+                        &tx_aggregate_id(&id),
+                        TransactionCommand::ApplyTransaction(ApplyTransactionPayload { id }),
+                    )
+                    .await?;
+            }
+            PaymentStep::UpdateTransaction => {
+                let payment_id = Some(payment_id.to_owned());
+                let amount = payment.amount.clone();
+                let command = match payment.action {
+                    Some(PaymentAction::Dispute) => {
+                        TransactionCommand::DisputeTransaction(DisputeTransactionPayload {
+                            id: id.clone(),
+                            amount,
+                            payment_id,
+                        })
+                    }
+                    Some(PaymentAction::Resolve) => TransactionCommand::ResolveTransactionDispute(
+                        ResolveTransactionDisputePayload {
+                            id: id.clone(),
+                            amount,
+                            payment_id,
+                        },
+                    ),
+                    Some(PaymentAction::Chargeback) => {
+                        TransactionCommand::ChargebackTransactionDispute(
+                            ChargebackTransactionDisputePayload {
+                                id: id.clone(),
+                                amount,
+                                payment_id,
+                            },
+                        )
+                    }
+                    Some(PaymentAction::Capture) => {
+                        let amount = match amount {
+                            Some(amount) => amount,
+                            None => Amount(
+                                require_transaction(&self.transactions_store, &id)
+                                    .await?
+                                    .amount,
+                            ),
+                        };
+                        TransactionCommand::CaptureTransactionAuthorization(
+                            CaptureTransactionAuthorizationPayload {
+                                id: id.clone(),
+                                amount,
+                            },
+                        )
+                    }
+                    Some(PaymentAction::Void) => TransactionCommand::VoidTransactionAuthorization(
+                        VoidTransactionAuthorizationPayload { id: id.clone() },
+                    ),
+                    None => return Err(eyre!(PaymentError::UnexpectedStep)),
+                };
+                self.transaction_cqrs
+                    .execute(&tx_aggregate_id(&id), command)
+                    .await?;
+            }
+            PaymentStep::RevertTransfer => {
+                execute_account(
+                    &self.account_cqrs,
+                    &client_id.to_owned(),
+                    AccountCommand::RevertTransfer(RevertTransferPayload {
+                        client_id,
+                        transaction_id: id,
+                        to_client_id: counterparty_id()?,
+                        amount: amount()?,
+                        currency: payment.currency.clone(),
+                    }),
+                )
+                .await?;
+            }
+            PaymentStep::VoidTransaction => {
+                self.transaction_cqrs
+                    .execute(
+                        &tx_aggregate_id(&id),
+                        TransactionCommand::VoidTransaction(VoidTransactionPayload {
+                            id,
+                            reason: payment.failure.clone().unwrap_or_default(),
+                        }),
+                    )
+                    .await?;
+            }
+        }
 
         Ok(())
     }
 
-    pub async fn handle_chargeback_dispute(&self, r: csv::CsvPaymentRecord) -> Result<()> {
-        let _ = require_transaction(&self.transactions_store, &r.tx_id).await?;
+    /// A dispute of the transaction holds the given part of its amount, in the currency of the payment.
+    /// A capture takes the given part of the authorization, in the same currency.
+    async fn action_account_command(
+        &self,
+        payment: &Payment,
+        payment_id: &str,
+        action: PaymentAction,
+        transaction_id: TransactionId,
+        client_id: &ClientId,
+    ) -> Result<AccountCommand> {
+        let client_id = client_id.clone();
+        let amount = payment.amount.clone();
+        let currency = Some(payment.currency.clone());
+        let payment_id = Some(payment_id.to_owned());
 
-        // If there was no open dispute, this will fail as expected.
-        let _ = self
-            .account_cqrs
-            .execute(
-                &format!("Account-{}", r.client_id),
+        let command = match action {
+            PaymentAction::Dispute => {
+                let transaction =
+                    require_transaction(&self.transactions_store, &transaction_id).await?;
+                let transaction_amount = Amount(transaction.amount);
+                let transaction_currency = transaction.currency;
+                match payment.tx_type {
+                    Some(TxType::Withdrawal) => {
+                        AccountCommand::DisputeWithdrawal(DisputeWithdrawalPayload {
+                            client_id,
+                            transaction_id,
+                            transaction_amount,
+                            amount,
+                            transaction_currency,
+                            currency,
+                            payment_id,
+                        })
+                    }
+                    _ => AccountCommand::DisputeFunds(DisputeFundsPayload {
+                        client_id,
+                        transaction_id,
+                        transaction_amount,
+                        amount,
+                        transaction_currency,
+                        currency,
+                        payment_id,
+                    }),
+                }
+            }
+            PaymentAction::Resolve => AccountCommand::ResolveDispute(ResolveDisputePayload {
+                client_id,
+                transaction_id,
+                amount,
+                currency,
+                payment_id,
+            }),
+            PaymentAction::Chargeback => {
                 AccountCommand::ChargebackDispute(ChargebackDisputePayload {
-                    client_id: ClientId(r.client_id),
-                    transaction_id: TransactionId(r.tx_id.to_owned()),
-                }),
-            )
-            .await;
+                    client_id,
+                    transaction_id,
+                    amount,
+                    currency,
+                    fee: payment.fee.clone(),
+                    payment_id,
+                })
+            }
+            PaymentAction::Capture => {
+                AccountCommand::CaptureAuthorization(CaptureAuthorizationPayload {
+                    client_id,
+                    transaction_id,
+                    amount,
+                    currency,
+                    fee: payment.fee.clone(),
+                    clock: payment.clock,
+                    payment_id,
+                })
+            }
+            PaymentAction::Void => AccountCommand::VoidAuthorization(VoidAuthorizationPayload {
+                client_id,
+                transaction_id,
+                payment_id,
+            }),
+        };
+
+        Ok(command)
+    }
+
+    async fn load_payment(&self, payment_id: &str) -> Result<Payment> {
+        Ok(self
+            .payments_store
+            .load_aggregate(payment_id)
+            .await
+            .map_err(|e| eyre!(e))?
+            .aggregate)
+    }
+
+    /// Runs payments interrupted by a crash to the end, so Transaction and Account aggregates agree again.
+    pub async fn resume_payments(&self) -> Result<()> {
+        let aggregate_ids: Vec<String> = sqlx::query(
+            "select aggregate_id from events where aggregate_type = 'Payment'
+                group by aggregate_id
+                having sum(event_type in ('PaymentCompleted', 'PaymentFailed')) = 0",
+        )
+        .fetch_all(&self.sqlite_pool)
+        .await
+        .map_err(|e| eyre!(e))?
+        .iter()
+        .map(|row| row.get("aggregate_id"))
+        .collect();
+
+        for aggregate_id in aggregate_ids {
+            let Some(payment_id) = aggregate_id.strip_prefix(PAYMENT_AGGREGATE_PREFIX) else {
+                continue;
+            };
+            debug!("Resuming payment {}", payment_id);
+            // A rejection is compensated as in the original run, only its row is not known anymore
+            match self.run_payment(payment_id).await {
+                Err(e) if !is_rejection(&e) => return Err(e),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The row will be skipped when the same input is processed again against this store.
+    pub async fn mark_processed(&self, row_key: &str) -> Result<()> {
+        mark_processed(&self.sqlite_pool, row_key).await
+    }
+
+    /// Operator actions on an account, not related to any transaction.
+    pub async fn handle_account_admin(
+        &self,
+        client_id: &str,
+        command: AccountCommand,
+    ) -> Result<()> {
+        execute_account(&self.account_cqrs, client_id, command).await?;
 
         Ok(())
     }
 }
 
-fn require_amount(amount_opt: Option<Decimal>, tx_id: &str) -> Result<Decimal> {
-    amount_opt.ok_or_eyre(format!("No amount found in row for tx {}", tx_id))
+/// Currency named by the row, if any.
+fn row_currency(r: &csv::CsvPaymentRecord) -> Option<Currency> {
+    r.currency.as_deref().map(Currency::new)
+}
+
+/// Actions on a transaction are told apart by the row they come from, by its key or else its position.
+fn action_payment_id(
+    tx_id: &str,
+    action: PaymentAction,
+    row_key: Option<&str>,
+    clock: &InputClock,
+) -> String {
+    match row_key {
+        Some(row_key) => format!("{}:{}:{}", tx_id, action, row_key),
+        None => format!("{}:{}:{}", tx_id, action, clock.position),
+    }
+}
+
+/// Domain errors reject the payment, others (e.g. storage) may go through when retried.
+/// Fees are charged with deposits, withdrawals, captures and chargebacks.
+fn fee_kind(payment: &Payment) -> Option<FeeKind> {
+    match (payment.action, payment.tx_type?) {
+        (Some(PaymentAction::Capture), _) => Some(FeeKind::Withdrawal),
+        (Some(PaymentAction::Chargeback), _) => Some(FeeKind::Chargeback),
+        (Some(_), _) => None,
+        (None, TxType::Deposit) => Some(FeeKind::Deposit),
+        (None, TxType::Withdrawal) => Some(FeeKind::Withdrawal),
+        (None, TxType::Transfer | TxType::Authorization) => None,
+    }
+}
+
+fn is_rejection(e: &Report) -> bool {
+    matches!(
+        e.downcast_ref::<AggregateError<AccountError>>(),
+        Some(AggregateError::UserError(_))
+    ) || matches!(
+        e.downcast_ref::<AggregateError<TransactionError>>(),
+        Some(AggregateError::UserError(_))
+    )
+}
+
+/// An operation held by the risk screening is rejected, and its account frozen for review.
+async fn execute_account(
+    account_cqrs: &Mutex<AccountCqrs>,
+    client_id: &str,
+    command: AccountCommand,
+) -> Result<()> {
+    let account_cqrs = account_cqrs.lock().await;
+    let aggregate_id = acc_aggregate_id(client_id);
+    let result = account_cqrs.execute(&aggregate_id, command).await;
+    if let Err(AggregateError::UserError(AccountError::RiskHeld)) = &result {
+        let freeze = AccountCommand::FreezeAccount(FreezeAccountPayload {
+            client_id: ClientId(client_id.to_owned()),
+            reason: HOLD_REASON.to_owned(),
+            operator_id: SCREENING_OPERATOR.to_owned(),
+        });
+        match account_cqrs.execute(&aggregate_id, freeze).await {
+            // Already frozen for another reason
+            Ok(()) | Err(AggregateError::UserError(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    result?;
+
+    Ok(())
 }
 
 async fn require_transaction(
@@ -218,3 +956,12 @@ async fn require_transaction(
         .map_err(|e| eyre!(e))?
         .aggregate)
 }
+
+#[allow(clippy::expect_used)] // without this working, it's a show over
+async fn events_table_exists(sqlite_pool: &Pool<Sqlite>) -> bool {
+    sqlx::query("select name from sqlite_master where type = 'table' and name = 'events'")
+        .fetch_optional(sqlite_pool)
+        .await
+        .expect("Failed to check DB tables")
+        .is_some()
+}
@@ -1,21 +1,60 @@
-use std::io;
+use std::{cmp::Ordering, collections::BTreeMap, io, sync::Arc};
 
 use color_eyre::eyre::{Result, eyre};
-use cqrs_es::{EventEnvelope, View, persist::GenericQuery};
-use csv::WriterBuilder;
+use cqrs_es::{
+    EventEnvelope, EventStore, Query, View,
+    persist::{GenericQuery, PersistedEventStore},
+};
 use futures::TryStreamExt;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use sqlite_es::SqliteViewRepository;
+use sqlite_es::{SqliteEventRepository, SqliteViewRepository};
 use sqlx::{Pool, Row, Sqlite, SqlitePool};
 
-use crate::domain::account::{aggregate::Account, event::AccountEvent};
+use crate::{
+    cli::OutputArgs,
+    domain::{
+        account::{
+            aggregate::{Account, shortfall},
+            event::AccountEvent,
+        },
+        props::Currency,
+    },
+    query::output::accounts_writer,
+};
 
 pub(crate) type AccountQueryRepository =
     GenericQuery<SqliteViewRepository<AccountView, Account>, AccountView, Account>;
 
+/// Account state of a client, with its funds in each of the currencies it has.
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
 pub(crate) struct AccountView {
+    #[serde(rename = "client")]
+    pub client_id: String,
+    #[serde(rename = "locked")]
+    pub is_locked: bool,
+    /// Funds can come in, but not leave
+    #[serde(rename = "frozen", default)]
+    pub is_frozen: bool,
+    #[serde(default)]
+    pub balances: BTreeMap<Currency, BalanceView>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub(crate) struct BalanceView {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    /// Owed by the client after a dispute larger than the available funds
+    pub debt: Decimal,
+    /// Reserved by open authorizations
+    #[serde(default)]
+    pub authorized: Decimal,
+}
+
+/// Output row, funds of a client in one currency.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub(crate) struct AccountRow {
     #[serde(rename = "client")]
     pub client_id: String,
     #[serde(rename = "available")]
@@ -26,6 +65,41 @@ pub(crate) struct AccountView {
     pub total_funds: Decimal,
     #[serde(rename = "locked")]
     pub is_locked: bool,
+    pub debt: Decimal,
+    #[serde(rename = "frozen")]
+    pub is_frozen: bool,
+    pub currency: Currency,
+    #[serde(default)]
+    pub authorized: Decimal,
+}
+
+impl AccountView {
+    fn balance_mut(&mut self, currency: &Currency) -> &mut BalanceView {
+        self.balances.entry(currency.clone()).or_default()
+    }
+
+    /// A row per currency, an account without any funds yet still has one in the default currency.
+    pub fn rows(&self) -> Vec<AccountRow> {
+        let row = |currency: &Currency, balance: &BalanceView| AccountRow {
+            client_id: self.client_id.clone(),
+            available_funds: balance.available,
+            held_funds: balance.held,
+            total_funds: balance.total,
+            is_locked: self.is_locked,
+            debt: balance.debt,
+            is_frozen: self.is_frozen,
+            currency: currency.clone(),
+            authorized: balance.authorized,
+        };
+
+        if self.balances.is_empty() {
+            return vec![row(&Currency::default(), &BalanceView::default())];
+        }
+        self.balances
+            .iter()
+            .map(|(currency, balance)| row(currency, balance))
+            .collect()
+    }
 }
 
 impl View<Account> for AccountView {
@@ -33,26 +107,112 @@ impl View<Account> for AccountView {
         match &event.payload {
             AccountEvent::AccountDeposited(p) => {
                 self.client_id = p.client_id.to_string();
-                self.available_funds += *p.amount;
-                self.total_funds += *p.amount;
+                let balance = self.balance_mut(&p.currency);
+                balance.available += *p.amount;
+                balance.total += *p.amount;
             }
             AccountEvent::AccountWithdrawn(p) => {
-                self.available_funds -= *p.amount;
-                self.total_funds -= *p.amount;
+                let balance = self.balance_mut(&p.currency);
+                balance.available -= *p.amount;
+                balance.total -= *p.amount;
             }
             AccountEvent::FundsDisputed(p) => {
-                self.available_funds -= *p.amount;
-                self.held_funds += *p.amount;
+                let balance = self.balance_mut(&p.currency);
+                balance.debt += shortfall(balance.available, *p.amount);
+                balance.available -= *p.amount;
+                balance.held += *p.amount;
             }
             AccountEvent::DisputeResolved(p) => {
-                self.available_funds += *p.amount;
-                self.held_funds -= *p.amount;
+                let balance = self.balance_mut(&p.currency);
+                balance.available += *p.amount;
+                balance.held -= *p.amount;
             }
             AccountEvent::DisputeChargedback(p) => {
-                self.held_funds -= *p.amount;
-                self.total_funds -= *p.amount;
+                let balance = self.balance_mut(&p.currency);
+                balance.held -= *p.amount;
+                balance.total -= *p.amount;
+                self.is_locked = true;
+            }
+            AccountEvent::WithdrawalDisputed(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.held += *p.amount;
+                balance.total += *p.amount;
+            }
+            AccountEvent::WithdrawalDisputeResolved(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.held -= *p.amount;
+                balance.total -= *p.amount;
+            }
+            AccountEvent::WithdrawalDisputeChargedback(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.held -= *p.amount;
+                balance.available += *p.amount;
+            }
+            AccountEvent::DebtRepaid(p) => {
+                self.balance_mut(&p.currency).debt -= *p.amount;
+            }
+            AccountEvent::AccountLocked(p) => {
+                self.client_id = p.client_id.to_string();
                 self.is_locked = true;
             }
+            AccountEvent::AccountUnlocked(p) => {
+                self.client_id = p.client_id.to_string();
+                self.is_locked = false;
+                self.is_frozen = false;
+            }
+            AccountEvent::AccountFrozen(p) => {
+                self.client_id = p.client_id.to_string();
+                self.is_frozen = true;
+            }
+            AccountEvent::TransferSent(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.available -= *p.amount;
+                balance.total -= *p.amount;
+            }
+            AccountEvent::TransferReceived(p) => {
+                self.client_id = p.client_id.to_string();
+                let balance = self.balance_mut(&p.currency);
+                balance.available += *p.amount;
+                balance.total += *p.amount;
+            }
+            AccountEvent::TransferReverted(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.available += *p.amount;
+                balance.total += *p.amount;
+            }
+            AccountEvent::FeeCharged(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.debt += shortfall(balance.available, *p.amount);
+                balance.available -= *p.amount;
+                balance.total -= *p.amount;
+            }
+            AccountEvent::FundsAuthorized(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.available -= *p.amount;
+                balance.authorized += *p.amount;
+            }
+            AccountEvent::AuthorizationCaptured(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.authorized -= *p.amount + *p.released;
+                balance.available += *p.released;
+                balance.total -= *p.amount;
+            }
+            AccountEvent::AuthorizationVoided(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.authorized -= *p.amount;
+                balance.available += *p.amount;
+            }
+            AccountEvent::AuthorizationExpired(p) => {
+                let balance = self.balance_mut(&p.currency);
+                balance.authorized -= *p.amount;
+                balance.available += *p.amount;
+            }
+            AccountEvent::FeeCollected(p) => {
+                self.client_id = p.client_id.to_string();
+                let balance = self.balance_mut(&p.currency);
+                balance.available += *p.amount;
+                balance.total += *p.amount;
+            }
         }
     }
 }
@@ -73,18 +233,115 @@ pub async fn init_accounts_table(sqlite_pool: &Pool<Sqlite>) {
     .expect("Failed to initialize accounts table");
 }
 
-pub async fn print_accounts_csv(sqlite_pool: &SqlitePool) -> Result<()> {
-    let mut csv_writer = WriterBuilder::new()
-        .has_headers(false)
-        .from_writer(io::stdout());
+/// Rebuilds the accounts projection from the Account events of the partition.
+pub async fn replay_accounts(sqlite_pool: &SqlitePool) -> Result<()> {
+    init_accounts_table(sqlite_pool).await;
+    sqlx::query("delete from accounts")
+        .execute(sqlite_pool)
+        .await
+        .map_err(|e| eyre!(e))?;
+
+    let view_repo =
+        SqliteViewRepository::<AccountView, Account>::new("accounts", sqlite_pool.clone());
+    let account_query = AccountQueryRepository::new(Arc::new(view_repo));
+
+    dispatch_account_events(sqlite_pool, &account_query).await
+}
+
+/// Feeds all Account events of the partition to the query, aggregate by aggregate.
+pub async fn dispatch_account_events(
+    sqlite_pool: &SqlitePool,
+    query: &dyn Query<Account>,
+) -> Result<()> {
+    let accounts_store: PersistedEventStore<SqliteEventRepository, Account> =
+        PersistedEventStore::new_event_store(SqliteEventRepository::new(sqlite_pool.clone()));
+
+    let aggregate_ids: Vec<String> =
+        sqlx::query("select distinct aggregate_id from events where aggregate_type = 'Account'")
+            .fetch_all(sqlite_pool)
+            .await
+            .map_err(|e| eyre!(e))?
+            .iter()
+            .map(|row| row.get("aggregate_id"))
+            .collect();
+    for aggregate_id in aggregate_ids {
+        let events = accounts_store
+            .load_events(&aggregate_id)
+            .await
+            .map_err(|e| eyre!(e))?;
+        query.dispatch(&aggregate_id, &events).await;
+    }
+
+    Ok(())
+}
+
+pub async fn print_accounts(sqlite_pools: &[SqlitePool], output: &OutputArgs) -> Result<()> {
+    let mut writer = accounts_writer(output.format, output.all_columns, io::stdout());
+
+    writer.begin()?;
+    if output.sorted {
+        for account in load_sorted_accounts(sqlite_pools).await? {
+            for row in account.rows() {
+                writer.write(&row)?;
+            }
+        }
+    } else {
+        // Each partition is printed as is, without collecting all accounts in memory
+        for sqlite_pool in sqlite_pools {
+            for account in load_accounts(sqlite_pool).await? {
+                for row in account.rows() {
+                    writer.write(&row)?;
+                }
+            }
+        }
+    }
+    writer.end()
+}
+
+async fn load_accounts(sqlite_pool: &SqlitePool) -> Result<Vec<AccountView>> {
+    let mut accounts = vec![];
 
     let mut query = sqlx::query("select payload from accounts").fetch(sqlite_pool);
     while let Some(row) = query.try_next().await.map_err(|e| eyre!(e))? {
         let s: String = row.get("payload");
         if let Ok(obj) = serde_json::from_str::<AccountView>(&s) {
-            let _ = csv_writer.serialize(obj);
+            accounts.push(obj);
         }
     }
 
-    Ok(())
+    Ok(accounts)
+}
+
+async fn load_sorted_accounts(sqlite_pools: &[SqlitePool]) -> Result<Vec<AccountView>> {
+    let mut accounts = vec![];
+    for sqlite_pool in sqlite_pools {
+        accounts.extend(load_accounts(sqlite_pool).await?);
+    }
+    accounts.sort_by(|a, b| compare_client_ids(&a.client_id, &b.client_id));
+
+    Ok(accounts)
+}
+
+/// Numeric client ids are compared as numbers and go before non numeric ones, which are compared as strings.
+fn compare_client_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u128>(), b.parse::<u128>()) {
+        (Ok(a_num), Ok(b_num)) => a_num.cmp(&b_num).then_with(|| a.cmp(b)),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::account::compare_client_ids;
+
+    #[test]
+    fn sorts_client_ids() {
+        let mut client_ids = vec!["10", "b", "2", "a", "1", "02"];
+
+        client_ids.sort_by(|a, b| compare_client_ids(a, b));
+
+        assert_eq!(client_ids, vec!["1", "02", "2", "10", "a", "b"]);
+    }
 }
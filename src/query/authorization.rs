@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+use color_eyre::eyre::{Result, eyre};
+use cqrs_es::{EventEnvelope, Query};
+use sqlx::{Pool, Row, Sqlite, SqlitePool};
+use tracing::debug;
+
+use crate::{
+    domain::{
+        account::{
+            aggregate::Account,
+            event::{
+                AccountEvent, AuthorizationCapturedPayload, AuthorizationExpiredPayload,
+                AuthorizationVoidedPayload,
+            },
+        },
+        props::{ClientId, Expiry, InputClock, TransactionId},
+    },
+    query::account::dispatch_account_events,
+};
+
+/// Keeps open authorizations of the partition with their expiry, so due ones are found without loading accounts.
+pub struct AuthorizationQuery {
+    sqlite_pool: SqlitePool,
+}
+
+impl AuthorizationQuery {
+    pub fn new(sqlite_pool: SqlitePool) -> Self {
+        AuthorizationQuery { sqlite_pool }
+    }
+
+    async fn write(&self, event: &EventEnvelope<Account>) -> Result<()> {
+        let query = match &event.payload {
+            AccountEvent::FundsAuthorized(p) => sqlx::query(
+                "insert or replace into authorizations
+                    (tx_id, client_id, after_position, at_time)
+                    values (?, ?, ?, ?)",
+            )
+            .bind(p.transaction_id.as_str())
+            .bind(p.client_id.as_str())
+            .bind(p.expiry.after_position.map(|position| position as i64))
+            .bind(p.expiry.at_time),
+            AccountEvent::AuthorizationCaptured(AuthorizationCapturedPayload {
+                transaction_id,
+                ..
+            })
+            | AccountEvent::AuthorizationVoided(AuthorizationVoidedPayload {
+                transaction_id,
+                ..
+            })
+            | AccountEvent::AuthorizationExpired(AuthorizationExpiredPayload {
+                transaction_id,
+                ..
+            }) => sqlx::query("delete from authorizations where tx_id = ?")
+                .bind(transaction_id.as_str()),
+            _ => return Ok(()),
+        };
+        query
+            .execute(&self.sqlite_pool)
+            .await
+            .map_err(|e| eyre!(e))?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Query<Account> for AuthorizationQuery {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<Account>]) {
+        for event in events {
+            // Queries cannot fail the command, like the accounts view the error is only logged
+            if let Err(e) = self.write(event).await {
+                debug!("Error writing authorizations of {}: {}", aggregate_id, e);
+            }
+        }
+    }
+}
+
+/// Open authorizations of the partition due by the clock, in the order they were authorized.
+pub async fn due_authorizations(
+    sqlite_pool: &SqlitePool,
+    clock: &InputClock,
+) -> Result<Vec<(ClientId, TransactionId)>> {
+    Ok(sqlx::query(
+        "select client_id, tx_id from authorizations
+            where after_position < ? or at_time <= ?
+            order by rowid",
+    )
+    .bind(clock.position as i64)
+    .bind(clock.time)
+    .fetch_all(sqlite_pool)
+    .await
+    .map_err(|e| eyre!(e))?
+    .iter()
+    .map(|row| {
+        (
+            ClientId(row.get("client_id")),
+            TransactionId(row.get("tx_id")),
+        )
+    })
+    .collect())
+}
+
+/// Earliest expiry of the open authorizations of the partition, never due when there are none.
+pub async fn earliest_expiry(sqlite_pool: &SqlitePool) -> Result<Expiry> {
+    let row = sqlx::query(
+        "select min(after_position) as after_position, min(at_time) as at_time
+            from authorizations",
+    )
+    .fetch_one(sqlite_pool)
+    .await
+    .map_err(|e| eyre!(e))?;
+
+    Ok(Expiry {
+        after_position: row
+            .get::<Option<i64>, _>("after_position")
+            .map(|position| position as u64),
+        at_time: row.get("at_time"),
+    })
+}
+
+#[allow(clippy::expect_used)] // without this working, it's a show over
+pub async fn init_authorizations_table(sqlite_pool: &Pool<Sqlite>) {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS authorizations
+            (
+                tx_id          text   NOT NULL PRIMARY KEY,
+                client_id      text   NOT NULL,
+                after_position bigint,
+                at_time        bigint
+            );",
+    )
+    .execute(sqlite_pool)
+    .await
+    .expect("Failed to initialize authorizations table");
+}
+
+/// Rebuilds open authorizations from the Account events of the partition.
+pub async fn replay_authorizations(sqlite_pool: &SqlitePool) -> Result<()> {
+    init_authorizations_table(sqlite_pool).await;
+    sqlx::query("delete from authorizations")
+        .execute(sqlite_pool)
+        .await
+        .map_err(|e| eyre!(e))?;
+
+    dispatch_account_events(sqlite_pool, &AuthorizationQuery::new(sqlite_pool.clone())).await
+}
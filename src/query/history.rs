@@ -0,0 +1,39 @@
+use std::io;
+
+use color_eyre::eyre::{Result, eyre};
+use csv::WriterBuilder;
+use futures::TryStreamExt;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::domain::account::aggregate::acc_aggregate_id;
+
+#[derive(Serialize)]
+struct HistoryRow {
+    sequence: i64,
+    event_type: String,
+    payload: String,
+}
+
+/// Prints all events of the client account in the order they were applied.
+pub async fn print_account_history(sqlite_pool: &SqlitePool, client_id: &str) -> Result<()> {
+    let mut csv_writer = WriterBuilder::new().from_writer(io::stdout());
+
+    let mut query = sqlx::query(
+        "select sequence, event_type, payload from events
+            where aggregate_type = 'Account' and aggregate_id = ?
+            order by sequence",
+    )
+    .bind(acc_aggregate_id(client_id))
+    .fetch(sqlite_pool);
+    while let Some(row) = query.try_next().await.map_err(|e| eyre!(e))? {
+        csv_writer.serialize(HistoryRow {
+            sequence: row.get("sequence"),
+            event_type: row.get("event_type"),
+            payload: row.get("payload"),
+        })?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,516 @@
+use std::{collections::BTreeMap, io};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{Result, eyre};
+use cqrs_es::{EventEnvelope, Query};
+use csv::WriterBuilder;
+use futures::TryStreamExt;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite, SqlitePool};
+use tracing::debug;
+
+use crate::{
+    domain::{
+        account::{aggregate::Account, event::AccountEvent},
+        props::{ClientId, Currency, DEFAULT_CURRENCY},
+    },
+    query::account::dispatch_account_events,
+};
+
+// System accounts, the other side of client balances.
+/// Funds deposited into the engine
+pub const CASH_IN: &str = "cash-in";
+/// Funds withdrawn from the engine
+pub const CASH_OUT: &str = "cash-out";
+/// Withdrawals provisionally credited back while disputed
+pub const DISPUTE_SUSPENSE: &str = "dispute-suspense";
+/// Funds lost to chargebacks
+pub const CHARGEBACK_LOSS: &str = "chargeback-loss";
+/// Transfers sent, but not received yet, as both sides may be in different partitions
+pub const TRANSFER_CLEARING: &str = "transfer-clearing";
+/// Fees charged, but not collected yet, as the house account may be in another partition
+pub const FEE_CLEARING: &str = "fee-clearing";
+
+/// One side of a journal entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub account: String,
+    pub debit: Decimal,
+    pub credit: Decimal,
+    pub currency: Currency,
+}
+
+/// Balanced postings of an account event, events not moving any funds have none.
+/// Client balances are split into available, held and authorized ledger accounts, both sides are in the event currency.
+pub fn postings(event: &AccountEvent) -> Vec<Posting> {
+    match event {
+        AccountEvent::AccountDeposited(p) => {
+            entry(CASH_IN, &available(&p.client_id), *p.amount, &p.currency)
+        }
+        AccountEvent::AccountWithdrawn(p) => {
+            entry(&available(&p.client_id), CASH_OUT, *p.amount, &p.currency)
+        }
+        AccountEvent::FundsDisputed(p) => entry(
+            &available(&p.client_id),
+            &held(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::DisputeResolved(p) => entry(
+            &held(&p.client_id),
+            &available(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::DisputeChargedback(p) => {
+            entry(&held(&p.client_id), CHARGEBACK_LOSS, *p.amount, &p.currency)
+        }
+        AccountEvent::WithdrawalDisputed(p) => entry(
+            DISPUTE_SUSPENSE,
+            &held(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::WithdrawalDisputeResolved(p) => entry(
+            &held(&p.client_id),
+            DISPUTE_SUSPENSE,
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::WithdrawalDisputeChargedback(p) => {
+            let mut postings = entry(
+                &held(&p.client_id),
+                &available(&p.client_id),
+                *p.amount,
+                &p.currency,
+            );
+            postings.extend(entry(
+                CHARGEBACK_LOSS,
+                DISPUTE_SUSPENSE,
+                *p.amount,
+                &p.currency,
+            ));
+            postings
+        }
+        AccountEvent::TransferSent(p) => entry(
+            &available(&p.client_id),
+            TRANSFER_CLEARING,
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::TransferReceived(p) => entry(
+            TRANSFER_CLEARING,
+            &available(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::TransferReverted(p) => entry(
+            TRANSFER_CLEARING,
+            &available(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::FeeCharged(p) => entry(
+            &available(&p.client_id),
+            FEE_CLEARING,
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::FeeCollected(p) => entry(
+            FEE_CLEARING,
+            &available(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::FundsAuthorized(p) => entry(
+            &available(&p.client_id),
+            &authorized(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::AuthorizationCaptured(p) => {
+            let mut postings = entry(&authorized(&p.client_id), CASH_OUT, *p.amount, &p.currency);
+            if !p.released.is_zero() {
+                postings.extend(entry(
+                    &authorized(&p.client_id),
+                    &available(&p.client_id),
+                    *p.released,
+                    &p.currency,
+                ));
+            }
+            postings
+        }
+        AccountEvent::AuthorizationVoided(p) => entry(
+            &authorized(&p.client_id),
+            &available(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        AccountEvent::AuthorizationExpired(p) => entry(
+            &authorized(&p.client_id),
+            &available(&p.client_id),
+            *p.amount,
+            &p.currency,
+        ),
+        // Debt is the negative part of available funds, already posted by the credit repaying it
+        AccountEvent::DebtRepaid(_)
+        | AccountEvent::AccountLocked(_)
+        | AccountEvent::AccountUnlocked(_)
+        | AccountEvent::AccountFrozen(_) => vec![],
+    }
+}
+
+fn entry(
+    debit_account: &str,
+    credit_account: &str,
+    amount: Decimal,
+    currency: &Currency,
+) -> Vec<Posting> {
+    vec![
+        Posting {
+            account: debit_account.to_owned(),
+            debit: amount,
+            credit: Decimal::ZERO,
+            currency: currency.clone(),
+        },
+        Posting {
+            account: credit_account.to_owned(),
+            debit: Decimal::ZERO,
+            credit: amount,
+            currency: currency.clone(),
+        },
+    ]
+}
+
+fn available(client_id: &ClientId) -> String {
+    format!("client:{}:available", client_id)
+}
+
+fn held(client_id: &ClientId) -> String {
+    format!("client:{}:held", client_id)
+}
+
+fn authorized(client_id: &ClientId) -> String {
+    format!("client:{}:authorized", client_id)
+}
+
+/// Writes postings of account events into the partition journal table.
+pub struct JournalQuery {
+    sqlite_pool: SqlitePool,
+}
+
+impl JournalQuery {
+    pub fn new(sqlite_pool: SqlitePool) -> Self {
+        JournalQuery { sqlite_pool }
+    }
+
+    async fn write(&self, event: &EventEnvelope<Account>) -> Result<()> {
+        for (index, posting) in postings(&event.payload).iter().enumerate() {
+            // Postings already written are kept, so events can be dispatched again
+            sqlx::query(
+                "insert or ignore into journal
+                    (aggregate_id, sequence, posting, account, debit, credit, currency)
+                    values (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&event.aggregate_id)
+            .bind(event.sequence as i64)
+            .bind(index as i64)
+            .bind(&posting.account)
+            .bind(posting.debit.to_string())
+            .bind(posting.credit.to_string())
+            .bind(posting.currency.as_str())
+            .execute(&self.sqlite_pool)
+            .await
+            .map_err(|e| eyre!(e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Query<Account> for JournalQuery {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<Account>]) {
+        for event in events {
+            // Queries cannot fail the command, like the accounts view the error is only logged
+            if let Err(e) = self.write(event).await {
+                debug!("Error writing journal of {}: {}", aggregate_id, e);
+            }
+        }
+    }
+}
+
+#[allow(clippy::expect_used)] // without this working, it's a show over
+pub async fn init_journal_table(sqlite_pool: &Pool<Sqlite>) {
+    let _ = sqlx::query(
+        "CREATE TABLE IF NOT EXISTS journal
+            (
+                aggregate_id text                     NOT NULL,
+                sequence     bigint                   NOT NULL,
+                posting      int                      NOT NULL,
+                account      text                     NOT NULL,
+                debit        text                     NOT NULL,
+                credit       text                     NOT NULL,
+                currency     text                     NOT NULL,
+                PRIMARY KEY (aggregate_id, sequence, posting)
+            );",
+    )
+    .execute(sqlite_pool)
+    .await
+    .expect("Failed to initialize journal table");
+
+    // Journals written before multi-currency accounts are all in the default currency
+    let has_currency =
+        sqlx::query("select 1 from pragma_table_info('journal') where name = 'currency'")
+            .fetch_optional(sqlite_pool)
+            .await
+            .expect("Failed to check journal table")
+            .is_some();
+    if !has_currency {
+        sqlx::query(&format!(
+            "ALTER TABLE journal ADD COLUMN currency text NOT NULL DEFAULT '{}'",
+            DEFAULT_CURRENCY
+        ))
+        .execute(sqlite_pool)
+        .await
+        .expect("Failed to migrate journal table");
+    }
+}
+
+/// Rebuilds the journal from the Account events of the partition, e.g. for a store created before it existed.
+pub async fn replay_journal(sqlite_pool: &SqlitePool) -> Result<()> {
+    init_journal_table(sqlite_pool).await;
+    sqlx::query("delete from journal")
+        .execute(sqlite_pool)
+        .await
+        .map_err(|e| eyre!(e))?;
+
+    dispatch_account_events(sqlite_pool, &JournalQuery::new(sqlite_pool.clone())).await
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+struct TrialBalanceRow {
+    account: String,
+    debit: Decimal,
+    credit: Decimal,
+    balance: Decimal,
+    currency: Currency,
+}
+
+impl TrialBalanceRow {
+    fn add(&mut self, posting: &Posting) {
+        self.debit += posting.debit;
+        self.credit += posting.credit;
+        self.balance = self.debit - self.credit;
+    }
+}
+
+/// Prints debit and credit totals per ledger account and currency of all partitions,
+/// followed by the total of each currency, as amounts in different currencies do not add up.
+/// Fails when the books of any currency do not sum to zero.
+pub async fn print_trial_balance(sqlite_pools: &[SqlitePool]) -> Result<()> {
+    let mut postings = vec![];
+    for sqlite_pool in sqlite_pools {
+        postings.extend(load_postings(sqlite_pool).await?);
+    }
+    let (rows, totals) = trial_balance(&postings);
+
+    let mut csv_writer = WriterBuilder::new().from_writer(io::stdout());
+    for row in rows.iter().chain(&totals) {
+        csv_writer.serialize(row)?;
+    }
+    csv_writer.flush()?;
+
+    if let Some(total) = totals.iter().find(|total| !total.balance.is_zero()) {
+        return Err(eyre!(
+            "Trial balance does not sum to zero: {} {}",
+            total.balance,
+            total.currency
+        ));
+    }
+
+    Ok(())
+}
+
+fn trial_balance(postings: &[Posting]) -> (Vec<TrialBalanceRow>, Vec<TrialBalanceRow>) {
+    let mut accounts = BTreeMap::<(&Currency, &str), TrialBalanceRow>::new();
+    let mut totals = BTreeMap::<&Currency, TrialBalanceRow>::new();
+    for posting in postings {
+        accounts
+            .entry((&posting.currency, &posting.account))
+            .or_insert_with(|| TrialBalanceRow {
+                account: posting.account.to_owned(),
+                currency: posting.currency.clone(),
+                ..Default::default()
+            })
+            .add(posting);
+        totals
+            .entry(&posting.currency)
+            .or_insert_with(|| TrialBalanceRow {
+                account: "total".to_owned(),
+                currency: posting.currency.clone(),
+                ..Default::default()
+            })
+            .add(posting);
+    }
+
+    (
+        accounts.into_values().collect(),
+        totals.into_values().collect(),
+    )
+}
+
+async fn load_postings(sqlite_pool: &SqlitePool) -> Result<Vec<Posting>> {
+    let mut postings = vec![];
+
+    let mut query =
+        sqlx::query("select account, debit, credit, currency from journal").fetch(sqlite_pool);
+    while let Some(row) = query.try_next().await.map_err(|e| eyre!(e))? {
+        let debit: String = row.get("debit");
+        let credit: String = row.get("credit");
+        postings.push(Posting {
+            account: row.get("account"),
+            debit: debit
+                .parse()
+                .map_err(|e| eyre!("Invalid journal debit: {}", e))?,
+            credit: credit
+                .parse()
+                .map_err(|e| eyre!("Invalid journal credit: {}", e))?,
+            currency: Currency(row.get("currency")),
+        });
+    }
+
+    Ok(postings)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::{Decimal, dec};
+
+    use crate::{
+        domain::{
+            account::event::{
+                AccountDepositedPayload, AccountEvent, DebtRepaidPayload, FeeChargedPayload,
+                FeeCollectedPayload, WithdrawalDisputeChargedbackPayload,
+            },
+            fee::{FeeKind, HOUSE_CLIENT_ID},
+            props::{Amount, ClientId, Currency, TransactionId},
+        },
+        query::journal::{
+            CASH_IN, CHARGEBACK_LOSS, DISPUTE_SUSPENSE, FEE_CLEARING, postings, trial_balance,
+        },
+    };
+
+    fn deposited(client_id: &str, amount: Decimal, currency: &str) -> AccountEvent {
+        AccountEvent::AccountDeposited(AccountDepositedPayload {
+            client_id: ClientId(client_id.to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            amount: Amount(amount),
+            currency: Currency::new(currency),
+            rounding: None,
+            clock: None,
+        })
+    }
+
+    #[test]
+    fn posts_balanced_entries() {
+        let chargeback = postings(&AccountEvent::WithdrawalDisputeChargedback(
+            WithdrawalDisputeChargedbackPayload {
+                client_id: ClientId("1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(2.5)),
+                currency: Currency::default(),
+                rounding: None,
+                payment_id: None,
+            },
+        ));
+
+        assert_eq!(chargeback.len(), 4);
+        assert_eq!(
+            chargeback.iter().map(|p| p.debit).sum::<Decimal>(),
+            chargeback.iter().map(|p| p.credit).sum::<Decimal>()
+        );
+        assert_eq!(chargeback[2].account, CHARGEBACK_LOSS);
+        assert_eq!(chargeback[3].account, DISPUTE_SUSPENSE);
+        assert!(
+            postings(&AccountEvent::DebtRepaid(DebtRepaidPayload {
+                client_id: ClientId("1".to_owned()),
+                transaction_id: TransactionId("tx-1".to_owned()),
+                amount: Amount(dec!(1.0)),
+                currency: Currency::default(),
+            }))
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn clears_collected_fees() {
+        let mut all_postings = postings(&AccountEvent::FeeCharged(FeeChargedPayload {
+            client_id: ClientId("1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            kind: FeeKind::Deposit,
+            amount: Amount(dec!(0.5)),
+            currency: Currency::default(),
+            rounding: None,
+        }));
+        all_postings.extend(postings(&AccountEvent::FeeCollected(FeeCollectedPayload {
+            client_id: ClientId(HOUSE_CLIENT_ID.to_owned()),
+            from_client_id: ClientId("1".to_owned()),
+            transaction_id: TransactionId("tx-1".to_owned()),
+            kind: FeeKind::Deposit,
+            amount: Amount(dec!(0.5)),
+            currency: Currency::default(),
+            payment_id: "tx-1".to_owned(),
+        })));
+
+        let (rows, _) = trial_balance(&all_postings);
+
+        let balances: Vec<_> = rows
+            .iter()
+            .map(|r| (r.account.as_str(), r.balance))
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                ("client:1:available", dec!(0.5)),
+                ("client:house:available", dec!(-0.5)),
+                (FEE_CLEARING, dec!(0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sums_trial_balance() {
+        let mut all_postings = postings(&deposited("1", dec!(1.5), "USD"));
+        all_postings.extend(postings(&deposited("2", dec!(2.0), "USD")));
+
+        let (rows, totals) = trial_balance(&all_postings);
+
+        let accounts: Vec<_> = rows.iter().map(|r| r.account.as_str()).collect();
+        assert_eq!(
+            accounts,
+            vec![CASH_IN, "client:1:available", "client:2:available"]
+        );
+        assert_eq!(rows[0].balance, dec!(3.5));
+        assert_eq!(rows[2].balance, dec!(-2.0));
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].debit, dec!(3.5));
+        assert_eq!(totals[0].credit, dec!(3.5));
+        assert!(totals[0].balance.is_zero());
+    }
+
+    #[test]
+    fn sums_trial_balance_per_currency() {
+        let mut all_postings = postings(&deposited("1", dec!(1.5), "USD"));
+        all_postings.extend(postings(&deposited("1", dec!(2.0), "EUR")));
+
+        let (rows, totals) = trial_balance(&all_postings);
+
+        assert_eq!(rows.len(), 4);
+        let currencies: Vec<_> = totals.iter().map(|t| t.currency.as_str()).collect();
+        assert_eq!(currencies, vec!["EUR", "USD"]);
+        assert_eq!(totals[0].debit, dec!(2.0));
+        assert!(totals.iter().all(|t| t.balance.is_zero()));
+    }
+}
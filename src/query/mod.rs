@@ -1 +1,5 @@
 pub mod account;
+pub mod authorization;
+pub mod history;
+pub mod journal;
+pub mod output;
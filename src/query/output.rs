@@ -0,0 +1,215 @@
+use std::io;
+
+use color_eyre::eyre::Result;
+use csv::WriterBuilder;
+
+use crate::{cli::OutputFormat, query::account::AccountRow};
+
+const CSV_HEADER: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// Columns added to the csv output by `--all-columns`.
+const EXTRA_CSV_HEADER: [&str; 4] = ["debt", "frozen", "currency", "authorized"];
+
+/// Writes account rows in one of the output formats.
+/// `begin` and `end` are called once around all the accounts, so the writer owns its header/preamble.
+pub(crate) trait AccountsWriter {
+    fn begin(&mut self) -> Result<()>;
+    fn write(&mut self, account: &AccountRow) -> Result<()>;
+    fn end(&mut self) -> Result<()>;
+}
+
+/// Csv has only the baseline columns unless all are asked for, the JSON formats always have all of them.
+pub(crate) fn accounts_writer<'a, W: io::Write + 'a>(
+    format: OutputFormat,
+    all_columns: bool,
+    writer: W,
+) -> Box<dyn AccountsWriter + 'a> {
+    match format {
+        OutputFormat::Csv => Box::new(CsvAccountsWriter {
+            csv_writer: WriterBuilder::new().has_headers(false).from_writer(writer),
+            all_columns,
+        }),
+        OutputFormat::Json => Box::new(JsonAccountsWriter { writer, written: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonAccountsWriter { writer }),
+    }
+}
+
+struct CsvAccountsWriter<W: io::Write> {
+    csv_writer: csv::Writer<W>,
+    all_columns: bool,
+}
+
+impl<W: io::Write> AccountsWriter for CsvAccountsWriter<W> {
+    fn begin(&mut self) -> Result<()> {
+        // Written explicitly, as serialize only writes headers together with the first account
+        if self.all_columns {
+            self.csv_writer
+                .write_record(CSV_HEADER.iter().chain(&EXTRA_CSV_HEADER))?;
+        } else {
+            self.csv_writer.write_record(CSV_HEADER)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, account: &AccountRow) -> Result<()> {
+        if self.all_columns {
+            self.csv_writer.serialize(account)?;
+        } else {
+            self.csv_writer.serialize((
+                &account.client_id,
+                account.available_funds,
+                account.held_funds,
+                account.total_funds,
+                account.is_locked,
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A single JSON array, with an account per line.
+struct JsonAccountsWriter<W: io::Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: io::Write> AccountsWriter for JsonAccountsWriter<W> {
+    fn begin(&mut self) -> Result<()> {
+        self.writer.write_all(b"[")?;
+        Ok(())
+    }
+
+    fn write(&mut self, account: &AccountRow) -> Result<()> {
+        let separator: &[u8] = if self.written == 0 { b"\n" } else { b",\n" };
+        self.writer.write_all(separator)?;
+        serde_json::to_writer(&mut self.writer, account)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        let closing: &[u8] = if self.written == 0 { b"]\n" } else { b"\n]\n" };
+        self.writer.write_all(closing)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// An account JSON object per line.
+struct NdjsonAccountsWriter<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> AccountsWriter for NdjsonAccountsWriter<W> {
+    fn begin(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write(&mut self, account: &AccountRow) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, account)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use crate::{
+        cli::OutputFormat,
+        domain::props::Currency,
+        query::{account::AccountRow, output::accounts_writer},
+    };
+
+    fn write_accounts(format: OutputFormat, all_columns: bool, accounts: &[AccountRow]) -> String {
+        let mut out = vec![];
+        {
+            let mut writer = accounts_writer(format, all_columns, &mut out);
+            writer.begin().unwrap();
+            for account in accounts {
+                writer.write(account).unwrap();
+            }
+            writer.end().unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn accounts() -> Vec<AccountRow> {
+        vec![
+            AccountRow {
+                client_id: "1".to_owned(),
+                available_funds: dec!(1.5),
+                held_funds: dec!(0.0),
+                total_funds: dec!(1.5),
+                is_locked: false,
+                debt: dec!(0.0),
+                is_frozen: false,
+                currency: Currency::default(),
+                authorized: dec!(0.0),
+            },
+            AccountRow {
+                client_id: "2".to_owned(),
+                available_funds: dec!(0.0),
+                held_funds: dec!(2.0),
+                total_funds: dec!(3.25),
+                is_locked: true,
+                debt: dec!(0.5),
+                is_frozen: true,
+                currency: Currency::new("eur"),
+                authorized: dec!(1.25),
+            },
+        ]
+    }
+
+    #[test]
+    fn writes_csv() {
+        assert_eq!(
+            write_accounts(OutputFormat::Csv, false, &accounts()),
+            "client,available,held,total,locked\n1,1.5,0.0,1.5,false\n2,0.0,2.0,3.25,true\n"
+        );
+        assert_eq!(
+            write_accounts(OutputFormat::Csv, false, &[]),
+            "client,available,held,total,locked\n"
+        );
+    }
+
+    #[test]
+    fn writes_csv_with_all_columns() {
+        assert_eq!(
+            write_accounts(OutputFormat::Csv, true, &accounts()),
+            "client,available,held,total,locked,debt,frozen,currency,authorized\n1,1.5,0.0,1.5,false,0.0,false,USD,0.0\n2,0.0,2.0,3.25,true,0.5,true,EUR,1.25\n"
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let out = write_accounts(OutputFormat::Json, false, &accounts());
+
+        let parsed: Vec<AccountRow> = serde_json::from_str(&out).unwrap();
+        assert_eq!(parsed, accounts());
+        assert_eq!(write_accounts(OutputFormat::Json, false, &[]), "[]\n");
+    }
+
+    #[test]
+    fn writes_ndjson() {
+        let out = write_accounts(OutputFormat::Ndjson, false, &accounts());
+
+        let parsed: Vec<AccountRow> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, accounts());
+        assert_eq!(write_accounts(OutputFormat::Ndjson, false, &[]), "");
+    }
+}
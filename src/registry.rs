@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::{Result, eyre};
+use futures::TryStreamExt;
+use sqlx::{Row, SqlitePool};
+
+use crate::domain::{props::InputClock, transaction::aggregate::TX_AGGREGATE_PREFIX};
+
+/// Keeps track of transaction ids seen across all work partitions.
+/// `Transaction` aggregates live in a per partition event store, so they can only detect duplicates
+/// within their own partition. The registry is owned by the sender thread, which sees every row in
+/// input order, so the first occurrence of a transaction id wins regardless of partitioning.
+#[derive(Default)]
+pub struct TransactionRegistry {
+    tx_ids: HashSet<String>,
+}
+
+impl TransactionRegistry {
+    /// Registers the transaction id, returns `false` if it was already taken.
+    pub fn claim(&mut self, tx_id: &str) -> bool {
+        self.tx_ids.insert(tx_id.to_owned())
+    }
+
+    /// Registers transaction ids already recorded in the partition event store.
+    pub async fn seed(&mut self, sqlite_pool: &SqlitePool) -> Result<()> {
+        let mut query = sqlx::query(
+            "select distinct aggregate_id from events where aggregate_type = 'Transaction'",
+        )
+        .fetch(sqlite_pool);
+        while let Some(row) = query.try_next().await.map_err(|e| eyre!(e))? {
+            let aggregate_id: String = row.get("aggregate_id");
+            if let Some(tx_id) = aggregate_id.strip_prefix(TX_AGGREGATE_PREFIX) {
+                self.claim(tx_id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps track of input rows already processed against the store, by their idempotency key
+/// (see: `PaymentRow::idempotency_key`), so processing the same input again skips them.
+/// Rows are marked after they are processed, so disputes, resolves and chargebacks are identified by the
+/// row key as well, a row which went through just before a crash is not applied twice.
+#[derive(Default)]
+pub struct RowRegistry {
+    row_keys: HashSet<String>,
+}
+
+impl RowRegistry {
+    /// Registers the row key, returns `false` if the row was already processed.
+    pub fn claim(&mut self, row_key: &str) -> bool {
+        self.row_keys.insert(row_key.to_owned())
+    }
+
+    /// Registers row keys processed in the previous runs against the partition.
+    pub async fn seed(&mut self, sqlite_pool: &SqlitePool) -> Result<()> {
+        let mut query = sqlx::query("select row_key from processed_rows").fetch(sqlite_pool);
+        while let Some(row) = query.try_next().await.map_err(|e| eyre!(e))? {
+            self.claim(row.get("row_key"));
+        }
+
+        Ok(())
+    }
+}
+
+#[allow(clippy::expect_used)] // without this working, it's a show over
+pub async fn init_processed_rows_table(sqlite_pool: &SqlitePool) {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS processed_rows
+            (
+                row_key text NOT NULL PRIMARY KEY
+            );",
+    )
+    .execute(sqlite_pool)
+    .await
+    .expect("Failed to initialize processed rows table");
+}
+
+/// Records the row as processed, whether it was applied or rejected.
+pub async fn mark_processed(sqlite_pool: &SqlitePool, row_key: &str) -> Result<()> {
+    sqlx::query("insert or ignore into processed_rows (row_key) values (?)")
+        .bind(row_key)
+        .execute(sqlite_pool)
+        .await
+        .map_err(|e| eyre!(e))?;
+
+    Ok(())
+}
+
+#[allow(clippy::expect_used)] // without this working, it's a show over
+pub async fn init_input_clock_table(sqlite_pool: &SqlitePool) {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS input_clock
+            (
+                id       int    NOT NULL PRIMARY KEY CHECK (id = 0),
+                position bigint NOT NULL,
+                time     bigint
+            );",
+    )
+    .execute(sqlite_pool)
+    .await
+    .expect("Failed to initialize input clock table");
+}
+
+/// Clock where the previous runs against the store stopped, partitions may be behind when a run failed.
+pub async fn load_input_clock(sqlite_pools: &[SqlitePool]) -> Result<InputClock> {
+    let mut clock = InputClock::default();
+    for sqlite_pool in sqlite_pools {
+        let saved = sqlx::query("select position, time from input_clock")
+            .fetch_optional(sqlite_pool)
+            .await
+            .map_err(|e| eyre!(e))?;
+        if let Some(row) = saved {
+            clock.position = clock.position.max(row.get::<i64, _>("position") as u64);
+            clock.time = clock.time.max(row.get("time"));
+        }
+    }
+
+    Ok(clock)
+}
+
+pub async fn save_input_clock(sqlite_pool: &SqlitePool, clock: &InputClock) -> Result<()> {
+    sqlx::query("insert or replace into input_clock (id, position, time) values (0, ?, ?)")
+        .bind(clock.position as i64)
+        .bind(clock.time)
+        .execute(sqlite_pool)
+        .await
+        .map_err(|e| eyre!(e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{RowRegistry, TransactionRegistry};
+
+    #[test]
+    fn claims_tx_id_once() {
+        let mut registry = TransactionRegistry::default();
+
+        assert!(registry.claim("1"));
+        assert!(registry.claim("2"));
+        assert!(!registry.claim("1"));
+    }
+
+    #[test]
+    fn claims_row_key_once() {
+        let mut registry = RowRegistry::default();
+
+        assert!(registry.claim("key:a"));
+        assert!(registry.claim("/input.csv:2"));
+        assert!(!registry.claim("key:a"));
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::mpsc,
+    thread,
+};
+
+use color_eyre::eyre::{Report, Result, eyre};
+use cqrs_es::AggregateError;
+use csv::WriterBuilder;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    cli::RejectsFormat,
+    csv::{InputSource, RowError},
+    domain::{
+        account::error::AccountError, payment::error::PaymentError,
+        transaction::error::TransactionError,
+    },
+};
+
+/// Input row which was ignored, with the reason why.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Rejection {
+    #[serde(skip)]
+    pub source_index: usize,
+    pub source: String,
+    pub line: u64,
+    pub row: String,
+    pub reason: &'static str,
+}
+
+/// Handle for reporting rejected rows, shared by the sender and all the receivers.
+/// Rejections are only logged when no report was requested.
+#[derive(Clone)]
+pub struct Rejects {
+    sender: Option<mpsc::Sender<Rejection>>,
+}
+
+impl Rejects {
+    pub fn reject(&self, source: &InputSource, line: u64, row: &str, error: &Report) {
+        debug!(
+            "Rejected row {} line {} '{}': {}",
+            source.path, line, row, error
+        );
+
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Rejection {
+                source_index: source.index,
+                source: source.path.clone(),
+                line,
+                row: row.to_owned(),
+                reason: reason_code(error),
+            });
+        }
+    }
+}
+
+/// Starts the report writer thread, which finishes once all `Rejects` handles are dropped.
+pub fn start_rejects_writer(
+    file_path: Option<&str>,
+    format: RejectsFormat,
+) -> Result<(Rejects, Option<thread::JoinHandle<Result<()>>>)> {
+    let Some(file_path) = file_path else {
+        return Ok((Rejects { sender: None }, None));
+    };
+
+    let file =
+        File::create(file_path).map_err(|e| eyre!("Could not create rejects file: {}", e))?;
+    let (sender, receiver) = mpsc::channel::<Rejection>();
+
+    let writer_thread = thread::spawn(move || {
+        // Receivers report in parallel, so rows are put back in input order
+        let mut rejections = receiver.iter().collect::<Vec<_>>();
+        rejections.sort_by_key(|r| (r.source_index, r.line));
+
+        write_rejections(file, format, &rejections)
+    });
+
+    Ok((
+        Rejects {
+            sender: Some(sender),
+        },
+        Some(writer_thread),
+    ))
+}
+
+fn write_rejections(file: File, format: RejectsFormat, rejections: &[Rejection]) -> Result<()> {
+    match format {
+        RejectsFormat::Csv => {
+            let mut csv_writer = WriterBuilder::new().from_writer(file);
+            for rejection in rejections {
+                csv_writer.serialize(rejection)?;
+            }
+            csv_writer.flush()?;
+        }
+        RejectsFormat::Ndjson => {
+            let mut writer = BufWriter::new(file);
+            for rejection in rejections {
+                serde_json::to_writer(&mut writer, rejection)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Maps domain and row errors to a machine readable reason.
+pub fn reason_code(error: &Report) -> &'static str {
+    if let Some(AggregateError::UserError(e)) = error.downcast_ref::<AggregateError<AccountError>>()
+    {
+        return e.code();
+    }
+    if let Some(AggregateError::UserError(e)) =
+        error.downcast_ref::<AggregateError<TransactionError>>()
+    {
+        return e.code();
+    }
+    if let Some(AggregateError::UserError(e)) = error.downcast_ref::<AggregateError<PaymentError>>()
+    {
+        return e.code();
+    }
+    if let Some(e) = error.downcast_ref::<AccountError>() {
+        return e.code();
+    }
+    if let Some(e) = error.downcast_ref::<TransactionError>() {
+        return e.code();
+    }
+    if let Some(e) = error.downcast_ref::<RowError>() {
+        return e.code();
+    }
+
+    "unexpected_error"
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::{WrapErr, eyre};
+    use cqrs_es::AggregateError;
+
+    use crate::{
+        csv::RowError,
+        domain::{account::error::AccountError, transaction::error::TransactionError},
+        rejects::reason_code,
+    };
+
+    #[test]
+    fn maps_reason_codes() {
+        assert_eq!(
+            reason_code(&eyre!(AggregateError::UserError(
+                AccountError::InsufficientFunds
+            ))),
+            "insufficient_funds"
+        );
+        assert_eq!(
+            reason_code(&eyre!(AggregateError::UserError(
+                TransactionError::DuplicateTransaction
+            ))),
+            "duplicate_transaction"
+        );
+        assert_eq!(
+            reason_code(&eyre!(TransactionError::ClientMismatch)),
+            "client_mismatch"
+        );
+        assert_eq!(
+            reason_code(
+                &Err::<(), _>(RowError::MissingAmount)
+                    .wrap_err("No amount")
+                    .unwrap_err()
+            ),
+            "missing_amount"
+        );
+        assert_eq!(reason_code(&eyre!("boom")), "unexpected_error");
+    }
+}
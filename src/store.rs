@@ -0,0 +1,148 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{OptionExt, Result, eyre};
+use sqlx::{
+    SqlitePool,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous},
+};
+
+const PARTITIONS_FILE: &str = "partitions";
+
+/// Location of the sqlite dbs, one per work partition.
+/// Each db holds the aggregate events as well as the `accounts` projection.
+pub enum Store {
+    /// Temp dbs like 'XDB-1761491588862857000-0.db' in the working dir, removed after the run.
+    Temporary { suffix: u128 },
+    /// Durable dbs kept in a directory, so subsequent runs continue from the existing state.
+    Persistent { dir: PathBuf },
+}
+
+impl Store {
+    pub fn open(store_dir: Option<&str>) -> Result<Self> {
+        match store_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)
+                    .map_err(|e| eyre!("Could not create store directory: {}", e))?;
+                Ok(Store::Persistent {
+                    dir: PathBuf::from(dir),
+                })
+            }
+            None => Ok(Store::Temporary {
+                suffix: epoch_nanos()?,
+            }),
+        }
+    }
+
+    /// Opens a persistent store created by one of the previous runs.
+    pub fn open_existing(dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        if !dir.join(PARTITIONS_FILE).exists() {
+            return Err(eyre!("Store not found in {}", dir.display()));
+        }
+
+        Ok(Store::Persistent { dir })
+    }
+
+    /// Clients are pinned to partitions by hash, so a persistent store has to keep
+    /// the partition count it was created with, regardless of the current core count.
+    pub fn partition_count(&self, requested: Option<usize>, default: usize) -> Result<usize> {
+        match self {
+            Store::Temporary { .. } => Ok(requested.unwrap_or(default)),
+            Store::Persistent { dir } => {
+                let partitions_file = dir.join(PARTITIONS_FILE);
+                if partitions_file.exists() {
+                    let partitions = fs::read_to_string(&partitions_file)?
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|e| eyre!("Corrupted store partitions file: {}", e))?;
+                    match requested {
+                        Some(requested) if requested != partitions => Err(eyre!(
+                            "Store was created with {} workers, {} requested",
+                            partitions,
+                            requested
+                        )),
+                        _ => Ok(partitions),
+                    }
+                } else {
+                    let partitions = requested.unwrap_or(default);
+                    fs::write(&partitions_file, partitions.to_string())?;
+                    Ok(partitions)
+                }
+            }
+        }
+    }
+
+    pub async fn connect(&self, partitions: usize) -> Result<Vec<SqlitePool>> {
+        let mut pools = Vec::with_capacity(partitions);
+        for partition in 0..partitions {
+            pools.push(sqlite_pool(&self.sqlite_uri(partition), self.synchronous()).await?);
+        }
+        Ok(pools)
+    }
+
+    fn sqlite_uri(&self, partition: usize) -> String {
+        match self {
+            Store::Temporary { suffix } => {
+                format!("sqlite:XDB-{}-{}.db?mode=rwc", suffix, partition)
+            }
+            Store::Persistent { dir } => format!(
+                "sqlite:{}?mode=rwc",
+                dir.join(format!("XDB-{}.db", partition)).display()
+            ),
+        }
+    }
+
+    // Temp dbs are thrown away anyway, so there is no point in paying for durability.
+    fn synchronous(&self) -> SqliteSynchronous {
+        match self {
+            Store::Temporary { .. } => SqliteSynchronous::Off,
+            Store::Persistent { .. } => SqliteSynchronous::Normal,
+        }
+    }
+
+    /// Removes temp dbs, persistent ones are kept.
+    pub async fn close(&self, pools: &[SqlitePool]) -> Result<()> {
+        for pool in pools {
+            pool.close().await;
+        }
+
+        match self {
+            Store::Temporary { .. } => cleanup_temp_dbs(pools),
+            Store::Persistent { .. } => Ok(()),
+        }
+    }
+}
+
+async fn sqlite_pool(sqlite_uri: &str, synchronous: SqliteSynchronous) -> Result<SqlitePool> {
+    let opts = SqliteConnectOptions::from_str(sqlite_uri)?
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(synchronous);
+    SqlitePool::connect_with(opts).await.map_err(|e| eyre!(e))
+}
+
+fn epoch_nanos() -> Result<u128> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|e| eyre!(e))?
+        .as_nanos())
+}
+
+fn cleanup_temp_dbs(pools: &[SqlitePool]) -> Result<()> {
+    for pool in pools {
+        let options = pool.connect_options();
+        let db_path = options
+            .get_filename()
+            .to_str()
+            .ok_or_eyre("no db file name")?;
+        let _ = fs::remove_file(Path::new(db_path));
+        let _ = fs::remove_file(Path::new(&format!("{}-shm", db_path)));
+        let _ = fs::remove_file(Path::new(&format!("{}-wal", db_path)));
+    }
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ const BIN_NAME: &str = "payments-toy-engine";
 fn sample_input_output() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin(BIN_NAME)?;
 
-    cmd.arg("sample/transactions.csv");
+    cmd.args(["--sorted", "sample/transactions.csv"]);
 
     cmd.assert()
         .stdout(fs::read_to_string("sample/accounts.csv").unwrap())
@@ -17,11 +17,33 @@ fn sample_input_output() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn sorted_output_regardless_of_workers() -> Result<(), Box<dyn std::error::Error>> {
+    for workers in ["1", "2", "7"] {
+        let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+        cmd.args([
+            "process",
+            "--workers",
+            workers,
+            "--sorted",
+            "sample/transactions.csv",
+        ]);
+
+        cmd.assert()
+            .success()
+            .stdout(fs::read_to_string("sample/accounts.csv").unwrap())
+            .stderr("");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn duplicate_tx_ids_ignored() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin(BIN_NAME)?;
 
-    cmd.arg("sample/transactions_with_duplicates.csv");
+    cmd.args(["--sorted", "sample/transactions_with_duplicates.csv"]);
 
     cmd.assert()
         .stdout(fs::read_to_string("sample/accounts.csv").unwrap())
@@ -88,3 +110,1400 @@ fn chargeback_reflecting() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn cross_client_dispute_ignored() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["--sorted", "sample/transaction_cross_client_dispute.csv"]);
+
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,1.0,0.0,1.0,false
+2,5.0,0.0,5.0,false
+"#,
+        )
+        .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn duplicate_tx_ids_across_clients_ignored() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.arg("sample/transactions_duplicates_across_clients.csv");
+
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,1.0,0.0,1.0,false
+"#,
+        )
+        .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn persistent_store_continues_from_previous_run() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-store-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/batch_day_1.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,3.0,0.0,3.0,false
+"#,
+        );
+
+    // tx 1 was already recorded by the previous run, so only the withdrawal applies
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/batch_day_2.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,2.5,0.0,2.5,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn reprocessed_input_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-store-{}-rerun", std::process::id()));
+    let rejects_file =
+        std::env::temp_dir().join(format!("payments-rejects-{}-rerun.csv", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+    let expected = r#"client,available,held,total,locked
+1,7.5,0.0,7.5,true
+2,0.5,2.5,3.0,false
+"#;
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "--store"])
+        .arg(&store_dir)
+        .arg("sample/transaction_partial_dispute.csv")
+        .assert()
+        .success()
+        .stdout(expected);
+
+    // Disputes, resolves and chargebacks are not applied again, nor deposits rejected as duplicates
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "--store"])
+        .arg(&store_dir)
+        .arg("--rejects")
+        .arg(&rejects_file)
+        .arg("sample/transaction_partial_dispute.csv")
+        .assert()
+        .success()
+        .stdout(expected);
+
+    // Nothing rejected, so not even the header is written
+    assert_eq!(fs::read_to_string(&rejects_file)?, "");
+    fs::remove_file(&rejects_file)?;
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn reprocessed_duplicates_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-store-{}-duplicates", std::process::id()));
+    let rejects_file = std::env::temp_dir().join(format!(
+        "payments-rejects-{}-duplicates.csv",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&store_dir);
+    let expected = r#"client,available,held,total,locked
+1,1.0,0.0,1.0,false
+"#;
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "--store"])
+        .arg(&store_dir)
+        .arg("sample/transactions_duplicates_across_clients.csv")
+        .assert()
+        .success()
+        .stdout(expected);
+
+    // Rows the sender rejected as duplicates are processed too, so they are not rejected again
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "--store"])
+        .arg(&store_dir)
+        .arg("--rejects")
+        .arg(&rejects_file)
+        .arg("sample/transactions_duplicates_across_clients.csv")
+        .assert()
+        .success()
+        .stdout(expected);
+
+    assert_eq!(fs::read_to_string(&rejects_file)?, "");
+    fs::remove_file(&rejects_file)?;
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn partially_processed_input_with_keys() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-store-{}-keys", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+    let input = fs::read_to_string("sample/transaction_idempotency_keys.csv")?;
+    let processed: String = input
+        .lines()
+        .take(3)
+        .map(|line| format!("{}\n", line))
+        .collect();
+
+    // Keys identify the rows regardless of where they are read from, stdin included
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--sorted", "--store"])
+        .arg(&store_dir)
+        .arg("-")
+        .write_stdin(processed)
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,6.0,4.0,10.0,false
+"#,
+        );
+
+    // The retried dispute with the same key is skipped too
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--sorted", "--store"])
+        .arg(&store_dir)
+        .arg("-")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,7.0,3.0,10.0,false
+2,5.0,0.0,5.0,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn admin_freezes_and_unlocks_account() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-admin-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/batch_day_1.csv")
+        .assert()
+        .success();
+
+    let admin = |action: &str| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin(BIN_NAME)?;
+        cmd.args([
+            "admin",
+            action,
+            "--client",
+            "1",
+            "--reason",
+            "aml",
+            "--operator",
+            "op-1",
+        ])
+        .arg("--store")
+        .arg(&store_dir);
+        Ok(cmd)
+    };
+
+    admin("freeze")?.assert().success().stdout("");
+
+    // The withdrawal is rejected while the account is frozen
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--all-columns", "--store"])
+        .arg(&store_dir)
+        .arg("sample/batch_day_2.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,3.0,0.0,3.0,false,0.0,true,USD,0.0
+"#,
+        );
+
+    admin("unlock")?.assert().success();
+    admin("unlock")?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("AccountNotLocked"));
+
+    Command::cargo_bin(BIN_NAME)?
+        .arg("accounts")
+        .arg("--store")
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,3.0,0.0,3.0,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn transfer_between_partitions() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!("transfer-{}.csv", std::process::id()));
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["--workers", "4", "--sorted", "--rejects"])
+        .arg(&rejects_file)
+        .arg("sample/transaction_transfer.csv");
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,2.5,0.0,2.5,false
+2,3.0,0.0,3.0,false
+4,0.5,0.0,0.5,false
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_transfer.csv,5,"transfer, 2, 4, 10.0, 1",insufficient_funds
+sample/transaction_transfer.csv,7,"dispute, 1, 3,,",not_disputable
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn multi_currency_accounts() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!("currency-{}.csv", std::process::id()));
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["--sorted", "--all-columns", "--rejects"])
+        .arg(&rejects_file)
+        .arg("sample/transaction_multi_currency.csv");
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,3.0,2.0,5.0,false,0.0,false,EUR,0.0
+1,11.0,0.0,11.0,false,0.0,false,USD,0.0
+2,2.0,0.0,2.0,false,0.0,false,USD,0.0
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_multi_currency.csv,4,"withdrawal, 1, 3, 6.0, , , EUR",insufficient_funds
+sample/transaction_multi_currency.csv,5,"dispute, 1, 2, , , , USD",currency_mismatch
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn rounded_amounts() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!("rounding-{}.csv", std::process::id()));
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["--sorted", "--all-columns", "--rounding", "truncate"])
+        .args(["--precision", "EUR=2:half-even", "--precision", "jpy=0"])
+        .arg("--rejects")
+        .arg(&rejects_file)
+        .arg("sample/transaction_rounding.csv");
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,4.98,5.0,9.98,false,0.0,false,EUR,0.0
+1,900.0,0.0,900.0,false,0.0,false,JPY,0.0
+1,2.1234,0.0,2.1234,false,0.0,false,USD,0.0
+2,100.0,0.0,100.0,false,0.0,false,JPY,0.0
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_rounding.csv,8,"withdrawal, 1, 6, 0.4, , , JPY",illegal_amount
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn failed_transfer_credit_is_reverted() -> Result<(), Box<dyn std::error::Error>> {
+    // The chargeback locks client 2 before the transfer, whichever partitions the clients end up in
+    for workers in ["1", "2", "7"] {
+        let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+        cmd.args(["--workers", workers, "--sorted"])
+            .arg("sample/transaction_transfer_locked.csv");
+        cmd.assert()
+            .success()
+            .stdout(
+                r#"client,available,held,total,locked
+1,5.0,0.0,5.0,false
+2,0.0,0.0,0.0,true
+"#,
+            )
+            .stderr("");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn trial_balance_sums_to_zero() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-journal-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+    let trial_balance = r#"account,debit,credit,balance,currency
+cash-in,20.0,0.0,20.0,USD
+cash-out,0.0,11.0,-11.0,USD
+chargeback-loss,5.0,0.0,5.0,USD
+client:1:available,4.0,10.0,-6.0,USD
+client:1:held,0.0,4.0,-4.0,USD
+client:2:available,2.0,5.0,-3.0,USD
+client:2:held,2.0,2.0,0.0,USD
+client:3:available,5.0,10.0,-5.0,USD
+client:3:held,5.0,5.0,0.0,USD
+dispute-suspense,11.0,7.0,4.0,USD
+total,54.0,54.0,0.0,USD
+"#;
+
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/transaction_withdrawal_dispute.csv")
+        .assert()
+        .success();
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["trial-balance", "--store"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(trial_balance);
+
+    // The journal rebuilt from events is the same
+    Command::cargo_bin(BIN_NAME)?
+        .args(["replay", "--store"])
+        .arg(&store_dir)
+        .assert()
+        .success();
+    Command::cargo_bin(BIN_NAME)?
+        .args(["trial-balance", "--store"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(trial_balance);
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn fees_charged_to_house_account() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-fees-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "--all-columns", "--fees", "sample/fees.json"])
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/transaction_fees.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,0.075,0.0,0.075,false,0.0,false,USD,0.0
+2,9.8,0.0,9.8,false,0.0,false,USD,0.0
+3,-1.2,0.0,-1.2,true,1.2,false,USD,0.0
+house,2.825,0.0,2.825,false,0.0,false,USD,0.0
+"#,
+        )
+        .stderr("");
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["trial-balance", "--store"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(
+            r#"account,debit,credit,balance,currency
+cash-in,34.0,0.0,34.0,USD
+cash-out,0.0,19.5,-19.5,USD
+chargeback-loss,0.0,3.0,-3.0,USD
+client:1:available,9.925,10.0,-0.075,USD
+client:2:available,10.2,20.0,-9.8,USD
+client:3:available,5.2,4.0,1.2,USD
+client:3:held,3.0,3.0,0.0,USD
+client:house:available,0.0,2.825,-2.825,USD
+fee-clearing,2.825,2.825,0.0,USD
+total,65.15,65.15,0.0,USD
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn authorizations_captured_voided_and_expired() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-auth-{}", std::process::id()));
+    let rejects_file = std::env::temp_dir().join(format!("auth-{}.csv", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    // tx 4 expires before its capture, tx 7 once the input is processed
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "--sorted",
+            "--workers",
+            "1",
+            "--authorization-expiry-secs",
+            "300",
+            "--store",
+        ])
+        .arg(&store_dir)
+        .arg("--rejects")
+        .arg(&rejects_file)
+        .arg("sample/transaction_authorization.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,2.5,0.0,2.5,false
+2,5.0,0.0,5.0,false
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_authorization.csv,11,"withdrawal, 2, 8, 1.0, , , , 1700000090",insufficient_funds
+sample/transaction_authorization.csv,12,"capture, 1, 4, , , , , 1700000500",authorization_expired
+"#
+    );
+
+    // The transactions follow the authorizations to their end
+    let pool =
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", store_dir.join("XDB-0.db").display()))
+            .await?;
+    let closed: Vec<(String, String)> = sqlx::query_as(
+        "select aggregate_id, event_type from events
+            where aggregate_type = 'Transaction' and event_type like 'Authorization%'
+            order by aggregate_id",
+    )
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+    assert_eq!(
+        closed,
+        [
+            ("Transaction-2", "AuthorizationCaptured"),
+            ("Transaction-3", "AuthorizationVoided"),
+            ("Transaction-4", "AuthorizationExpired"),
+            ("Transaction-7", "AuthorizationExpired"),
+        ]
+        .map(|(id, event)| (id.to_owned(), event.to_owned()))
+    );
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["trial-balance", "--store"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(
+            r#"account,debit,credit,balance,currency
+cash-in,15.0,0.0,15.0,USD
+cash-out,0.0,7.5,-7.5,USD
+client:1:authorized,8.0,8.0,0.0,USD
+client:1:available,13.0,15.5,-2.5,USD
+client:2:authorized,5.0,5.0,0.0,USD
+client:2:available,5.0,10.0,-5.0,USD
+total,46.0,46.0,0.0,USD
+"#,
+        );
+
+    fs::remove_file(&rejects_file)?;
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn authorization_expires_by_rows_across_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-auth-{}-rows", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .args([
+            "--all-columns",
+            "--authorization-expiry-rows",
+            "2",
+            "--store",
+        ])
+        .arg(&store_dir)
+        .arg("-")
+        .write_stdin("type,client,tx,amount\ndeposit,1,1,5.0\nauthorize,1,2,3.0\n")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,2.0,0.0,5.0,false,0.0,false,USD,3.0
+"#,
+        );
+
+    // Rows are counted on from the previous run, the expiry was fixed when authorized
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("-")
+        .write_stdin("type,client,tx,amount\ndeposit,1,3,1.0\ndeposit,1,4,1.0\ndeposit,1,5,1.0\n")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,8.0,0.0,8.0,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn cli_invalid_fee_schedule() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "--fees",
+            "sample/transactions.csv",
+            "sample/transactions.csv",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid fee schedule"));
+
+    Ok(())
+}
+
+#[test]
+fn temp_store_removed_on_failure() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = std::env::temp_dir().join(format!("payments-failed-{}", std::process::id()));
+    std::fs::create_dir_all(&work_dir)?;
+    let sample = |name: &str| format!("{}/sample/{}", env!("CARGO_MANIFEST_DIR"), name);
+
+    Command::cargo_bin(BIN_NAME)?
+        .current_dir(&work_dir)
+        .args([
+            "--fees",
+            &sample("transactions.csv"),
+            &sample("transactions.csv"),
+        ])
+        .assert()
+        .failure();
+
+    let leftovers: Vec<_> = std::fs::read_dir(&work_dir)?.collect::<Result<_, _>>()?;
+    std::fs::remove_dir_all(&work_dir)?;
+    assert!(leftovers.is_empty(), "temp dbs left: {:?}", leftovers);
+
+    Ok(())
+}
+
+#[test]
+fn withdrawal_limits_enforced() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!("limits-{}.csv", std::process::id()));
+
+    // Client 1 has the default limits, client 2 only its own count of withdrawals in 5 rows
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "--limits", "sample/limits.json", "--rejects"])
+        .arg(&rejects_file)
+        .arg("sample/transaction_limits.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,901.0,0.0,901.0,false
+2,7.0,0.0,7.0,false
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_limits.csv,3,"withdrawal, 1, 2, 600.0, , , , 1700000010",limit_exceeded
+sample/transaction_limits.csv,6,"withdrawal, 1, 5, 200.0, , , , 1700000040",limit_exceeded
+sample/transaction_limits.csv,10,"withdrawal, 2, 9, 1.0, , , , 1700000080",limit_exceeded
+"#
+    );
+
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn risk_screening_rejects_and_holds() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!("risk-{}.csv", std::process::id()));
+
+    // Client 1 withdraws most of a deposit within the hour, client 2 deposits above the threshold,
+    // both are frozen for review. Client 3 is blocked, client 4 withdraws only after the hour.
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "--sorted",
+            "--all-columns",
+            "--risk-rules",
+            "sample/risk_rules.json",
+            "--rejects",
+        ])
+        .arg(&rejects_file)
+        .arg("sample/transaction_risk.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,100.0,0.0,100.0,false,0.0,true,USD,0.0
+2,5000.0,0.0,5000.0,false,0.0,true,USD,0.0
+4,5.0,0.0,5.0,false,0.0,false,USD,0.0
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_risk.csv,3,"withdrawal, 1, 2, 95.0, , , , 1700000060",risk_held
+sample/transaction_risk.csv,4,"withdrawal, 1, 3, 10.0, , , , 1700000120",account_frozen
+sample/transaction_risk.csv,6,"withdrawal, 2, 5, 1.0, , , , 1700000240",account_frozen
+sample/transaction_risk.csv,7,"deposit, 3, 6, 1.0, , , , 1700000300",risk_rejected
+"#
+    );
+
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interrupted_payment_resumed() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir = std::env::temp_dir().join(format!("payments-resume-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--workers", "1", "--store"])
+        .arg(&store_dir)
+        .arg("sample/batch_day_1.csv")
+        .assert()
+        .success();
+
+    // As if the run crashed right after the deposit of tx 2 started, while its steps went through
+    let pool =
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", store_dir.join("XDB-0.db").display()))
+            .await?;
+    sqlx::query("delete from events where aggregate_id = 'Payment-2' and sequence > 1")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    // The deposit is resumed without being applied twice
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/batch_day_2.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,2.5,0.0,2.5,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interrupted_dispute_resumed() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-resume-dispute-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--workers", "1", "--store"])
+        .arg(&store_dir)
+        .arg("sample/batch_dispute.csv")
+        .assert()
+        .success();
+
+    // As if the run crashed right after the dispute started, while both of its steps went through
+    let pool =
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", store_dir.join("XDB-0.db").display()))
+            .await?;
+    sqlx::query(
+        "delete from events where aggregate_id like 'Payment-1:Dispute:%' and sequence > 1",
+    )
+    .execute(&pool)
+    .await?;
+    pool.close().await;
+
+    // The dispute is resumed without holding its part twice
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/batch_day_2.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,3.5,1.0,4.5,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unmarked_dispute_row_applied_once() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-unmarked-dispute-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--workers", "1", "--store"])
+        .arg(&store_dir)
+        .arg("sample/batch_dispute.csv")
+        .assert()
+        .success();
+
+    // As if the run crashed after the dispute went through, before its row was marked processed
+    let pool =
+        sqlx::SqlitePool::connect(&format!("sqlite:{}", store_dir.join("XDB-0.db").display()))
+            .await?;
+    sqlx::query("delete from processed_rows where row_key like '%batch_dispute.csv:3:%'")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    // The same input again only marks the dispute row, its part is not held twice
+    Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("sample/batch_dispute.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,4.0,1.0,5.0,false
+"#,
+        );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interrupted_capture_resumed() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-resume-capture-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--workers", "1", "--store"])
+        .arg(&store_dir)
+        .arg("sample/batch_capture.csv")
+        .assert()
+        .success();
+
+    // As if the run stopped once the account captured, before the payment and the transaction followed
+    let db = format!("sqlite:{}", store_dir.join("XDB-0.db").display());
+    let pool = sqlx::SqlitePool::connect(&db).await?;
+    sqlx::query(
+        "delete from events where aggregate_id like 'Payment-2:Capture:%' and sequence > 1",
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        "delete from events where aggregate_id = 'Transaction-2' and event_type = 'AuthorizationCaptured'",
+    )
+    .execute(&pool)
+    .await?;
+    pool.close().await;
+
+    // The capture is resumed, the account does not capture again
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("-")
+        .write_stdin("type,client,tx,amount\n")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,7.5,0.0,7.5,false
+"#,
+        );
+
+    let pool = sqlx::SqlitePool::connect(&db).await?;
+    let captured: Vec<(String, String)> = sqlx::query_as(
+        "select aggregate_type, event_type from events where event_type = 'AuthorizationCaptured'
+            order by aggregate_type",
+    )
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+    assert_eq!(
+        captured,
+        [
+            ("Account", "AuthorizationCaptured"),
+            ("Transaction", "AuthorizationCaptured"),
+        ]
+        .map(|(aggregate, event)| (aggregate.to_owned(), event.to_owned()))
+    );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn capture_rejected_after_account_step_left_open() -> Result<(), Box<dyn std::error::Error>> {
+    let store_dir =
+        std::env::temp_dir().join(format!("payments-open-capture-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&store_dir);
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--workers", "1", "--store"])
+        .arg(&store_dir)
+        .arg("sample/batch_capture.csv")
+        .assert()
+        .success();
+
+    // As if the run stopped once the account captured, with the transaction then gone
+    let db = format!("sqlite:{}", store_dir.join("XDB-0.db").display());
+    let pool = sqlx::SqlitePool::connect(&db).await?;
+    sqlx::query(
+        "delete from events where aggregate_id like 'Payment-2:Capture:%' and sequence > 2",
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("delete from events where aggregate_id = 'Transaction-2'")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    // The transaction rejects the resumed capture, the captured account can not be compensated
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .arg("--store")
+        .arg(&store_dir)
+        .arg("-")
+        .write_stdin("type,client,tx,amount\n")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,7.5,0.0,7.5,false
+"#,
+        );
+
+    // The payment is left open to be resumed by the next run
+    let pool = sqlx::SqlitePool::connect(&db).await?;
+    let events: Vec<(String,)> = sqlx::query_as(
+        "select event_type from events where aggregate_id like 'Payment-2:Capture:%'
+            order by sequence",
+    )
+    .fetch_all(&pool)
+    .await?;
+    pool.close().await;
+    assert_eq!(
+        events,
+        [("PaymentStarted",), ("StepCompleted",)].map(|(event,)| (event.to_owned(),))
+    );
+
+    fs::remove_dir_all(&store_dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn rejected_withdrawal_not_disputable() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!("voided-{}.csv", std::process::id()));
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.arg("--rejects")
+        .arg(&rejects_file)
+        .arg("sample/transaction_voided_dispute.csv");
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,1.0,0.0,1.0,false
+"#,
+        )
+        .stderr("");
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_voided_dispute.csv,3,"withdrawal, 1, 2, 5.0",insufficient_funds
+sample/transaction_voided_dispute.csv,4,"dispute, 1, 2,",transaction_voided
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn cli_process_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args([
+        "process",
+        "--workers",
+        "2",
+        "--channel-capacity",
+        "1",
+        "--format",
+        "csv",
+        "sample/transaction_dispute.csv",
+    ]);
+
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,0.0,1.0,1.0,false
+"#,
+        )
+        .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn cli_process_no_input_file_passed() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.arg("process");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Input file not passed"))
+        .stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn cli_accounts_store_not_found() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["accounts", "--store", "sample/non_existing_store"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Store not found"))
+        .stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn cli_validate_invalid_rows() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["validate", "sample/transactions_invalid.csv"]);
+    cmd.assert()
+        .failure()
+        .stdout("rows: 3, invalid: 2\n")
+        .stderr(predicate::str::contains("No amount found in row for tx 2"));
+
+    Ok(())
+}
+
+#[test]
+fn cli_generate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(BIN_NAME)?;
+
+    cmd.args(["generate", "--clients", "2", "--rows", "1"]);
+    cmd.assert()
+        .success()
+        .stdout(
+            r#"type,client,tx,amount
+deposit,2,c2-1-dps,1.2345
+withdrawal,2,c2-1-wthr,0.2345
+"#,
+        )
+        .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn rejects_report() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file =
+        std::env::temp_dir().join(format!("payments-rejects-{}.csv", std::process::id()));
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--rejects"])
+        .arg(&rejects_file)
+        .arg("sample/transactions_with_rejects.csv")
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,1.0,0.0,1.0,false
+"#,
+        );
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transactions_with_rejects.csv,3,"deposit, 1, 1, 2.0",duplicate_transaction
+sample/transactions_with_rejects.csv,4,"withdrawal, 1, 2, 5.0",insufficient_funds
+sample/transactions_with_rejects.csv,5,"dispute, 1, 9,",transaction_not_found
+sample/transactions_with_rejects.csv,6,"resolve, 1, 1,",dispute_not_found
+sample/transactions_with_rejects.csv,7,"deposit, , 3, 1.0",missing_client_id
+sample/transactions_with_rejects.csv,8,"deposit, 1, 4,",missing_amount
+sample/transactions_with_rejects.csv,9,"refund, 1, 5, 1.0",unparsable_row
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn rejects_report_ndjson() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file =
+        std::env::temp_dir().join(format!("payments-rejects-{}.ndjson", std::process::id()));
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--rejects-format", "ndjson", "--rejects"])
+        .arg(&rejects_file)
+        .arg("sample/transaction_cross_client_dispute.csv")
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"{"source":"sample/transaction_cross_client_dispute.csv","line":4,"row":"dispute, 2, 1,","reason":"client_mismatch"}
+{"source":"sample/transaction_cross_client_dispute.csv","line":5,"row":"chargeback, 2, 1,","reason":"client_mismatch"}
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn json_output() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--format", "json", "--sorted", "sample/transactions.csv"])
+        .assert()
+        .success()
+        .stdout(
+            r#"[
+{"client":"1","available":1.5,"held":0.0,"total":1.5,"locked":false,"debt":0.0,"frozen":false,"currency":"USD","authorized":0.0},
+{"client":"2","available":2.0,"held":0.0,"total":2.0,"locked":false,"debt":0.0,"frozen":false,"currency":"USD","authorized":0.0}
+]
+"#,
+        );
+
+    Ok(())
+}
+
+#[test]
+fn ndjson_output() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--format", "ndjson", "--sorted", "sample/transactions.csv"])
+        .assert()
+        .success()
+        .stdout(
+            r#"{"client":"1","available":1.5,"held":0.0,"total":1.5,"locked":false,"debt":0.0,"frozen":false,"currency":"USD","authorized":0.0}
+{"client":"2","available":2.0,"held":0.0,"total":2.0,"locked":false,"debt":0.0,"frozen":false,"currency":"USD","authorized":0.0}
+"#,
+        );
+
+    Ok(())
+}
+
+#[test]
+fn stdin_input() -> Result<(), Box<dyn std::error::Error>> {
+    // std Command has no stdin helpers
+    assert_cmd::Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--sorted", "-"])
+        .write_stdin(fs::read_to_string("sample/transactions.csv")?)
+        .assert()
+        .success()
+        .stdout(fs::read_to_string("sample/accounts.csv")?);
+
+    Ok(())
+}
+
+#[test]
+fn multiple_compressed_inputs() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file =
+        std::env::temp_dir().join(format!("payments-rejects-{}-multi.csv", std::process::id()));
+
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "process",
+            "--sorted",
+            "sample/shard_1.csv.gz",
+            "sample/shard_2.csv.zst",
+            "sample/transactions.csv",
+            "--rejects",
+        ])
+        .arg(&rejects_file)
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,3.5,0.0,3.5,false
+2,2.0,0.0,2.0,false
+3,4.5,0.0,4.5,false
+"#,
+        );
+
+    // Line numbers are per input, rejects keep the input order
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/shard_1.csv.gz,5,"deposit, 1, 101, 1.0",duplicate_transaction
+sample/shard_2.csv.zst,5,"withdrawal, 3, 105, 100.0",insufficient_funds
+sample/transactions.csv,6,"withdrawal, 2, 5, 3.0",insufficient_funds
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn strict_mode_stops_at_malformed_row() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "process",
+            "--strict",
+            "sample/transactions_strict_invalid.csv",
+        ])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Invalid row in sample/transactions_strict_invalid.csv line 3",
+        ))
+        .stderr(predicate::str::contains("No amount found in row for tx 2"));
+
+    // Without strict mode the row is just rejected
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "sample/transactions_strict_invalid.csv"])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn strict_mode_rejects_non_positive_partial_amount() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "process",
+            "--strict",
+            "sample/transactions_strict_partial_amount.csv",
+        ])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Invalid row in sample/transactions_strict_partial_amount.csv line 3",
+        ))
+        .stderr(predicate::str::contains(
+            "Amount not positive in row for tx 1",
+        ));
+
+    // Without strict mode the account rejects the dispute
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "sample/transactions_strict_partial_amount.csv"])
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,1.0,0.0,1.0,false
+"#,
+        );
+
+    Ok(())
+}
+
+#[test]
+fn strict_mode_checks_header_and_field_counts() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--strict", "sample/transactions_bad_header.csv"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("Unexpected header"));
+
+    // Optional columns may be left out
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "process",
+            "--strict",
+            "--sorted",
+            "--all-columns",
+            "sample/transactions_currency_only.csv",
+        ])
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,3.0,0.0,3.0,false,0.0,false,EUR,0.0
+1,1.0,0.0,1.0,false,0.0,false,USD,0.0
+"#,
+        );
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--strict", "sample/transaction_chargeback.csv"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("line 5"))
+        .stderr(predicate::str::contains("Expected 4 fields, found 3"));
+
+    Command::cargo_bin(BIN_NAME)?
+        .args(["process", "--strict", "--sorted", "sample/transactions.csv"])
+        .assert()
+        .success()
+        .stdout(fs::read_to_string("sample/accounts.csv")?);
+
+    Ok(())
+}
+
+#[test]
+fn withdrawal_dispute_reflecting() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args(["--sorted", "sample/transaction_withdrawal_dispute.csv"])
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,6.0,4.0,10.0,false
+2,3.0,0.0,3.0,false
+3,5.0,0.0,5.0,false
+"#,
+        );
+
+    Ok(())
+}
+
+#[test]
+fn negative_dispute_policy() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(BIN_NAME)?
+        .args(["sample/transaction_negative_dispute.csv"])
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,7.0,0.0,7.0,false
+"#,
+        );
+
+    // The dispute leaves a debt of 8.0, the next deposit pays 5.0 of it
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "--all-columns",
+            "--dispute-policy",
+            "allow-negative",
+            "sample/transaction_negative_dispute.csv",
+        ])
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked,debt,frozen,currency,authorized
+1,-3.0,10.0,7.0,false,3.0,false,USD,0.0
+"#,
+        );
+
+    Ok(())
+}
+
+#[test]
+fn partial_dispute_reflecting() -> Result<(), Box<dyn std::error::Error>> {
+    let rejects_file = std::env::temp_dir().join(format!(
+        "payments-rejects-{}-partial.csv",
+        std::process::id()
+    ));
+
+    Command::cargo_bin(BIN_NAME)?
+        .args([
+            "--sorted",
+            "sample/transaction_partial_dispute.csv",
+            "--rejects",
+        ])
+        .arg(&rejects_file)
+        .assert()
+        .success()
+        .stdout(
+            r#"client,available,held,total,locked
+1,7.5,0.0,7.5,true
+2,0.5,2.5,3.0,false
+"#,
+        );
+
+    assert_eq!(
+        fs::read_to_string(&rejects_file)?,
+        r#"source,line,row,reason
+sample/transaction_partial_dispute.csv,4,"dispute, 1, 1, 7.0",dispute_amount_exceeded
+"#
+    );
+    fs::remove_file(&rejects_file)?;
+
+    Ok(())
+}